@@ -0,0 +1,99 @@
+#![no_main]
+
+//! Feed adversarial hints to the leading-zero gadget and check that it never reports more
+//! leading zeros than the hash has.
+//!
+//! The input is laid out as:
+//!   byte order (1 byte), hash (32 bytes), number of forced leading zeros (1 byte),
+//!   claimed leading zeros / 8 (1 byte), claimed leading zeros % 8 (1 byte),
+//!   followed by up to four length-prefixed elements that replace the hint elements in turn,
+//!   where a length byte of 0xff keeps the element that the claim implies.
+
+use bitcoin::opcodes::all::OP_GREATERTHAN;
+use bitcoin::script::Builder;
+use bitcoin::ScriptBuf;
+use libfuzzer_sys::fuzz_target;
+use reuse_bitcoin_pow_gadgets::hint::HintSource;
+use reuse_bitcoin_pow_gadgets::pow::{count_leading_zeros, HashByteOrder, HashPowGadget};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 36 {
+        return;
+    }
+
+    let order = if data[0] & 1 == 0 {
+        HashByteOrder::LittleEndian
+    } else {
+        HashByteOrder::BigEndian
+    };
+
+    let mut be_bytes: [u8; 32] = data[1..33].try_into().unwrap();
+    for i in 0..(data[33] as usize) {
+        be_bytes[i / 8] &= !(0x80 >> (i % 8));
+    }
+    let mut hash = be_bytes;
+    if order == HashByteOrder::LittleEndian {
+        hash.reverse();
+    }
+
+    let q = (data[34] % 34) as usize;
+    let r = (data[35] % 9) as usize;
+
+    // split the hash as if it had 8 * q + r leading zeros
+    let start = (q + (r != 0) as usize).min(32);
+    let mut remainder = be_bytes[start..].to_vec();
+    if order == HashByteOrder::LittleEndian {
+        remainder.reverse();
+    }
+    let mut hint = vec![
+        if q == 0 { vec![] } else { vec![q as u8] },
+        if r == 0 { vec![] } else { vec![r as u8] },
+        remainder,
+        vec![be_bytes[q.min(31)]],
+    ];
+
+    let mut rest = &data[36..];
+    for element in hint.iter_mut() {
+        let Some((&len, tail)) = rest.split_first() else {
+            break;
+        };
+        if len == 0xff {
+            rest = tail;
+            continue;
+        }
+        let len = (len as usize).min(tail.len());
+        *element = tail[..len].to_vec();
+        rest = &tail[len..];
+    }
+
+    // the gadget pulls the msb only if leading_zeros % 8 is nonzero
+    let r_is_zero = hint[1]
+        .iter()
+        .enumerate()
+        .all(|(i, &b)| b == 0 || (i == hint[1].len() - 1 && b == 0x80));
+    if r_is_zero {
+        hint.pop();
+    }
+
+    let actual = count_leading_zeros(&hash, order);
+
+    let mut script = Builder::new().push_slice(hash).into_script().into_bytes();
+    script.extend_from_slice(
+        HashPowGadget::get_leading_zeros(order, &HintSource::default()).as_bytes(),
+    );
+    script.extend_from_slice(
+        Builder::new()
+            .push_int(actual as i64)
+            .push_opcode(OP_GREATERTHAN)
+            .into_script()
+            .as_bytes(),
+    );
+
+    let exec_result =
+        bitcoin_scriptexec::execute_script_with_witness(ScriptBuf::from_bytes(script), hint);
+    assert!(
+        !exec_result.success,
+        "the gadget reports more than {} leading zeros",
+        actual
+    );
+});
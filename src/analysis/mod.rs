@@ -0,0 +1,393 @@
+use crate::treepp::*;
+use anyhow::{Error, Result};
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::Instruction;
+
+pub mod policy;
+
+#[cfg(any(test, feature = "trace"))]
+pub mod trace;
+
+/// The size and the worst-case stack usage of a script, found without executing it.
+///
+/// When the two branches of an `OP_IF` leave different numbers of elements, the larger one is
+/// used afterwards. `OP_PICK`, `OP_ROLL`, and `OP_CHECKMULTISIG` are counted with the fewest
+/// elements that they can consume.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScriptStats {
+    /// The length of the script in bytes.
+    pub script_len: usize,
+    /// The number of opcodes other than pushes, which is what the 201-opcode limit counts.
+    pub opcode_count: usize,
+    /// The largest number of elements on the main stack.
+    pub max_main_depth: usize,
+    /// The largest number of elements on the altstack.
+    pub max_alt_depth: usize,
+    /// The largest number of elements on both stacks together, which is what the 1000-element
+    /// limit counts.
+    pub max_total_depth: usize,
+    /// The number of elements on the main stack at the end.
+    pub final_main_depth: usize,
+    /// The number of elements on the altstack at the end.
+    pub final_alt_depth: usize,
+}
+
+impl ScriptStats {
+    /// Analyze a script that starts with the given numbers of elements on the two stacks.
+    ///
+    /// The initial main stack should include the inputs of the script as well as the hints
+    /// that it pulls from the witness.
+    pub fn analyze(
+        script: &Script,
+        initial_main_depth: usize,
+        initial_alt_depth: usize,
+    ) -> Result<Self> {
+        let instructions = script
+            .instructions()
+            .collect::<Result<Vec<Instruction>, _>>()
+            .map_err(|e| Error::msg(format!("The script cannot be parsed: {}", e)))?;
+
+        let initial = StackDepth {
+            main: initial_main_depth,
+            alt: initial_alt_depth,
+        };
+
+        let mut analyzer = Analyzer {
+            opcode_count: 0,
+            max: initial,
+            max_total: initial.main + initial.alt,
+        };
+
+        let mut pos = 0;
+        let (end, terminator) = analyzer.walk(&instructions, &mut pos, initial)?;
+        if terminator.is_some() {
+            return Err(Error::msg(
+                "The script has an unbalanced OP_ELSE or OP_ENDIF.",
+            ));
+        }
+
+        Ok(Self {
+            script_len: script.len(),
+            opcode_count: analyzer.opcode_count,
+            max_main_depth: analyzer.max.main,
+            max_alt_depth: analyzer.max.alt,
+            max_total_depth: analyzer.max_total,
+            final_main_depth: end.main,
+            final_alt_depth: end.alt,
+        })
+    }
+}
+
+/// A script composed of labeled segments, such as the gadgets of a covenant.
+#[derive(Clone, Debug, Default)]
+pub struct LabeledScript {
+    pub segments: Vec<(String, Script)>,
+}
+
+impl LabeledScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a segment.
+    pub fn push(&mut self, label: impl Into<String>, script: Script) {
+        self.segments.push((label.into(), script));
+    }
+
+    /// Append all the segments of another labeled script, with their labels prefixed.
+    pub fn extend(&mut self, prefix: &str, other: LabeledScript) {
+        for (label, script) in other.segments {
+            self.segments
+                .push((format!("{} / {}", prefix, label), script));
+        }
+    }
+
+    /// Concatenate the segments into one script.
+    pub fn compile(&self) -> Script {
+        let mut bytes = vec![];
+        for (_, script) in self.segments.iter() {
+            bytes.extend_from_slice(script.as_bytes());
+        }
+        Script::from_bytes(bytes)
+    }
+
+    /// Analyze each segment in turn, with the stack depths carried over from one to the next.
+    pub fn analyze(
+        &self,
+        initial_main_depth: usize,
+        initial_alt_depth: usize,
+    ) -> Result<Vec<(String, ScriptStats)>> {
+        let mut main_depth = initial_main_depth;
+        let mut alt_depth = initial_alt_depth;
+
+        let mut res = vec![];
+        for (label, script) in self.segments.iter() {
+            let stats = ScriptStats::analyze(script, main_depth, alt_depth)
+                .map_err(|e| Error::msg(format!("{}: {}", label, e)))?;
+            main_depth = stats.final_main_depth;
+            alt_depth = stats.final_alt_depth;
+            res.push((label.clone(), stats));
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct StackDepth {
+    main: usize,
+    alt: usize,
+}
+
+struct Analyzer {
+    opcode_count: usize,
+    max: StackDepth,
+    max_total: usize,
+}
+
+impl Analyzer {
+    /// Walk through the instructions until the end of the script or an `OP_ELSE` or `OP_ENDIF`
+    /// at this level, which is returned.
+    fn walk(
+        &mut self,
+        instructions: &[Instruction],
+        pos: &mut usize,
+        mut depth: StackDepth,
+    ) -> Result<(StackDepth, Option<Opcode>)> {
+        while *pos < instructions.len() {
+            let idx = *pos;
+            *pos += 1;
+
+            let opcode = match instructions[idx] {
+                Instruction::PushBytes(_) => {
+                    depth = self.apply(depth, idx, (0, 1, 0, 0))?;
+                    continue;
+                }
+                Instruction::Op(opcode) => opcode,
+            };
+
+            if opcode.to_u8() > OP_PUSHNUM_16.to_u8() {
+                self.opcode_count += 1;
+            }
+
+            match opcode {
+                OP_IF | OP_NOTIF => {
+                    depth = self.apply(depth, idx, (1, 0, 0, 0))?;
+
+                    // the two branches, where more than one OP_ELSE switches back and forth
+                    let mut branches = [depth, depth];
+                    let mut cur = 0;
+                    loop {
+                        let (end, terminator) = self.walk(instructions, pos, branches[cur])?;
+                        branches[cur] = end;
+                        match terminator {
+                            Some(OP_ELSE) => cur ^= 1,
+                            Some(_) => break,
+                            None => {
+                                return Err(Error::msg(format!(
+                                    "The OP_IF at instruction {} is not closed.",
+                                    idx
+                                )))
+                            }
+                        }
+                    }
+
+                    depth = StackDepth {
+                        main: branches[0].main.max(branches[1].main),
+                        alt: branches[0].alt.max(branches[1].alt),
+                    };
+                }
+                OP_ELSE | OP_ENDIF => return Ok((depth, Some(opcode))),
+                _ => {
+                    let effect = stack_effect(opcode).ok_or_else(|| {
+                        Error::msg(format!(
+                            "The opcode {} at instruction {} is not supported.",
+                            opcode, idx
+                        ))
+                    })?;
+                    depth = self.apply(depth, idx, effect)?;
+                }
+            }
+        }
+        Ok((depth, None))
+    }
+
+    fn apply(
+        &mut self,
+        depth: StackDepth,
+        idx: usize,
+        (main_pops, main_pushes, alt_pops, alt_pushes): (usize, usize, usize, usize),
+    ) -> Result<StackDepth> {
+        if depth.main < main_pops {
+            return Err(Error::msg(format!(
+                "The main stack underflows at instruction {}.",
+                idx
+            )));
+        }
+        if depth.alt < alt_pops {
+            return Err(Error::msg(format!(
+                "The altstack underflows at instruction {}.",
+                idx
+            )));
+        }
+
+        let depth = StackDepth {
+            main: depth.main - main_pops + main_pushes,
+            alt: depth.alt - alt_pops + alt_pushes,
+        };
+        self.max.main = self.max.main.max(depth.main);
+        self.max.alt = self.max.alt.max(depth.alt);
+        self.max_total = self.max_total.max(depth.main + depth.alt);
+        Ok(depth)
+    }
+}
+
+/// The numbers of elements that an opcode pops from and pushes to the main stack, and then the
+/// altstack.
+fn stack_effect(opcode: Opcode) -> Option<(usize, usize, usize, usize)> {
+    let effect = match opcode {
+        OP_PUSHNUM_NEG1 | OP_PUSHNUM_1 | OP_PUSHNUM_2 | OP_PUSHNUM_3 | OP_PUSHNUM_4
+        | OP_PUSHNUM_5 | OP_PUSHNUM_6 | OP_PUSHNUM_7 | OP_PUSHNUM_8 | OP_PUSHNUM_9
+        | OP_PUSHNUM_10 | OP_PUSHNUM_11 | OP_PUSHNUM_12 | OP_PUSHNUM_13 | OP_PUSHNUM_14
+        | OP_PUSHNUM_15 | OP_PUSHNUM_16 | OP_DEPTH => (0, 1, 0, 0),
+
+        OP_NOP | OP_NOP1 | OP_CLTV | OP_CSV | OP_NOP4 | OP_NOP5 | OP_NOP6 | OP_NOP7 | OP_NOP8
+        | OP_NOP9 | OP_NOP10 | OP_CODESEPARATOR | OP_RETURN => (0, 0, 0, 0),
+
+        OP_VERIFY | OP_DROP => (1, 0, 0, 0),
+        OP_TOALTSTACK => (1, 0, 0, 1),
+        OP_FROMALTSTACK => (0, 1, 1, 0),
+
+        OP_2DROP => (2, 0, 0, 0),
+        OP_2DUP => (2, 4, 0, 0),
+        OP_3DUP => (3, 6, 0, 0),
+        OP_2OVER => (4, 6, 0, 0),
+        OP_2ROT => (6, 6, 0, 0),
+        OP_2SWAP => (4, 4, 0, 0),
+        OP_IFDUP | OP_DUP | OP_SIZE => (1, 2, 0, 0),
+        OP_NIP => (2, 1, 0, 0),
+        OP_OVER | OP_TUCK => (2, 3, 0, 0),
+        OP_PICK => (2, 2, 0, 0),
+        OP_ROLL => (2, 1, 0, 0),
+        OP_ROT => (3, 3, 0, 0),
+        OP_SWAP => (2, 2, 0, 0),
+
+        OP_CAT | OP_EQUAL => (2, 1, 0, 0),
+        OP_EQUALVERIFY => (2, 0, 0, 0),
+
+        OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => (1, 1, 0, 0),
+        OP_ADD
+        | OP_SUB
+        | OP_BOOLAND
+        | OP_BOOLOR
+        | OP_NUMEQUAL
+        | OP_NUMNOTEQUAL
+        | OP_LESSTHAN
+        | OP_GREATERTHAN
+        | OP_LESSTHANOREQUAL
+        | OP_GREATERTHANOREQUAL
+        | OP_MIN
+        | OP_MAX => (2, 1, 0, 0),
+        OP_NUMEQUALVERIFY => (2, 0, 0, 0),
+        OP_WITHIN => (3, 1, 0, 0),
+
+        OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => (1, 1, 0, 0),
+
+        OP_CHECKSIG => (2, 1, 0, 0),
+        OP_CHECKSIGVERIFY => (2, 0, 0, 0),
+        OP_CHECKSIGADD => (3, 1, 0, 0),
+        OP_CHECKMULTISIG => (3, 1, 0, 0),
+        OP_CHECKMULTISIGVERIFY => (3, 0, 0, 0),
+
+        _ => return None,
+    };
+    Some(effect)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::{LabeledScript, ScriptStats};
+    use crate::hint::HintSource;
+    use crate::spv::TxInclusionProofGadget;
+    use crate::structures::hash::BlockHashGadget;
+    use crate::structures::header::HeaderGadget;
+    use crate::treepp::*;
+
+    #[test]
+    fn test_stats() {
+        let script = script! {
+            OP_DUP OP_TOALTSTACK
+            OP_IF
+                5 6
+            OP_ELSE
+                7
+            OP_ENDIF
+            OP_FROMALTSTACK
+        };
+
+        let stats = ScriptStats::analyze(&script, 1, 0).unwrap();
+        assert_eq!(stats.script_len, script.len());
+        assert_eq!(stats.opcode_count, 6);
+        assert_eq!(stats.max_main_depth, 3);
+        assert_eq!(stats.max_alt_depth, 1);
+        assert_eq!(stats.max_total_depth, 3);
+        assert_eq!(stats.final_main_depth, 3);
+        assert_eq!(stats.final_alt_depth, 0);
+
+        assert!(ScriptStats::analyze(&script, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_gadget_stats() {
+        let stats = ScriptStats::analyze(&HeaderGadget::compute_hash_from_stack(), 6, 0).unwrap();
+        assert_eq!(stats.max_main_depth, 6);
+        assert_eq!(stats.final_main_depth, 1);
+        assert_eq!(stats.final_alt_depth, 0);
+
+        let hint = HintSource::default();
+
+        // the leaf, the number of siblings, the idx, and 17 siblings at most
+        let stats =
+            ScriptStats::analyze(&TxInclusionProofGadget::compute_merkle_root(&hint), 20, 0)
+                .unwrap();
+        assert!(stats.max_total_depth < 1000);
+        assert_eq!(stats.max_alt_depth, 17);
+        assert_eq!(stats.final_alt_depth, 0);
+
+        // the hash and four hints at most
+        let stats = ScriptStats::analyze(&BlockHashGadget::get_bit_security(&hint), 5, 0).unwrap();
+        assert!(stats.max_total_depth < 1000);
+        assert_eq!(stats.final_alt_depth, 0);
+
+        let mut covenant = LabeledScript::new();
+        for i in 0..6 {
+            let mut header = LabeledScript::new();
+            header.push(
+                "compute_hash_from_stack",
+                HeaderGadget::compute_hash_from_stack(),
+            );
+            header.push("get_bit_security", BlockHashGadget::get_bit_security(&hint));
+            covenant.extend(&format!("header {}", i + 1), header);
+        }
+
+        let all_stats = covenant.analyze(100, 0).unwrap();
+        assert_eq!(all_stats.len(), 12);
+        assert_eq!(all_stats[5].0, "header 3 / get_bit_security");
+
+        let total = ScriptStats::analyze(&covenant.compile(), 100, 0).unwrap();
+        assert_eq!(
+            total.script_len,
+            all_stats
+                .iter()
+                .map(|(_, stats)| stats.script_len)
+                .sum::<usize>()
+        );
+        assert_eq!(
+            total.opcode_count,
+            all_stats
+                .iter()
+                .map(|(_, stats)| stats.opcode_count)
+                .sum::<usize>()
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use crate::analysis::LabeledScript;
+use crate::treepp::*;
+use anyhow::{Error, Result};
+use bitcoin::blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use bitcoin::opcodes::all::{
+    OP_2DIV, OP_2MUL, OP_AND, OP_CAT, OP_CHECKSIGADD, OP_DIV, OP_INVERT, OP_LEFT, OP_LSHIFT,
+    OP_MOD, OP_MUL, OP_OR, OP_PUSHNUM_NEG1, OP_RIGHT, OP_RSHIFT, OP_SUBSTR, OP_XOR,
+};
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::Instruction;
+
+/// The largest script allowed by consensus in P2WSH.
+pub const MAX_SCRIPT_SIZE: usize = 10000;
+/// The largest number of non-push opcodes allowed by consensus in P2WSH.
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// The largest number of elements on the main stack and the altstack together.
+pub const MAX_STACK_SIZE: usize = 1000;
+/// The largest witness script that the default relay policy accepts.
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+/// The largest number of witness elements (besides the script) that the default relay policy
+/// accepts in P2WSH.
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+/// The largest witness element that the default relay policy accepts in P2WSH.
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+/// The largest witness element that the default relay policy accepts in tapscript.
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+/// The opcodes that make a P2WSH spend invalid: those disabled outside of tapscript, such as
+/// `OP_CAT`, and `OP_CHECKSIGADD`, which only exists in tapscript.
+pub const P2WSH_INVALID_OPCODES: [Opcode; 16] = [
+    OP_CAT,
+    OP_SUBSTR,
+    OP_LEFT,
+    OP_RIGHT,
+    OP_INVERT,
+    OP_AND,
+    OP_OR,
+    OP_XOR,
+    OP_2MUL,
+    OP_2DIV,
+    OP_MUL,
+    OP_DIV,
+    OP_MOD,
+    OP_LSHIFT,
+    OP_RSHIFT,
+    OP_CHECKSIGADD,
+];
+
+/// How the locking script is spent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptContext {
+    P2wsh,
+    Tapscript,
+}
+
+/// Whether breaking a rule makes the spending transaction invalid or only non-standard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Consensus,
+    Policy,
+}
+
+/// A broken rule, together with the gadget or witness segment that caused it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub severity: Severity,
+    pub label: String,
+    pub message: String,
+}
+
+/// Check a locking script and its witness against the consensus rules and the default relay
+/// policy.
+///
+/// Both are given as labeled segments, and the witness segments must consist of pushes only.
+/// In P2WSH, each of [`P2WSH_INVALID_OPCODES`] that a segment uses is a consensus violation, even
+/// in a branch that is not taken. Limits that depend on the sizes of the elements created during
+/// the execution, such as the 520-byte limit for the result of `OP_CAT`, are not checked.
+pub fn check_covenant(
+    context: ScriptContext,
+    script: &LabeledScript,
+    witness: &LabeledScript,
+) -> Result<Vec<Violation>> {
+    let mut violations = vec![];
+
+    let mut num_witness_elements = 0;
+    for (label, segment) in witness.segments.iter() {
+        let elements =
+            witness_elements(segment).map_err(|e| Error::msg(format!("{}: {}", label, e)))?;
+
+        for (i, element) in elements.iter().enumerate() {
+            let idx = num_witness_elements + i;
+            if element.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                violations.push(Violation {
+                    severity: Severity::Consensus,
+                    label: label.clone(),
+                    message: format!(
+                        "The witness element {} has {} bytes, over the limit of {}.",
+                        idx,
+                        element.len(),
+                        MAX_SCRIPT_ELEMENT_SIZE
+                    ),
+                });
+            } else {
+                let limit = match context {
+                    ScriptContext::P2wsh => MAX_STANDARD_P2WSH_STACK_ITEM_SIZE,
+                    ScriptContext::Tapscript => MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE,
+                };
+                if element.len() > limit {
+                    violations.push(Violation {
+                        severity: Severity::Policy,
+                        label: label.clone(),
+                        message: format!(
+                            "The witness element {} has {} bytes, over the standard limit of {}.",
+                            idx,
+                            element.len(),
+                            limit
+                        ),
+                    });
+                }
+            }
+        }
+
+        num_witness_elements += elements.len();
+        if context == ScriptContext::P2wsh
+            && num_witness_elements > MAX_STANDARD_P2WSH_STACK_ITEMS
+            && num_witness_elements - elements.len() <= MAX_STANDARD_P2WSH_STACK_ITEMS
+        {
+            violations.push(Violation {
+                severity: Severity::Policy,
+                label: label.clone(),
+                message: format!(
+                    "The witness reaches {} elements, over the standard limit of {}.",
+                    num_witness_elements, MAX_STANDARD_P2WSH_STACK_ITEMS
+                ),
+            });
+        }
+    }
+
+    if num_witness_elements > MAX_STACK_SIZE {
+        violations.push(Violation {
+            severity: Severity::Consensus,
+            label: "witness".to_string(),
+            message: format!(
+                "The witness has {} elements, over the limit of {}.",
+                num_witness_elements, MAX_STACK_SIZE
+            ),
+        });
+    }
+
+    let mut size_limits = vec![];
+    if context == ScriptContext::P2wsh {
+        size_limits.push((Severity::Consensus, MAX_SCRIPT_SIZE));
+        size_limits.push((Severity::Policy, MAX_STANDARD_P2WSH_SCRIPT_SIZE));
+    }
+
+    let all_stats = script.analyze(num_witness_elements, 0)?;
+
+    let mut script_len = 0;
+    let mut opcode_count = 0;
+    let mut stack_exceeded = false;
+    for ((label, segment), (_, stats)) in script.segments.iter().zip(all_stats.iter()) {
+        for (severity, limit) in size_limits.iter() {
+            if script_len <= *limit && script_len + stats.script_len > *limit {
+                violations.push(Violation {
+                    severity: *severity,
+                    label: label.clone(),
+                    message: format!(
+                        "The script grows from {} to {} bytes, over the {} limit of {}.",
+                        script_len,
+                        script_len + stats.script_len,
+                        if *severity == Severity::Consensus {
+                            "consensus"
+                        } else {
+                            "standard"
+                        },
+                        limit
+                    ),
+                });
+            }
+        }
+
+        if context == ScriptContext::P2wsh {
+            let mut used = vec![];
+            for instruction in segment.instructions() {
+                if let Ok(Instruction::Op(opcode)) = instruction {
+                    if P2WSH_INVALID_OPCODES.contains(&opcode) && !used.contains(&opcode) {
+                        used.push(opcode);
+                    }
+                }
+            }
+            for opcode in used {
+                violations.push(Violation {
+                    severity: Severity::Consensus,
+                    label: label.clone(),
+                    message: format!(
+                        "The script uses {}, which is not available in P2WSH.",
+                        opcode
+                    ),
+                });
+            }
+        }
+
+        if context == ScriptContext::P2wsh
+            && opcode_count <= MAX_OPS_PER_SCRIPT
+            && opcode_count + stats.opcode_count > MAX_OPS_PER_SCRIPT
+        {
+            violations.push(Violation {
+                severity: Severity::Consensus,
+                label: label.clone(),
+                message: format!(
+                    "The opcode count grows from {} to {}, over the limit of {}.",
+                    opcode_count,
+                    opcode_count + stats.opcode_count,
+                    MAX_OPS_PER_SCRIPT
+                ),
+            });
+        }
+
+        if !stack_exceeded && stats.max_total_depth > MAX_STACK_SIZE {
+            stack_exceeded = true;
+            violations.push(Violation {
+                severity: Severity::Consensus,
+                label: label.clone(),
+                message: format!(
+                    "The stacks may hold {} elements, over the limit of {}.",
+                    stats.max_total_depth, MAX_STACK_SIZE
+                ),
+            });
+        }
+
+        script_len += stats.script_len;
+        opcode_count += stats.opcode_count;
+    }
+
+    Ok(violations)
+}
+
+/// Convert a script of pushes into the witness elements that it pushes.
+pub fn witness_elements(script: &Script) -> Result<Vec<Vec<u8>>> {
+    let mut elements = vec![];
+    for instruction in script.instructions() {
+        match instruction.map_err(|e| Error::msg(format!("The witness cannot be parsed: {}", e)))? {
+            Instruction::PushBytes(bytes) => elements.push(bytes.as_bytes().to_vec()),
+            Instruction::Op(opcode) => {
+                match opcode.classify(bitcoin::opcodes::ClassifyContext::Legacy) {
+                    bitcoin::opcodes::Class::PushNum(v) => {
+                        if opcode == OP_PUSHNUM_NEG1 {
+                            elements.push(vec![0x81]);
+                        } else {
+                            elements.push(vec![v as u8]);
+                        }
+                    }
+                    _ => {
+                        return Err(Error::msg(format!(
+                            "The witness contains {}, which is not a push.",
+                            opcode
+                        )))
+                    }
+                }
+            }
+        }
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::policy::{check_covenant, ScriptContext, Severity, Violation};
+    use crate::analysis::LabeledScript;
+    use crate::treepp::*;
+
+    fn count(violations: &[Violation], label: &str, severity: Severity) -> usize {
+        violations
+            .iter()
+            .filter(|v| v.label == label && v.severity == severity)
+            .count()
+    }
+
+    #[test]
+    fn test_check_covenant() {
+        let mut script = LabeledScript::new();
+        script.push(
+            "small",
+            script! {
+                OP_2DROP OP_2DROP
+            },
+        );
+        script.push(
+            "large",
+            script! {
+                for _ in 0..4000 {
+                    OP_NOP
+                }
+            },
+        );
+        script.push(
+            "deep",
+            script! {
+                for _ in 0..6000 {
+                    OP_DEPTH
+                }
+            },
+        );
+
+        let mut witness = LabeledScript::new();
+        witness.push(
+            "fine",
+            script! {
+                { vec![0u8; 80] }
+                1
+            },
+        );
+        witness.push(
+            "nonstandard",
+            script! {
+                { vec![0u8; 81] }
+                { vec![0u8; 521] }
+            },
+        );
+
+        let violations = check_covenant(ScriptContext::P2wsh, &script, &witness).unwrap();
+
+        // the 81-byte element (policy) and the 521-byte element (consensus)
+        assert_eq!(count(&violations, "nonstandard", Severity::Policy), 1);
+        assert_eq!(count(&violations, "nonstandard", Severity::Consensus), 1);
+        // the script size (policy) and the opcode count (consensus)
+        assert_eq!(count(&violations, "large", Severity::Policy), 1);
+        assert_eq!(count(&violations, "large", Severity::Consensus), 1);
+        // the script size (consensus) and the stack size (consensus)
+        assert_eq!(count(&violations, "deep", Severity::Consensus), 2);
+        assert_eq!(violations.len(), 6);
+
+        let violations = check_covenant(ScriptContext::Tapscript, &script, &witness).unwrap();
+
+        // the 81-byte element (policy), the 521-byte element (consensus), and the stack size
+        assert_eq!(count(&violations, "nonstandard", Severity::Policy), 1);
+        assert_eq!(count(&violations, "deep", Severity::Consensus), 1);
+        assert_eq!(violations.len(), 3);
+    }
+
+    #[test]
+    fn test_check_covenant_opcodes() {
+        let mut script = LabeledScript::new();
+        script.push(
+            "cat",
+            script! {
+                OP_CAT OP_CAT
+                OP_IF OP_CHECKSIGADD OP_ENDIF
+            },
+        );
+        script.push("add", script! { OP_ADD });
+
+        let mut witness = LabeledScript::new();
+        witness.push(
+            "elements",
+            script! {
+                for _ in 0..8 {
+                    1
+                }
+            },
+        );
+
+        // OP_CAT and OP_CHECKSIGADD, reported once each even though OP_CAT is used twice
+        let violations = check_covenant(ScriptContext::P2wsh, &script, &witness).unwrap();
+        assert_eq!(count(&violations, "cat", Severity::Consensus), 2);
+        assert_eq!(violations.len(), 2);
+
+        let violations = check_covenant(ScriptContext::Tapscript, &script, &witness).unwrap();
+        assert!(violations.is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::analysis::LabeledScript;
+use crate::treepp::*;
+use anyhow::Result;
+use bitcoin_scriptexec::execute_script_with_witness;
+use std::fmt::{Display, Formatter};
+
+/// The state after executing a segment of a labeled script.
+pub struct TraceStep {
+    pub label: String,
+    /// The main stack after the segment, or at the point of failure.
+    pub main_stack: String,
+    /// The altstack after the segment, or before the segment if it fails.
+    pub alt_stack: String,
+    /// The static stack depths after the segment, or before the segment if it fails.
+    pub main_depth: usize,
+    pub alt_depth: usize,
+    pub error: Option<String>,
+}
+
+/// A segment-by-segment trace of the execution of a labeled script, which stops at the first
+/// segment that fails.
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+    pub success: bool,
+}
+
+impl Trace {
+    /// Execute the script with the witness one more segment at a time.
+    ///
+    /// The executor only reports the main stack, so the altstack is read by dropping the main
+    /// stack and moving the altstack over, which relies on the static stack depths from
+    /// [`LabeledScript::analyze`].
+    pub fn run(script: &LabeledScript, witness: Vec<Vec<u8>>) -> Result<Self> {
+        let stats = script.analyze(witness.len(), 0)?;
+
+        let mut steps: Vec<TraceStep> = vec![];
+        let mut success = false;
+        let mut prefix = vec![];
+
+        let mut alt_stack = String::new();
+        let mut main_depth = witness.len();
+        let mut alt_depth = 0;
+
+        for (i, (label, segment)) in script.segments.iter().enumerate() {
+            prefix.extend_from_slice(segment.as_bytes());
+
+            let exec_result =
+                execute_script_with_witness(Script::from_bytes(prefix.clone()), witness.clone());
+
+            if let Some(error) = exec_result.error {
+                steps.push(TraceStep {
+                    label: label.clone(),
+                    main_stack: format!("{:8}", exec_result.final_stack),
+                    alt_stack,
+                    main_depth,
+                    alt_depth,
+                    error: Some(format!("{:?}", error)),
+                });
+                success = false;
+                break;
+            }
+            success = exec_result.success;
+
+            main_depth = stats[i].1.final_main_depth;
+            alt_depth = stats[i].1.final_alt_depth;
+
+            let dump_alt_stack = script! {
+                for _ in 0..main_depth {
+                    OP_DROP
+                }
+                for _ in 0..alt_depth {
+                    OP_FROMALTSTACK
+                }
+                // restore the order of the altstack
+                for j in 1..alt_depth {
+                    { j } OP_ROLL
+                }
+            };
+            let mut dump = prefix.clone();
+            dump.extend_from_slice(dump_alt_stack.as_bytes());
+
+            let alt_result = execute_script_with_witness(Script::from_bytes(dump), witness.clone());
+            alt_stack = match alt_result.error {
+                None => format!("{:8}", alt_result.final_stack),
+                Some(_) => "(unavailable as the static stack depths are inexact)".to_string(),
+            };
+
+            steps.push(TraceStep {
+                label: label.clone(),
+                main_stack: format!("{:8}", exec_result.final_stack),
+                alt_stack: alt_stack.clone(),
+                main_depth,
+                alt_depth,
+                error: None,
+            });
+        }
+
+        Ok(Self { steps, success })
+    }
+
+    /// The segment that fails, if any.
+    pub fn failure(&self) -> Option<&TraceStep> {
+        self.steps.last().filter(|step| step.error.is_some())
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for step in self.steps.iter() {
+            writeln!(
+                f,
+                "{:6} {} (main: {}, alt: {})",
+                if step.error.is_some() {
+                    "[FAIL]"
+                } else {
+                    "[ok]"
+                },
+                step.label,
+                step.main_depth,
+                step.alt_depth
+            )?;
+        }
+
+        match self.failure() {
+            Some(step) => {
+                writeln!(f)?;
+                writeln!(
+                    f,
+                    "{} fails with {}",
+                    step.label,
+                    step.error.as_ref().unwrap()
+                )?;
+                writeln!(f, "main stack at the failure:")?;
+                writeln!(f, "{}", step.main_stack)?;
+                writeln!(f, "altstack before {}:", step.label)?;
+                writeln!(f, "{}", step.alt_stack)?;
+            }
+            None if !self.success => {
+                writeln!(f)?;
+                writeln!(
+                    f,
+                    "The script ends without a single true element on the stack."
+                )?;
+                if let Some(step) = self.steps.last() {
+                    writeln!(f, "final main stack:")?;
+                    writeln!(f, "{}", step.main_stack)?;
+                    writeln!(f, "final altstack:")?;
+                    writeln!(f, "{}", step.alt_stack)?;
+                }
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::policy::witness_elements;
+    use crate::analysis::trace::Trace;
+    use crate::analysis::LabeledScript;
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::{block_845797, given_tx, mainnet_covenant};
+    use crate::treepp::*;
+    use bitcoin::block::Header;
+    use bitcoin::consensus::Decodable;
+    use bitcoin::Txid;
+    use std::str::FromStr;
+
+    fn example() -> LabeledScript {
+        let mut script = LabeledScript::new();
+        script.push("push", script! { 1 OP_TOALTSTACK 2 3 });
+        script.push("add", script! { OP_ADD 5 OP_EQUALVERIFY });
+        script.push("check", script! { OP_FROMALTSTACK 1 OP_EQUAL });
+        script
+    }
+
+    #[test]
+    fn test_trace() {
+        let trace = Trace::run(&example(), vec![]).unwrap();
+        assert!(trace.success);
+        assert!(trace.failure().is_none());
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!(trace.steps[0].main_depth, 2);
+        assert_eq!(trace.steps[0].alt_depth, 1);
+        assert_eq!(
+            trace.to_string(),
+            "[ok]   push (main: 2, alt: 1)\n\
+             [ok]   add (main: 0, alt: 1)\n\
+             [ok]   check (main: 1, alt: 0)\n"
+        );
+
+        let mut script = example();
+        script.segments[1].1 = script! { OP_ADD 6 OP_EQUALVERIFY };
+        let trace = Trace::run(&script, vec![]).unwrap();
+        assert!(!trace.success);
+        assert_eq!(trace.steps.len(), 2);
+        assert_eq!(trace.failure().unwrap().label, "add");
+        let display = trace.to_string();
+        let lines = display.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "[ok]   push (main: 2, alt: 1)");
+        assert_eq!(lines[1], "[FAIL] add (main: 2, alt: 1)");
+        assert!(lines[3].starts_with("add fails with "));
+        assert_eq!(lines[4], "main stack at the failure:");
+        assert!(lines.contains(&"altstack before add:"));
+
+        let mut script = example();
+        script.push("extra", script! { OP_DUP });
+        let trace = Trace::run(&script, vec![]).unwrap();
+        assert!(!trace.success);
+        assert!(trace.failure().is_none());
+        assert_eq!(trace.steps.len(), 4);
+        assert!(trace
+            .to_string()
+            .contains("The script ends without a single true element on the stack."));
+    }
+
+    #[test]
+    fn test_trace_covenant() {
+        let tx = given_tx();
+
+        let block = block_845797();
+
+        let txid =
+            Txid::from_str("ac85e99fd914ccea8231f234541364ed6c2f4112905a6ed9c5b83479bf96008a")
+                .unwrap();
+        let proof = TxInclusionProof::construct_from_block(&block, &txid).unwrap();
+
+        let bytes = hex::decode("00000028429f8ccc5a6349852c559f0df3dbb26f2d0a569595c2010000000000000000007ef4fd2b9a9520fba80a2d14f8b46d9878508489be73c03119555ac3b6c7673080a35866f055031778e193c2").unwrap();
+        let headers = vec![
+            block.header,
+            Header::consensus_decode(&mut bytes.as_slice()).unwrap(),
+        ];
+
+        let covenant = mainnet_covenant(&tx.output[0].script_pubkey, 2, 78);
+        let witness = witness_elements(&covenant.witness(&tx, &proof, &headers).unwrap()).unwrap();
+
+        let trace = Trace::run(&covenant.labeled_locking_script(), witness.clone()).unwrap();
+        assert!(trace.success);
+
+        // corrupt the hint of the minimal bits of the 2nd header
+        let mut corrupted = witness.clone();
+        corrupted.last_mut().unwrap()[0] ^= 1;
+
+        let trace = Trace::run(&covenant.labeled_locking_script(), corrupted).unwrap();
+        assert_eq!(trace.failure().unwrap().label, "header 2 / check_min_bits");
+        let display = trace.to_string();
+        assert!(display.contains("\n[FAIL] header 2 / check_min_bits (main: "));
+        assert!(display.contains("\nheader 2 / check_min_bits fails with "));
+
+        // ask for more security than the headers have
+        let secure = mainnet_covenant(&tx.output[0].script_pubkey, 2, 90);
+        let trace = Trace::run(&secure.labeled_locking_script(), witness).unwrap();
+        assert_eq!(trace.failure().unwrap().label, "header 1 / check_min_bits");
+    }
+}
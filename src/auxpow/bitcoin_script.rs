@@ -0,0 +1,345 @@
+use crate::auxpow::{find, op_return_output_index, CoinbaseCommitmentProof, CommitmentLocation};
+use crate::consensus_encode;
+use crate::hint::HintSource;
+use crate::treepp::*;
+use crate::utils::{byte_to_byte_string, varint_from_number};
+use bitcoin::consensus::Encodable;
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::OP_RETURN;
+use covenants_gadgets::wizards::tx::step3_input::Step3SequenceGadget;
+use covenants_gadgets::wizards::tx::step5_output::{Step1AmountGadget, Step2ScriptPubKeyGadget};
+use covenants_gadgets::wizards::tx::Step6LockTimeGadget;
+
+pub struct CoinbaseCommitmentGadget;
+
+impl CoinbaseCommitmentGadget {
+    /// Push the hint for the proof, in the order that [`Self::compute_merkle_root`] pulls it.
+    pub fn push_coinbase_commitment_proof_as_hint(
+        proof: &CoinbaseCommitmentProof,
+        commitment: &[u8; 32],
+    ) -> Script {
+        let coinbase = &proof.coinbase;
+        let script_sig = coinbase.input[0].script_sig.as_bytes();
+
+        let coinbase_hint = match &proof.location {
+            CommitmentLocation::ScriptSig => {
+                let pos = find(script_sig, commitment)
+                    .expect("The scriptSig of the coinbase does not include the commitment.");
+
+                let mut rest = consensus_encode!(coinbase.output);
+                rest.extend(consensus_encode!(coinbase.lock_time));
+
+                script! {
+                    { script_sig[..pos].to_vec() }
+                    { script_sig[pos + 32..].to_vec() }
+                    { consensus_encode!(coinbase.version) }
+                    { consensus_encode!(coinbase.input[0].sequence) }
+                    { rest }
+                }
+            }
+            CommitmentLocation::OpReturn { tag, .. } => {
+                let output_index = op_return_output_index(coinbase, tag, commitment)
+                    .expect("No output of the coinbase is an OP_RETURN with the commitment.");
+
+                script! {
+                    { consensus_encode!(coinbase.version) }
+                    { script_sig.to_vec() }
+                    { consensus_encode!(coinbase.input[0].sequence) }
+                    { coinbase.output.len() }
+                    { output_index }
+                    for (i, output) in coinbase.output.iter().enumerate() {
+                        { consensus_encode!(output.value) }
+                        if i != output_index {
+                            { output.script_pubkey.as_bytes().to_vec() }
+                        }
+                    }
+                    { consensus_encode!(coinbase.lock_time) }
+                }
+            }
+        };
+
+        script! {
+            { coinbase_hint }
+            { proof.proof.siblings.len() }
+            for sibling in proof.proof.siblings.iter() {
+                { sibling.as_byte_array().to_vec() }
+            }
+        }
+    }
+
+    /// Compute the merkle root of a block whose coinbase commits to the value at the location.
+    ///
+    /// The coinbase is rebuilt from the hint around the commitment, parsing it from the start
+    /// up to the commitment so that the commitment cannot be anywhere else. The coinbase is then
+    /// hashed up the left-most path of the merkle tree, which ensures that it is at index 0.
+    /// The rebuilt coinbase is always longer than 64 bytes, so it cannot be mistaken for an
+    /// inner node of the merkle tree.
+    ///
+    /// hint (for [`CommitmentLocation::ScriptSig`]):
+    ///     scriptSig before the commitment
+    ///     scriptSig after the commitment
+    ///     version
+    ///     sequence
+    ///     outputs (with the output count) and lock time
+    ///
+    /// hint (for [`CommitmentLocation::OpReturn`]):
+    ///     version
+    ///     scriptSig
+    ///     sequence
+    ///     number of outputs (at most `max_outputs`)
+    ///     index of the output of the commitment (smaller than the number of outputs)
+    ///     [amount and script pubkey of each output, but only the amount of the commitment]
+    ///     lock time
+    ///
+    /// hint (for both):
+    ///     num of siblings
+    ///     [each sibling]
+    ///
+    /// input:
+    ///     commitment
+    ///
+    /// output:
+    ///     merkle root
+    ///
+    /// The coinbase, without the witness, must fit in a stack element of 520 bytes.
+    pub fn compute_merkle_root(location: &CommitmentLocation, hint: &HintSource) -> Script {
+        let rebuild_coinbase = match location {
+            CommitmentLocation::ScriptSig => Self::rebuild_coinbase_in_script_sig(hint),
+            CommitmentLocation::OpReturn { tag, max_outputs } => {
+                Self::rebuild_coinbase_in_op_return(tag, *max_outputs, hint)
+            }
+        };
+
+        script! {
+            OP_SIZE 32 OP_EQUALVERIFY
+            { rebuild_coinbase }
+
+            // compute the txid of the coinbase
+            OP_SHA256 OP_SHA256
+
+            // pull the number of siblings
+            { hint.pull(1, 0) }
+            OP_DUP 0 OP_GREATERTHANOREQUAL OP_VERIFY
+            OP_DUP 17 OP_LESSTHANOREQUAL OP_VERIFY
+
+            // the coinbase is always the left child
+            for _ in 0..17 {
+                OP_DUP OP_0NOTEQUAL OP_IF
+                    OP_SWAP
+
+                    // pull the sibling
+                    { hint.pull(2, 0) }
+                    OP_SIZE 32 OP_EQUALVERIFY
+
+                    OP_CAT OP_SHA256 OP_SHA256
+
+                    OP_SWAP OP_1SUB
+                OP_ENDIF
+            }
+
+            // drop the number of siblings, which would be zero
+            OP_DROP
+        }
+    }
+
+    /// The number of inputs and the outpoint of the coinbase input.
+    fn coinbase_input_prefix() -> Vec<u8> {
+        let mut bytes = vec![1u8];
+        bytes.extend_from_slice(&[0u8; 32]);
+        bytes.extend_from_slice(&[0xffu8; 4]);
+        bytes
+    }
+
+    fn rebuild_coinbase_in_script_sig(hint: &HintSource) -> Script {
+        script! {
+            // pull the scriptSig around the commitment
+            { hint.pull(1, 0) }
+            { hint.pull(2, 0) }
+
+            // compute the length of the scriptSig, which must be between 2 and 100
+            OP_SIZE
+            2 OP_PICK OP_SIZE OP_NIP
+            OP_ADD 32 OP_ADD
+            OP_DUP 2 101 OP_WITHIN OP_VERIFY
+            { byte_to_byte_string() }
+
+            // stack: commitment, before, after, length
+            OP_ROT OP_CAT
+            OP_ROT OP_CAT
+            OP_SWAP OP_CAT
+
+            // pull the version
+            { hint.pull(1, 0) }
+            OP_SIZE 4 OP_EQUALVERIFY
+            { Self::coinbase_input_prefix() } OP_CAT
+            OP_SWAP OP_CAT
+
+            { hint.pull(1, 0) } { Step3SequenceGadget::from_provided() }
+            OP_CAT
+
+            // the rest is not parsed, as the commitment is already located
+            { hint.pull(1, 0) }
+            OP_CAT
+        }
+    }
+
+    fn rebuild_coinbase_in_op_return(tag: &[u8], max_outputs: usize, hint: &HintSource) -> Script {
+        assert!(tag.len() + 32 <= 75);
+        assert!(max_outputs > 0);
+
+        let mut script_pubkey_prefix = vec![
+            (tag.len() + 34) as u8,
+            OP_RETURN.to_u8(),
+            (tag.len() + 32) as u8,
+        ];
+        script_pubkey_prefix.extend_from_slice(tag);
+
+        // stack: script pubkey of the commitment, index of its output, coinbase so far
+        // altstack: number of outputs
+        let output = |i: usize| {
+            script! {
+                { hint.pull(3, 1) } { Step1AmountGadget::from_provided() }
+                OP_CAT
+
+                OP_OVER { i } OP_NUMEQUAL OP_IF
+                    2 OP_PICK
+                OP_ELSE
+                    { hint.pull(3, 1) } { Step2ScriptPubKeyGadget::from_provided() }
+                OP_ENDIF
+                OP_CAT
+            }
+        };
+
+        script! {
+            // the script pubkey of the commitment, with its length
+            { script_pubkey_prefix } OP_SWAP OP_CAT
+
+            // pull the version
+            { hint.pull(1, 0) }
+            OP_SIZE 4 OP_EQUALVERIFY
+            { Self::coinbase_input_prefix() } OP_CAT
+
+            // pull the scriptSig, which must be between 2 and 100 bytes
+            { hint.pull(2, 0) }
+            OP_SIZE
+            OP_DUP 2 101 OP_WITHIN OP_VERIFY
+            { byte_to_byte_string() }
+            OP_SWAP OP_CAT
+            OP_CAT
+
+            { hint.pull(2, 0) } { Step3SequenceGadget::from_provided() }
+            OP_CAT
+
+            // pull the number of outputs
+            { hint.pull(2, 0) }
+            OP_1ADD OP_1SUB
+            OP_DUP 1 { max_outputs + 1 } OP_WITHIN OP_VERIFY
+            OP_DUP OP_TOALTSTACK
+            { varint_from_number() }
+            OP_CAT
+
+            // pull the index of the output of the commitment
+            { hint.pull(2, 1) }
+            OP_1ADD OP_1SUB
+            OP_DUP 0 OP_GREATERTHANOREQUAL OP_VERIFY
+            OP_FROMALTSTACK
+            OP_2DUP OP_LESSTHAN OP_VERIFY
+            OP_TOALTSTACK
+            OP_SWAP
+
+            // the coinbase has at least one output
+            { output(0) }
+            for i in 1..max_outputs {
+                OP_FROMALTSTACK OP_DUP OP_TOALTSTACK
+                { i } OP_GREATERTHAN OP_IF
+                    { output(i) }
+                OP_ENDIF
+            }
+            OP_FROMALTSTACK OP_DROP
+
+            // drop the script pubkey of the commitment and the index of its output
+            OP_NIP OP_NIP
+
+            { hint.pull(1, 0) } { Step6LockTimeGadget::from_provided() }
+            OP_CAT
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::auxpow::{CoinbaseCommitmentGadget, CoinbaseCommitmentProof};
+    use crate::hint::HintSource;
+    use crate::test_utils::block_845797;
+    use crate::treepp::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_coinbase_commitment() {
+        let block = block_845797();
+
+        let script_sig_commitment: [u8; 32] =
+            hex::decode("9905ae5c00c6b69a0075c8fc70f44be76be866bc7d52f03239f15ad986b1b247")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        let op_return_commitment: [u8; 32] =
+            hex::decode("be9e31ee3612db206d5978e8683ba152825bffe1afee8995c765a21b00618350")
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+        let proofs = [
+            (
+                CoinbaseCommitmentProof::construct_in_script_sig(&block, &script_sig_commitment)
+                    .unwrap(),
+                script_sig_commitment,
+            ),
+            (
+                CoinbaseCommitmentProof::construct_in_op_return(
+                    &block,
+                    &op_return_commitment,
+                    b"RSKBLOCK:",
+                    8,
+                )
+                .unwrap(),
+                op_return_commitment,
+            ),
+        ];
+
+        for (proof, commitment) in proofs.iter() {
+            for hint in [
+                HintSource::Bottom { offset: 1 },
+                HintSource::AltStack,
+                HintSource::Inline,
+            ] {
+                let script = script! {
+                    { b"unrelated".to_vec() }
+                    { hint.push(CoinbaseCommitmentGadget::push_coinbase_commitment_proof_as_hint(proof, commitment)) }
+
+                    { commitment.to_vec() }
+                    { CoinbaseCommitmentGadget::compute_merkle_root(&proof.location, &hint) }
+                    { block.header.merkle_root.as_byte_array().to_vec() }
+                    OP_EQUALVERIFY
+                    { b"unrelated".to_vec() } OP_EQUAL
+                };
+
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+
+            // any other commitment leads to another merkle root
+            let mut other = *commitment;
+            other[0] ^= 1;
+            let script = script! {
+                { CoinbaseCommitmentGadget::push_coinbase_commitment_proof_as_hint(proof, commitment) }
+                { other.to_vec() }
+                { CoinbaseCommitmentGadget::compute_merkle_root(&proof.location, &HintSource::default()) }
+                { block.header.merkle_root.as_byte_array().to_vec() }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::spv::TxInclusionProof;
+use anyhow::{Error, Result};
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::{Block, Transaction, TxMerkleNode};
+
+mod bitcoin_script;
+pub use bitcoin_script::*;
+
+/// Where the coinbase of a block commits to a 32-byte value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitmentLocation {
+    /// Anywhere in the scriptSig of the coinbase input, which only the miner controls.
+    ScriptSig,
+    /// In an output whose script pubkey is exactly `OP_RETURN <tag || commitment>`, such as the
+    /// `RSKBLOCK:` commitment of merged mining, of a coinbase with at most `max_outputs` outputs.
+    OpReturn { tag: Vec<u8>, max_outputs: usize },
+}
+
+/// A proof that the coinbase of a block commits to a 32-byte value, which consists of the
+/// coinbase, the location of the commitment, and the inclusion proof of the coinbase, which is
+/// always at index 0.
+pub struct CoinbaseCommitmentProof {
+    pub coinbase: Transaction,
+    pub location: CommitmentLocation,
+    pub proof: TxInclusionProof,
+}
+
+impl CoinbaseCommitmentProof {
+    /// Construct the proof of a commitment in the scriptSig of the coinbase.
+    pub fn construct_in_script_sig(block: &Block, commitment: &[u8; 32]) -> Result<Self> {
+        let coinbase = Self::coinbase_of(block)?;
+        if find(coinbase.input[0].script_sig.as_bytes(), commitment).is_none() {
+            return Err(Error::msg(
+                "The scriptSig of the coinbase does not include the commitment.",
+            ));
+        }
+
+        Self::construct(block, coinbase, CommitmentLocation::ScriptSig)
+    }
+
+    /// Construct the proof of a commitment in an `OP_RETURN <tag || commitment>` output of the
+    /// coinbase, which must have at most `max_outputs` outputs.
+    pub fn construct_in_op_return(
+        block: &Block,
+        commitment: &[u8; 32],
+        tag: &[u8],
+        max_outputs: usize,
+    ) -> Result<Self> {
+        let coinbase = Self::coinbase_of(block)?;
+        if coinbase.output.len() > max_outputs {
+            return Err(Error::msg("The coinbase has too many outputs."));
+        }
+        op_return_output_index(&coinbase, tag, commitment)?;
+
+        let location = CommitmentLocation::OpReturn {
+            tag: tag.to_vec(),
+            max_outputs,
+        };
+        Self::construct(block, coinbase, location)
+    }
+
+    fn coinbase_of(block: &Block) -> Result<Transaction> {
+        block
+            .txdata
+            .first()
+            .filter(|tx| tx.is_coinbase())
+            .cloned()
+            .ok_or_else(|| Error::msg("The block does not start with a coinbase."))
+    }
+
+    fn construct(
+        block: &Block,
+        coinbase: Transaction,
+        location: CommitmentLocation,
+    ) -> Result<Self> {
+        let proof = TxInclusionProof::construct_from_block(block, &coinbase.compute_txid())?;
+        assert_eq!(proof.idx, 0);
+
+        Ok(Self {
+            coinbase,
+            location,
+            proof,
+        })
+    }
+
+    /// Verify that the coinbase commits to the value at its location and is the first
+    /// transaction under the merkle root.
+    pub fn verify(&self, commitment: &[u8; 32], root: &TxMerkleNode) -> Result<()> {
+        if !self.coinbase.is_coinbase() {
+            return Err(Error::msg("The transaction is not a coinbase."));
+        }
+        if self.proof.idx != 0 {
+            return Err(Error::msg("The coinbase must be at index 0."));
+        }
+
+        match &self.location {
+            CommitmentLocation::ScriptSig => {
+                if find(self.coinbase.input[0].script_sig.as_bytes(), commitment).is_none() {
+                    return Err(Error::msg(
+                        "The scriptSig of the coinbase does not include the commitment.",
+                    ));
+                }
+            }
+            CommitmentLocation::OpReturn { tag, max_outputs } => {
+                if self.coinbase.output.len() > *max_outputs {
+                    return Err(Error::msg("The coinbase has too many outputs."));
+                }
+                op_return_output_index(&self.coinbase, tag, commitment)?;
+            }
+        }
+
+        self.proof.verify_tx_inclusion(&self.coinbase, root)
+    }
+}
+
+/// Find the position of the commitment in the bytes.
+pub(crate) fn find(bytes: &[u8], commitment: &[u8; 32]) -> Option<usize> {
+    bytes
+        .windows(32)
+        .position(|window| window == commitment.as_slice())
+}
+
+/// Find the index of the `OP_RETURN <tag || commitment>` output of the coinbase.
+pub(crate) fn op_return_output_index(
+    coinbase: &Transaction,
+    tag: &[u8],
+    commitment: &[u8; 32],
+) -> Result<usize> {
+    let expected = op_return_script_pubkey(tag, commitment)?;
+    coinbase
+        .output
+        .iter()
+        .position(|output| output.script_pubkey.as_bytes() == expected.as_slice())
+        .ok_or_else(|| Error::msg("No output of the coinbase is an OP_RETURN with the commitment."))
+}
+
+/// The script pubkey `OP_RETURN <tag || commitment>`, with the length prefix omitted.
+pub(crate) fn op_return_script_pubkey(tag: &[u8], commitment: &[u8; 32]) -> Result<Vec<u8>> {
+    if tag.len() + 32 > 75 {
+        return Err(Error::msg(
+            "The tag and the commitment must fit in a single direct push of at most 75 bytes.",
+        ));
+    }
+
+    let mut bytes = vec![OP_RETURN.to_u8(), (tag.len() + 32) as u8];
+    bytes.extend_from_slice(tag);
+    bytes.extend_from_slice(commitment);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::auxpow::{CoinbaseCommitmentProof, CommitmentLocation};
+    use crate::test_utils::block_845797;
+    use bitcoin::hashes::Hash;
+    use bitcoin::TxMerkleNode;
+
+    // the merged-mining commitment after the magic bytes fabe6d6d in the scriptSig
+    const SCRIPT_SIG_COMMITMENT: &str =
+        "9905ae5c00c6b69a0075c8fc70f44be76be866bc7d52f03239f15ad986b1b247";
+
+    // the commitment in the RSKBLOCK: output
+    const OP_RETURN_COMMITMENT: &str =
+        "be9e31ee3612db206d5978e8683ba152825bffe1afee8995c765a21b00618350";
+
+    fn commitment(hex: &str) -> [u8; 32] {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_coinbase_commitment_proof() {
+        let block = block_845797();
+        let root = block.header.merkle_root;
+
+        let proof = CoinbaseCommitmentProof::construct_in_script_sig(
+            &block,
+            &commitment(SCRIPT_SIG_COMMITMENT),
+        )
+        .unwrap();
+        assert_eq!(proof.location, CommitmentLocation::ScriptSig);
+        proof
+            .verify(&commitment(SCRIPT_SIG_COMMITMENT), &root)
+            .unwrap();
+        assert!(proof
+            .verify(&commitment(OP_RETURN_COMMITMENT), &root)
+            .is_err());
+        assert!(proof
+            .verify(
+                &commitment(SCRIPT_SIG_COMMITMENT),
+                &TxMerkleNode::all_zeros()
+            )
+            .is_err());
+
+        let proof = CoinbaseCommitmentProof::construct_in_op_return(
+            &block,
+            &commitment(OP_RETURN_COMMITMENT),
+            b"RSKBLOCK:",
+            8,
+        )
+        .unwrap();
+        assert_eq!(
+            proof.location,
+            CommitmentLocation::OpReturn {
+                tag: b"RSKBLOCK:".to_vec(),
+                max_outputs: 8,
+            }
+        );
+        proof
+            .verify(&commitment(OP_RETURN_COMMITMENT), &root)
+            .unwrap();
+
+        // the coinbase has 5 outputs
+        assert!(CoinbaseCommitmentProof::construct_in_op_return(
+            &block,
+            &commitment(OP_RETURN_COMMITMENT),
+            b"RSKBLOCK:",
+            4
+        )
+        .is_err());
+
+        // a commitment in the scriptSig is not one in an OP_RETURN, and the other way around
+        assert!(CoinbaseCommitmentProof::construct_in_op_return(
+            &block,
+            &commitment(SCRIPT_SIG_COMMITMENT),
+            b"RSKBLOCK:",
+            8
+        )
+        .is_err());
+        assert!(CoinbaseCommitmentProof::construct_in_script_sig(
+            &block,
+            &commitment(OP_RETURN_COMMITMENT)
+        )
+        .is_err());
+    }
+}
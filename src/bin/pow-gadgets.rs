@@ -0,0 +1,401 @@
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::consensus::deserialize;
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::{Address, Block, Network, ScriptBuf, Transaction, TxMerkleNode, Txid};
+use reuse_bitcoin_pow_gadgets::analysis::policy::witness_elements;
+use reuse_bitcoin_pow_gadgets::analysis::trace::Trace;
+use reuse_bitcoin_pow_gadgets::covenant::PowSpvCovenant;
+use reuse_bitcoin_pow_gadgets::datadir::{BlockFile, BlocksDir};
+use reuse_bitcoin_pow_gadgets::network::NetworkParams;
+use reuse_bitcoin_pow_gadgets::spv::TxInclusionProof;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+const USAGE: &str = "Usage:
+    pow-gadgets proof --block <FILE> --txid <TXID> [--network <NETWORK>]
+        Build the inclusion proof of a transaction from a file with one block (raw or hex) or
+        from a blk*.dat file. Prints the index and then the siblings, one per line.
+
+    pow-gadgets script <COVENANT>
+        Print the locking script of the covenant and its taproot address, whose only leaf is
+        the locking script and whose internal key is unspendable.
+
+    pow-gadgets witness <COVENANT> --tx <HEX> --proof <FILE> --headers <FILE>
+        Print the complete witness for spending the covenant, one item per line: the witness
+        elements, the locking script, and the control block. The proof file is the output of
+        `proof`, and the headers file has one header in hex per line, starting from the block
+        with the transaction.
+
+    pow-gadgets run <COVENANT> --tx <HEX> --proof <FILE> --headers <FILE>
+        Execute the covenant with the witness and report whether it passes. On failure, print
+        a trace of the gadgets with the stacks where it fails.
+
+COVENANT:
+    --script-pubkey <HEX> | --address <ADDRESS>
+        The script pubkey that the first output of the transaction pays to.
+    [--max-inputs <N>]      The maximal number of inputs of the transaction, 1 by default.
+    [--max-outputs <N>]     The maximal number of outputs of the transaction, 2 by default.
+    [--num-headers <N>]     The number of headers, 6 by default.
+    [--min-bits <N>]        The minimal bits of security of each header, at most 256, 78 by
+                            default.
+    [--network <NETWORK>]   bitcoin, testnet, testnet4, signet, or regtest, bitcoin by default.
+";
+
+struct Args {
+    values: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut values = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(key) = iter.next() {
+            let key = key
+                .strip_prefix("--")
+                .ok_or_else(|| Error::msg(format!("Unexpected argument {}.", key)))?;
+            let value = iter
+                .next()
+                .ok_or_else(|| Error::msg(format!("Missing the value of --{}.", key)))?;
+            values.insert(key.to_string(), value.clone());
+        }
+        Ok(Self { values })
+    }
+
+    fn get(&self, key: &str) -> Result<&str> {
+        self.values
+            .get(key)
+            .map(String::as_str)
+            .ok_or_else(|| Error::msg(format!("Missing --{}.", key)))
+    }
+
+    fn get_or<T: FromStr>(&self, key: &str, default: T) -> Result<T> {
+        match self.values.get(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| Error::msg(format!("Invalid value of --{}: {}", key, value))),
+            None => Ok(default),
+        }
+    }
+
+    fn network(&self) -> Result<Network> {
+        self.get_or("network", Network::Bitcoin)
+    }
+
+    fn covenant(&self) -> Result<PowSpvCovenant> {
+        let script_pub_key = match (self.values.get("script-pubkey"), self.values.get("address")) {
+            (Some(hex), None) => ScriptBuf::from_hex(hex)?,
+            (None, Some(address)) => Address::from_str(address)?
+                .require_network(self.network()?)?
+                .script_pubkey(),
+            _ => {
+                return Err(Error::msg(
+                    "Exactly one of --script-pubkey and --address is required.",
+                ))
+            }
+        };
+
+        PowSpvCovenant::new(
+            NetworkParams::new(self.network()?),
+            script_pub_key,
+            self.get_or("max-inputs", 1)?,
+            self.get_or("max-outputs", 2)?,
+            self.get_or("num-headers", 6)?,
+            self.get_or("min-bits", 78)?,
+        )
+    }
+
+    fn witness_inputs(&self) -> Result<(Transaction, TxInclusionProof, Vec<Header>)> {
+        let tx: Transaction = deserialize_hex(self.get("tx")?.trim())?;
+
+        let proof = std::fs::read_to_string(self.get("proof")?)?;
+        let mut lines = proof.lines().map(str::trim).filter(|line| !line.is_empty());
+        let idx = lines
+            .next()
+            .ok_or_else(|| Error::msg("The proof file is empty."))?
+            .parse()?;
+        let siblings = lines
+            .map(TxMerkleNode::from_str)
+            .collect::<Result<Vec<TxMerkleNode>, _>>()?;
+
+        let headers = std::fs::read_to_string(self.get("headers")?)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(deserialize_hex)
+            .collect::<Result<Vec<Header>, _>>()?;
+
+        Ok((tx, TxInclusionProof { idx, siblings }, headers))
+    }
+}
+
+/// Read the block with the transaction from a file with a single block, in raw bytes or hex, or
+/// from a blk*.dat file, which may be obfuscated by the xor.dat next to it.
+fn read_block(path: &Path, txid: &Txid, network: Network) -> Result<Block> {
+    let bytes = std::fs::read(path)?;
+
+    // a block in hex starts with the 160 hex digits of its header, which raw bytes do not
+    let start = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .map_or(&bytes[..0], |pos| &bytes[pos..]);
+    if start.len() >= 160 && start[..160].iter().all(u8::is_ascii_hexdigit) {
+        let decoded = Vec::<u8>::from_hex(std::str::from_utf8(start)?.trim_end())?;
+        return Ok(deserialize(&decoded)?);
+    }
+    if let Ok(block) = deserialize::<Block>(&bytes) {
+        return Ok(block);
+    }
+
+    let dir = BlocksDir::open(path.parent().unwrap_or(Path::new(".")), network)?;
+    BlockFile::from_bytes(bytes, &dir.xor_key, network)
+        .blocks()?
+        .into_iter()
+        .find(|block| block.txdata.iter().any(|tx| tx.compute_txid() == *txid))
+        .ok_or_else(|| Error::msg("The transaction is not in the block file."))
+}
+
+fn run(command: &str, args: &Args, out: &mut impl Write) -> Result<bool> {
+    match command {
+        "proof" => {
+            let txid = Txid::from_str(args.get("txid")?)?;
+            let block = read_block(Path::new(args.get("block")?), &txid, args.network()?)?;
+            let proof = TxInclusionProof::construct_from_block(&block, &txid)?;
+
+            writeln!(out, "{}", proof.idx)?;
+            for sibling in proof.siblings.iter() {
+                writeln!(out, "{}", sibling)?;
+            }
+        }
+        "script" => {
+            let covenant = args.covenant()?;
+            let script = covenant.locking_script();
+
+            writeln!(out, "script: {}", script.as_bytes().to_lower_hex_string())?;
+            writeln!(out, "size: {}", script.len())?;
+            writeln!(out, "address: {}", covenant.address(args.network()?))?;
+        }
+        "witness" => {
+            let covenant = args.covenant()?;
+            let (tx, proof, headers) = args.witness_inputs()?;
+            let witness = covenant.witness(&tx, &proof, &headers)?;
+
+            for element in witness_elements(&witness)? {
+                writeln!(out, "{}", element.to_lower_hex_string())?;
+            }
+            writeln!(
+                out,
+                "{}",
+                covenant.locking_script().as_bytes().to_lower_hex_string()
+            )?;
+            writeln!(
+                out,
+                "{}",
+                covenant.control_block().serialize().to_lower_hex_string()
+            )?;
+        }
+        "run" => {
+            let covenant = args.covenant()?;
+            let (tx, proof, headers) = args.witness_inputs()?;
+            let witness = covenant.witness(&tx, &proof, &headers)?;
+
+            let witness = witness_elements(&witness)?;
+
+            let exec_result = bitcoin_scriptexec::execute_script_with_witness(
+                covenant.locking_script(),
+                witness.clone(),
+            );
+            if exec_result.success {
+                writeln!(out, "PASS")?;
+            } else {
+                writeln!(out, "FAIL")?;
+                writeln!(out)?;
+                write!(
+                    out,
+                    "{}",
+                    Trace::run(&covenant.labeled_locking_script(), witness)?
+                )?;
+            }
+            return Ok(exec_result.success);
+        }
+        _ => return Err(Error::msg(format!("Unknown command {}.", command))),
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.is_empty() || args[0] == "--help" || args[0] == "help" {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let result =
+        Args::parse(&args[1..]).and_then(|parsed| run(&args[0], &parsed, &mut std::io::stdout()));
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!();
+            eprint!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{read_block, run, Args};
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hex::{DisplayHex, FromHex};
+    use bitcoin::taproot::ControlBlock;
+    use bitcoin::{Network, ScriptBuf, Txid};
+    use reuse_bitcoin_pow_gadgets::covenant::PowSpvCovenant;
+    use reuse_bitcoin_pow_gadgets::network::NetworkParams;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    const TX: &str = "0200000000010152c0ef39e255fbe3858282c59ed3a3747b71bc17632daf1029e5f86e19761f290000000000fdffffff02e803000000000000220020ba714b93459645d8c931819b567a75b304eb8a69a3f71432f6ad3be9780b639c0085070000000000160014ba3cde39438c04d6645b8c130d36bb0c7cbf2fbd0247304402203d99f19bb84c2c8b60f6495b0851ff68d42527600735a521efcffe9549bcaa4002203b6cf74de4a1d36a4eb3cf8dd3e61d359dedd708153862501f9b439ab1da49d9012102113f09ba5f346c77205630298995acbe1f95c77c882b2c0e1408277e5290db4f9de70c00";
+    const TXID: &str = "ac85e99fd914ccea8231f234541364ed6c2f4112905a6ed9c5b83479bf96008a";
+    const SCRIPT_PUBKEY: &str =
+        "0020ba714b93459645d8c931819b567a75b304eb8a69a3f71432f6ad3be9780b639c";
+    const HEADERS: [&str; 5] = [
+        "00000028429f8ccc5a6349852c559f0df3dbb26f2d0a569595c2010000000000000000007ef4fd2b9a9520fba80a2d14f8b46d9878508489be73c03119555ac3b6c7673080a35866f055031778e193c2",
+        "000000266a8e17a3277e4f686ca9a94a4fa55b3e71bfdf67423202000000000000000000e61ba13c3fdd44bb5d0460be891f86c083ada120103eb771bc8db4996368e0ceffa85866f055031765f48dc8",
+        "00000020b0f13d66e3b1e4183fa8ef62841d915efe90657fff8702000000000000000000ad9bfd71cfa12ed9b56757bd9778bf253070c1c91e6b4558f34ac0fb8fb85cda41a95866f0550317af8e9959",
+        "00000520dfb88d3a4d93486a906de316f7d36b9aadbe0665cf4e0000000000000000000051e75174de68a0846322c6807468a8d2e9d3ea3c06cc590d4e4bd4632acd1fbe70ae5866f0550317671357d4",
+        "0060cb220f19d5c3831a175bdd905641c1ca5408aee009b13abc01000000000000000000acc4cdc5bd0a3a3fe71f2c73d6365bd94ae7e6160aacdb8d1c0dd4a9b88edb2d5cb05866f05503172b8d180d",
+    ];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "reuse-bitcoin-pow-gadgets-cli-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn run_command(command: &str, args: &[&str]) -> (bool, String) {
+        let args = args.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        let mut out = vec![];
+        let success = run(command, &Args::parse(&args).unwrap(), &mut out).unwrap();
+        (success, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_cli() {
+        let path = temp_dir("e2e");
+        let block_path = "./src/spv/block_845797";
+
+        // proof
+        let (_, proof) = run_command("proof", &["--block", block_path, "--txid", TXID]);
+        let lines = proof.lines().collect::<Vec<&str>>();
+        let siblings = lines[1..].len();
+        assert!(lines[0].parse::<u32>().is_ok());
+        assert!(siblings > 0);
+        let proof_path = path.join("proof");
+        std::fs::write(&proof_path, &proof).unwrap();
+
+        let block = read_block(
+            Path::new(block_path),
+            &Txid::from_str(TXID).unwrap(),
+            Network::Bitcoin,
+        )
+        .unwrap();
+        let headers_path = path.join("headers");
+        std::fs::write(
+            &headers_path,
+            [
+                vec![serialize_hex(&block.header)],
+                HEADERS.map(String::from).to_vec(),
+            ]
+            .concat()
+            .join("\n"),
+        )
+        .unwrap();
+
+        let covenant = PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            ScriptBuf::from_hex(SCRIPT_PUBKEY).unwrap(),
+            1,
+            2,
+            6,
+            78,
+        )
+        .unwrap();
+        let locking_script = covenant.locking_script().as_bytes().to_lower_hex_string();
+
+        // script
+        let (_, script) = run_command("script", &["--script-pubkey", SCRIPT_PUBKEY]);
+        let lines = script.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], format!("script: {}", locking_script));
+        assert_eq!(
+            lines[2],
+            format!("address: {}", covenant.address(Network::Bitcoin))
+        );
+
+        // witness, which ends with the locking script and the control block
+        let witness_args = [
+            "--script-pubkey",
+            SCRIPT_PUBKEY,
+            "--tx",
+            TX,
+            "--proof",
+            proof_path.to_str().unwrap(),
+            "--headers",
+            headers_path.to_str().unwrap(),
+        ];
+        let (_, witness) = run_command("witness", &witness_args);
+        let lines = witness.lines().collect::<Vec<&str>>();
+        assert!(lines.len() > 2);
+        assert_eq!(lines[lines.len() - 2], locking_script);
+        let control_block =
+            ControlBlock::decode(&Vec::<u8>::from_hex(lines[lines.len() - 1]).unwrap()).unwrap();
+        assert_eq!(control_block, covenant.control_block());
+
+        // run
+        let (success, output) = run_command("run", &witness_args);
+        assert!(success);
+        assert_eq!(output, "PASS\n");
+
+        // fewer headers than the covenant requires
+        let args = witness_args
+            .iter()
+            .map(|x| x.to_string())
+            .chain(["--num-headers".to_string(), "7".to_string()])
+            .collect::<Vec<String>>();
+        let mut out = vec![];
+        assert!(run("run", &Args::parse(&args).unwrap(), &mut out).is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_block() {
+        let path = temp_dir("read-block");
+        let txid = Txid::from_str(TXID).unwrap();
+        let block =
+            read_block(Path::new("./src/spv/block_845797"), &txid, Network::Bitcoin).unwrap();
+
+        // the same block in raw bytes
+        let raw_path = path.join("block.bin");
+        std::fs::write(&raw_path, bitcoin::consensus::serialize(&block)).unwrap();
+        let read = read_block(&raw_path, &txid, Network::Bitcoin).unwrap();
+        assert_eq!(read.block_hash(), block.block_hash());
+
+        // hex with surrounding whitespace
+        let hex_path = path.join("block.hex");
+        std::fs::write(&hex_path, format!("\n  {}\n", serialize_hex(&block))).unwrap();
+        let read = read_block(&hex_path, &txid, Network::Bitcoin).unwrap();
+        assert_eq!(read.block_hash(), block.block_hash());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
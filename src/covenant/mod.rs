@@ -0,0 +1,655 @@
+use crate::analysis::LabeledScript;
+use crate::consensus_encode;
+use crate::hint::HintSource;
+use crate::network::NetworkParams;
+use crate::pow::{count_leading_zeros, HashByteOrder};
+use crate::spv::{TxInclusionProof, TxInclusionProofGadget};
+use crate::structures::hash::BlockHashGadget;
+use crate::treepp::*;
+use crate::tx::{TxFields, TxFieldsGadget};
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::script::Instruction;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::{Address, KnownHrp, ScriptBuf, Transaction};
+use covenants_gadgets::utils::pseudo::OP_HINT;
+use std::str::FromStr;
+
+mod spent_outpoint;
+pub use spent_outpoint::*;
+
+mod refresh;
+pub use refresh::*;
+
+/// The PoW SPV covenant, which accepts a witness showing that a transaction paying to
+/// `script_pub_key` in its first output is included in a block, and that this block and the
+/// blocks after it, `num_headers` in total, each have at least `min_bit_security` bits of
+/// security.
+///
+/// The transaction can have up to `max_inputs` inputs, segwit or legacy, and up to `max_outputs`
+/// outputs, and is rebuilt with [`TxFieldsGadget::compute_txid`], so that without the witness it
+/// must fit in a stack element of 520 bytes. Larger transactions are not supported: the script
+/// of [`TxFieldsGadget::compute_txid_with_last_output`] is several megabytes and only exposes
+/// the last output.
+///
+/// The parameters are checked by [`Self::new`], which is the only way to create the covenant.
+pub struct PowSpvCovenant {
+    script_pub_key: ScriptBuf,
+    max_inputs: usize,
+    max_outputs: usize,
+    num_headers: usize,
+    min_bit_security: usize,
+    /// The network of the headers, whose proof-of-work limit the witness is checked against.
+    params: NetworkParams,
+}
+
+impl PowSpvCovenant {
+    /// Create the covenant for the headers of the given network, checking that it looks at one
+    /// header or more, that `min_bit_security` does not exceed the 256 bits of a block hash, and
+    /// that a transaction can have between one and 32767 inputs and outputs.
+    ///
+    /// On a network with blocks of the minimum difficulty, such as regtest, only
+    /// [`NetworkParams::min_bit_security`] can be counted on.
+    pub fn new(
+        params: NetworkParams,
+        script_pub_key: ScriptBuf,
+        max_inputs: usize,
+        max_outputs: usize,
+        num_headers: usize,
+        min_bit_security: usize,
+    ) -> Result<Self> {
+        check_header_chain_params(num_headers, min_bit_security)?;
+        if !(1..=0x7fff).contains(&max_inputs) || !(1..=0x7fff).contains(&max_outputs) {
+            return Err(Error::msg(
+                "The numbers of inputs and outputs must be between 1 and 32767.",
+            ));
+        }
+        Ok(Self {
+            script_pub_key,
+            max_inputs,
+            max_outputs,
+            num_headers,
+            min_bit_security,
+            params,
+        })
+    }
+
+    pub fn locking_script(&self) -> Script {
+        self.labeled_locking_script().compile()
+    }
+
+    /// The locking script split into labeled gadgets, for analysis and tracing.
+    pub fn labeled_locking_script(&self) -> LabeledScript {
+        assert!(self.num_headers > 0);
+
+        let mut script = LabeledScript::new();
+
+        script.push(
+            "tx / txid",
+            TxFieldsGadget::compute_txid(&self.tx_fields(), &HintSource::default()),
+        );
+        script.push(
+            "tx / check_output",
+            script! {
+                // stack: amount, script pubkey, txid
+                OP_SWAP
+                { self.script_pub_key.as_bytes().to_vec() } OP_EQUALVERIFY
+                OP_NIP
+            },
+        );
+
+        script.push(
+            "spv / compute_merkle_root",
+            TxInclusionProofGadget::compute_merkle_root(&HintSource::default()),
+        );
+        push_header_chain(&mut script, self.num_headers, self.min_bit_security);
+
+        script.push("accept", script! { OP_TRUE });
+        script
+    }
+
+    /// The taproot address of the covenant, with the locking script as its only leaf.
+    ///
+    /// The covenant relies on OP_CAT, which is only available in tapscript, and the internal key
+    /// is unspendable, see [`taproot_spend_info`].
+    pub fn address(&self, hrp: impl Into<KnownHrp>) -> Address {
+        Address::p2tr_tweaked(taproot_spend_info(self.locking_script()).output_key(), hrp)
+    }
+
+    /// The control block for spending the covenant through its locking script, which goes
+    /// after the locking script in the witness.
+    pub fn control_block(&self) -> ControlBlock {
+        let script = self.locking_script();
+        taproot_spend_info(script.clone())
+            .control_block(&(script, LeafVersion::TapScript))
+            .unwrap()
+    }
+
+    /// The fields of the transaction that the locking script rebuilds, exposing the first output.
+    fn tx_fields(&self) -> TxFields {
+        TxFields {
+            max_inputs: self.max_inputs,
+            max_outputs: self.max_outputs,
+            inputs: vec![],
+            outputs: vec![0],
+        }
+    }
+
+    /// Assemble the witness from the transaction, its inclusion proof, and the headers starting
+    /// from the block that includes the transaction.
+    pub fn witness(
+        &self,
+        tx: &Transaction,
+        proof: &TxInclusionProof,
+        headers: &[Header],
+    ) -> Result<Script> {
+        self.tx_fields().check(tx)?;
+        if tx.output[0].script_pubkey != self.script_pub_key {
+            return Err(Error::msg(
+                "The first output of the transaction does not pay to the covenant's target.",
+            ));
+        }
+        check_header_chain(
+            &self.params,
+            headers,
+            self.num_headers,
+            self.min_bit_security,
+        )?;
+        proof.verify_tx_inclusion(tx, &headers[0].merkle_root)?;
+
+        Ok(script! {
+            { TxFieldsGadget::push_tx_as_hint(tx) }
+            { TxInclusionProofGadget::push_tx_inclusion_proof_as_hint(proof) }
+            { push_header_chain_hint(headers, self.min_bit_security) }
+        })
+    }
+}
+
+/// The internal key of the covenant outputs, which is the point H from BIP-341 that has no known
+/// discrete logarithm, so that the outputs can only be spent through their script.
+pub(crate) fn nums_internal_key() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_str("50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0")
+        .unwrap()
+}
+
+/// The taproot tree of a covenant output, with `locking_script` as its only leaf and
+/// [`nums_internal_key`] as the internal key.
+pub(crate) fn taproot_spend_info(locking_script: Script) -> TaprootSpendInfo {
+    TaprootBuilder::new()
+        .add_leaf(0, locking_script)
+        .unwrap()
+        .finalize(&Secp256k1::verification_only(), nums_internal_key())
+        .unwrap()
+}
+
+/// Append the check that the merkle root on the stack is the one of the first of `num_headers`
+/// linked headers, each with at least `min_bit_security` bits of security.
+///
+/// The hint is from [`push_header_chain_hint`].
+pub(crate) fn push_header_chain(
+    script: &mut LabeledScript,
+    num_headers: usize,
+    min_bit_security: usize,
+) {
+    assert!(num_headers > 0);
+
+    script.push(
+        "spv / check_merkle_root",
+        script! {
+            OP_HINT OP_DUP OP_TOALTSTACK // save a copy of tx merkle root to the altstack
+            OP_EQUALVERIFY
+        },
+    );
+
+    for i in 0..num_headers {
+        let prefix = format!("header {}", i + 1);
+
+        let mut header = LabeledScript::new();
+        if i == 0 {
+            // the merkle root is from the spv proof, and the previous block hash is provided
+            header.push(
+                "fields",
+                script! {
+                    OP_HINT { crate::structures::version::VersionGadget::from_provided() }
+                    OP_HINT { crate::structures::hash::BlockHashGadget::from_provided() }
+                    OP_FROMALTSTACK { crate::structures::merkle_hash::MerkleHashGadget::from_provided() }
+                    OP_HINT { crate::structures::time::TimeGadget::from_provided() }
+                    OP_HINT { crate::structures::compact_target::CompactTargetGadget::from_provided() }
+                    OP_HINT { crate::structures::nonce::NonceGadget::from_provided() }
+                },
+            );
+        } else {
+            // the previous block hash is from the last header
+            header.push(
+                "fields",
+                script! {
+                    OP_HINT { crate::structures::version::VersionGadget::from_provided() }
+                    OP_FROMALTSTACK { crate::structures::hash::BlockHashGadget::from_provided() }
+                    OP_HINT { crate::structures::merkle_hash::MerkleHashGadget::from_provided() }
+                    OP_HINT { crate::structures::time::TimeGadget::from_provided() }
+                    OP_HINT { crate::structures::compact_target::CompactTargetGadget::from_provided() }
+                    OP_HINT { crate::structures::nonce::NonceGadget::from_provided() }
+                },
+            );
+        }
+        header.push(
+            "compute_hash",
+            script! {
+                { crate::structures::header::HeaderGadget::compute_hash_from_stack() }
+                OP_DUP OP_TOALTSTACK
+            },
+        );
+        header.push(
+            "check_min_bits",
+            BlockHashGadget::check_min_bits(min_bit_security, &HintSource::default()),
+        );
+
+        script.extend(&prefix, header);
+    }
+}
+
+/// Check that a chain of `num_headers` headers, each with at least `min_bit_security` bits of
+/// security, can be checked by [`push_header_chain`].
+pub(crate) fn check_header_chain_params(num_headers: usize, min_bit_security: usize) -> Result<()> {
+    if num_headers == 0 {
+        return Err(Error::msg("The covenant requires at least one header."));
+    }
+    if min_bit_security > 256 {
+        return Err(Error::msg(format!(
+            "The covenant requires {} bits of security, but a block hash has only 256 bits.",
+            min_bit_security
+        )));
+    }
+    Ok(())
+}
+
+/// Check that there are `num_headers` linked headers, each meeting its target within the
+/// proof-of-work limit of the network and having at least `min_bit_security` bits of security,
+/// and that the covenant parameters are valid, see [`check_header_chain_params`].
+pub(crate) fn check_header_chain(
+    params: &NetworkParams,
+    headers: &[Header],
+    num_headers: usize,
+    min_bit_security: usize,
+) -> Result<()> {
+    check_header_chain_params(num_headers, min_bit_security)?;
+    if headers.len() != num_headers {
+        return Err(Error::msg(format!(
+            "The covenant requires {} headers, but {} are given.",
+            num_headers,
+            headers.len()
+        )));
+    }
+    for (i, header) in headers.iter().enumerate() {
+        if i > 0 && header.prev_blockhash != headers[i - 1].block_hash() {
+            return Err(Error::msg(format!(
+                "The header {} does not follow the header {}.",
+                i,
+                i - 1
+            )));
+        }
+        params.check_pow(header)?;
+
+        let bit_security = count_leading_zeros(
+            header.block_hash().as_byte_array(),
+            HashByteOrder::LittleEndian,
+        );
+        if bit_security < min_bit_security {
+            return Err(Error::msg(format!(
+                "The header {} has {} bits of security, but the covenant requires {}.",
+                i, bit_security, min_bit_security
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Push the hint for [`push_header_chain`].
+pub(crate) fn push_header_chain_hint(headers: &[Header], min_bit_security: usize) -> Script {
+    script! {
+        // tx merkle root
+        { headers[0].merkle_root.as_byte_array().to_vec() }
+
+        // 1st block header
+        { consensus_encode!(headers[0].version) }
+        { consensus_encode!(headers[0].prev_blockhash) }
+        { consensus_encode!(headers[0].time) }
+        { consensus_encode!(headers[0].bits) }
+        { consensus_encode!(headers[0].nonce) }
+        { BlockHashGadget::push_min_bits_hint(&headers[0].block_hash(), min_bit_security) }
+
+        // subsequent block headers
+        for header in headers.iter().skip(1) {
+            { consensus_encode!(header.version) }
+            { consensus_encode!(header.merkle_root) }
+            { consensus_encode!(header.time) }
+            { consensus_encode!(header.bits) }
+            { consensus_encode!(header.nonce) }
+            { BlockHashGadget::push_min_bits_hint(&header.block_hash(), min_bit_security) }
+        }
+    }
+}
+
+/// Parse the headers back from the hint of [`push_header_chain_hint`] at the end of a witness.
+pub(crate) fn header_chain_from_witness(
+    witness: &Script,
+    num_headers: usize,
+    min_bit_security: usize,
+) -> Result<Vec<Header>> {
+    let min_bits_hint_len = match min_bit_security {
+        0 => 0,
+        bits if bits % 8 == 0 => 1,
+        _ => 2,
+    };
+    let header_hint_len = 5 + min_bits_hint_len;
+
+    let elements = witness
+        .instructions()
+        .map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes().to_vec()),
+            _ => None,
+        })
+        .collect::<Vec<Option<Vec<u8>>>>();
+    let len = 1 + num_headers * header_hint_len;
+    if elements.len() < len {
+        return Err(Error::msg("The witness is too short for the headers."));
+    }
+    let elements = &elements[elements.len() - len..];
+
+    let field = |i: usize| {
+        elements[i]
+            .clone()
+            .ok_or_else(|| Error::msg("The witness does not push the headers."))
+    };
+
+    let mut headers: Vec<Header> = vec![];
+    for i in 0..num_headers {
+        let start = 1 + i * header_hint_len;
+
+        // the first header has its merkle root in front, and the others have it in place of the
+        // hash of the previous header
+        let mut bytes = field(start)?;
+        match headers.last() {
+            None => {
+                bytes.extend(field(start + 1)?);
+                bytes.extend(field(0)?);
+            }
+            Some(prev) => {
+                bytes.extend(prev.block_hash().as_byte_array());
+                bytes.extend(field(start + 1)?);
+            }
+        }
+        for j in 2..5 {
+            bytes.extend(field(start + j)?);
+        }
+
+        headers.push(
+            deserialize(&bytes)
+                .map_err(|_| Error::msg("The witness does not push the headers."))?,
+        );
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::covenant::PowSpvCovenant;
+    use crate::network::NetworkParams;
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::{block_845797, given_tx, mainnet_covenant, mine_block, mine_chain};
+    use crate::treepp::*;
+    use bitcoin::block::Header;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::Decodable;
+    use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+    use bitcoin::{Network, ScriptBuf, Transaction, Txid};
+    use std::str::FromStr;
+
+    fn load() -> (Transaction, TxInclusionProof, Vec<Header>) {
+        let given_tx = given_tx();
+
+        let block = block_845797();
+
+        let txid =
+            Txid::from_str("ac85e99fd914ccea8231f234541364ed6c2f4112905a6ed9c5b83479bf96008a")
+                .unwrap();
+        let proof = TxInclusionProof::construct_from_block(&block, &txid).unwrap();
+
+        let mut headers = vec![block.header];
+        for header in [
+            "00000028429f8ccc5a6349852c559f0df3dbb26f2d0a569595c2010000000000000000007ef4fd2b9a9520fba80a2d14f8b46d9878508489be73c03119555ac3b6c7673080a35866f055031778e193c2",
+            "000000266a8e17a3277e4f686ca9a94a4fa55b3e71bfdf67423202000000000000000000e61ba13c3fdd44bb5d0460be891f86c083ada120103eb771bc8db4996368e0ceffa85866f055031765f48dc8",
+            "00000020b0f13d66e3b1e4183fa8ef62841d915efe90657fff8702000000000000000000ad9bfd71cfa12ed9b56757bd9778bf253070c1c91e6b4558f34ac0fb8fb85cda41a95866f0550317af8e9959",
+            "00000520dfb88d3a4d93486a906de316f7d36b9aadbe0665cf4e0000000000000000000051e75174de68a0846322c6807468a8d2e9d3ea3c06cc590d4e4bd4632acd1fbe70ae5866f0550317671357d4",
+            "0060cb220f19d5c3831a175bdd905641c1ca5408aee009b13abc01000000000000000000acc4cdc5bd0a3a3fe71f2c73d6365bd94ae7e6160aacdb8d1c0dd4a9b88edb2d5cb05866f05503172b8d180d",
+        ] {
+            let bytes = hex::decode(header).unwrap();
+            headers.push(Header::consensus_decode(&mut bytes.as_slice()).unwrap());
+        }
+
+        (given_tx, proof, headers)
+    }
+
+    #[test]
+    fn test_pow_spv_covenant() {
+        let (tx, proof, headers) = load();
+
+        let covenant = mainnet_covenant(&tx.output[0].script_pubkey, 6, 78);
+        // the address is a taproot output that commits to the locking script
+        let address = covenant.address(Network::Bitcoin);
+        assert!(address.script_pubkey().is_p2tr());
+        assert!(covenant.control_block().verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            XOnlyPublicKey::from_slice(&address.script_pubkey().as_bytes()[2..]).unwrap(),
+            &covenant.locking_script(),
+        ));
+
+        let witness = covenant.witness(&tx, &proof, &headers).unwrap();
+        let exec_result = execute_script_with_witness(
+            covenant.locking_script(),
+            convert_to_witness(witness).unwrap(),
+        );
+        assert!(exec_result.success);
+
+        // fewer headers
+        let covenant = mainnet_covenant(&tx.output[0].script_pubkey, 3, 78);
+        let witness = covenant.witness(&tx, &proof, &headers[..3]).unwrap();
+        let exec_result = execute_script_with_witness(
+            covenant.locking_script(),
+            convert_to_witness(witness).unwrap(),
+        );
+        assert!(exec_result.success);
+
+        // not enough security, which the witness refuses to prove
+        let covenant = mainnet_covenant(&tx.output[0].script_pubkey, 6, 90);
+        assert!(covenant.witness(&tx, &proof, &headers).is_err());
+
+        // and that the locking script rejects when given a witness for fewer bits
+        let weaker = mainnet_covenant(&tx.output[0].script_pubkey, 6, 78);
+        let witness = weaker.witness(&tx, &proof, &headers).unwrap();
+        let exec_result = execute_script_with_witness(
+            covenant.locking_script(),
+            convert_to_witness(witness).unwrap(),
+        );
+        assert!(!exec_result.success);
+    }
+
+    /// A covenant for up to three inputs and four outputs, and two mainnet headers.
+    fn wide_covenant(script_pub_key: &ScriptBuf) -> PowSpvCovenant {
+        PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            script_pub_key.clone(),
+            3,
+            4,
+            2,
+            78,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pow_spv_covenant_shapes() {
+        let (_, _, headers) = load();
+        let block = block_845797();
+
+        // transactions with one to three inputs, segwit or legacy, and one to four outputs
+        let txs = block
+            .txdata
+            .iter()
+            .skip(1)
+            .filter(|tx| tx.input.len() <= 3 && tx.output.len() <= 4 && tx.base_size() <= 520)
+            .take(10)
+            .collect::<Vec<_>>();
+        assert!(txs.iter().any(|tx| !tx.input[0].script_sig.is_empty()));
+
+        for tx in txs {
+            let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+            let covenant = wide_covenant(&tx.output[0].script_pubkey);
+
+            let witness = covenant.witness(tx, &proof, &headers[..2]).unwrap();
+            let exec_result = execute_script_with_witness(
+                covenant.locking_script(),
+                convert_to_witness(witness).unwrap(),
+            );
+            assert!(exec_result.success);
+        }
+
+        // a legacy transaction with two inputs and three outputs does not unlock a covenant for
+        // another script pubkey
+        let tx = &block.txdata[541];
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+        let covenant = wide_covenant(&tx.output[0].script_pubkey);
+        let other = wide_covenant(&tx.output[2].script_pubkey);
+        let witness = covenant.witness(tx, &proof, &headers[..2]).unwrap();
+        let exec_result = execute_script_with_witness(
+            other.locking_script(),
+            convert_to_witness(witness).unwrap(),
+        );
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_pow_spv_covenant_regtest() {
+        let (tx, _, _) = load();
+
+        // the transaction is mined after a coinbase, then buried under a regtest chain
+        let genesis = genesis_block(Network::Regtest);
+        let block = mine_block(
+            &genesis.header,
+            0,
+            vec![genesis.txdata[0].clone(), tx.clone()],
+        );
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+        let headers = [vec![block.header], mine_chain(&block.header, 5, 0)].concat();
+
+        let params = NetworkParams::new(Network::Regtest);
+        let covenant = PowSpvCovenant::new(
+            params.clone(),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            2,
+            6,
+            params.min_bit_security(),
+        )
+        .unwrap();
+        let witness = covenant.witness(&tx, &proof, &headers).unwrap();
+        let exec_result = execute_script_with_witness(
+            covenant.locking_script(),
+            convert_to_witness(witness).unwrap(),
+        );
+        assert!(exec_result.success);
+
+        // regtest headers are above the proof-of-work limit of mainnet
+        let mainnet = mainnet_covenant(&tx.output[0].script_pubkey, 6, params.min_bit_security());
+        assert!(mainnet.witness(&tx, &proof, &headers).is_err());
+
+        // and they do not have the security of mainnet blocks
+        let secure =
+            PowSpvCovenant::new(params, tx.output[0].script_pubkey.clone(), 1, 2, 6, 78).unwrap();
+        assert!(secure.witness(&tx, &proof, &headers).is_err());
+    }
+
+    #[test]
+    fn test_pow_spv_covenant_witness_errors() {
+        let (tx, proof, headers) = load();
+
+        let covenant = mainnet_covenant(&tx.output[0].script_pubkey, 6, 78);
+
+        assert!(covenant.witness(&tx, &proof, &headers[..5]).is_err());
+
+        let mut unlinked = headers.clone();
+        unlinked.swap(2, 3);
+        assert!(covenant.witness(&tx, &proof, &unlinked).is_err());
+
+        let wrong_proof = TxInclusionProof {
+            idx: proof.idx ^ 1,
+            siblings: proof.siblings.clone(),
+        };
+        assert!(covenant.witness(&tx, &wrong_proof, &headers).is_err());
+
+        let other = mainnet_covenant(&tx.output[1].script_pubkey, 6, 78);
+        assert!(other.witness(&tx, &proof, &headers).is_err());
+
+        // more bits of security than a block hash has, or no header at all
+        assert!(PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            2,
+            6,
+            257
+        )
+        .is_err());
+        assert!(PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            2,
+            0,
+            78
+        )
+        .is_err());
+        assert!(PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            2,
+            6,
+            256
+        )
+        .is_ok());
+        assert!(PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            tx.output[0].script_pubkey.clone(),
+            0,
+            2,
+            6,
+            78
+        )
+        .is_err());
+        assert!(PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            0x8000,
+            6,
+            78
+        )
+        .is_err());
+
+        // fewer outputs than the transaction has
+        let narrow = PowSpvCovenant::new(
+            NetworkParams::new(Network::Bitcoin),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            1,
+            6,
+            78,
+        )
+        .unwrap();
+        assert!(narrow.witness(&tx, &proof, &headers).is_err());
+    }
+}
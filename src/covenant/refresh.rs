@@ -0,0 +1,247 @@
+use crate::covenant::{header_chain_from_witness, PowSpvCovenant, SpentOutpointCovenant};
+use crate::light_client::{LightClient, TxStatus};
+use crate::spv::TxInclusionProof;
+use crate::treepp::*;
+use anyhow::Result;
+use bitcoin::block::Header;
+use bitcoin::{Transaction, Txid};
+
+/// The outcome of refreshing a witness against the best chain of a [`LightClient`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WitnessRefresh {
+    /// The headers are still those of the best chain, so the old witness remains valid.
+    Unchanged,
+    /// The best chain has changed since the old witness was built, so a new witness is built
+    /// from the new inclusion proof and headers.
+    Rebuilt {
+        witness: Script,
+        proof: TxInclusionProof,
+        headers: Vec<Header>,
+        /// The positions of the headers that differ from those of the old witness.
+        changed_headers: Vec<usize>,
+    },
+    /// The transaction is in the best chain, but without enough blocks for the covenant.
+    NotEnoughConfirmations { depth: u32 },
+    /// The block that includes the transaction is no longer in the best chain.
+    NotInBestChain,
+}
+
+/// Compare the headers of an old witness, parsed from the end of it, with those of the best
+/// chain, and rebuild the witness with `witness` if they differ.
+///
+/// The inclusion proof only depends on the block that includes the transaction, so it changes
+/// only if the first header does. The transaction must be watched by the light client, which
+/// provides this block and the inclusion proof. After a reorg that moves the transaction to
+/// another block, the new block should be watched with [`LightClient::watch_tx`] first.
+fn refresh_witness_with(
+    txid: &Txid,
+    old_witness: &Script,
+    num_headers: usize,
+    min_bit_security: usize,
+    client: &LightClient,
+    witness: impl FnOnce(&TxInclusionProof, &[Header]) -> Result<Script>,
+) -> Result<WitnessRefresh> {
+    let headers = header_chain_from_witness(old_witness, num_headers, min_bit_security)?;
+
+    match client.tx_status(txid)? {
+        TxStatus::NotInBestChain => return Ok(WitnessRefresh::NotInBestChain),
+        TxStatus::Confirmed { depth, .. } if (depth as usize) < num_headers => {
+            return Ok(WitnessRefresh::NotEnoughConfirmations { depth })
+        }
+        TxStatus::Confirmed { .. } => {}
+    }
+
+    let (_, new_proof) = client.get_watched_tx(txid).unwrap();
+    let new_headers = client.witness_headers(txid, num_headers)?.unwrap();
+
+    let changed_headers = (0..num_headers)
+        .filter(|i| headers[*i] != new_headers[*i])
+        .collect::<Vec<usize>>();
+    if changed_headers.is_empty() {
+        return Ok(WitnessRefresh::Unchanged);
+    }
+
+    Ok(WitnessRefresh::Rebuilt {
+        witness: witness(new_proof, &new_headers)?,
+        proof: new_proof.clone(),
+        headers: new_headers,
+        changed_headers,
+    })
+}
+
+impl PowSpvCovenant {
+    /// Check whether an old witness of the covenant for `tx` is still valid for the best chain
+    /// of the light client, and rebuild it if it is not.
+    pub fn refresh_witness(
+        &self,
+        tx: &Transaction,
+        old_witness: &Script,
+        client: &LightClient,
+    ) -> Result<WitnessRefresh> {
+        refresh_witness_with(
+            &tx.compute_txid(),
+            old_witness,
+            self.num_headers,
+            self.min_bit_security,
+            client,
+            |proof, headers| self.witness(tx, proof, headers),
+        )
+    }
+}
+
+impl SpentOutpointCovenant {
+    /// Check whether an old witness of the covenant for `tx` is still valid for the best chain
+    /// of the light client, and rebuild it if it is not.
+    pub fn refresh_witness(
+        &self,
+        tx: &Transaction,
+        old_witness: &Script,
+        client: &LightClient,
+    ) -> Result<WitnessRefresh> {
+        refresh_witness_with(
+            &tx.compute_txid(),
+            old_witness,
+            self.num_headers,
+            self.min_bit_security,
+            client,
+            |proof, headers| self.witness(tx, proof, headers),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::covenant::{PowSpvCovenant, WitnessRefresh};
+    use crate::light_client::LightClient;
+    use crate::network::NetworkParams;
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::{given_tx, mine, mine_chain};
+    use crate::treepp::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Network, TxMerkleNode};
+
+    #[test]
+    fn test_refresh_witness() {
+        let tx = given_tx();
+        let txid = tx.compute_txid();
+
+        // the transaction is the only one in its block
+        let merkle_root = TxMerkleNode::from_byte_array(txid.to_byte_array());
+        let proof = TxInclusionProof {
+            idx: 0,
+            siblings: vec![],
+        };
+
+        // regtest headers only guarantee the minimum difficulty
+        let params = NetworkParams::new(Network::Regtest);
+        let covenant = PowSpvCovenant::new(
+            params.clone(),
+            tx.output[0].script_pubkey.clone(),
+            1,
+            2,
+            3,
+            params.min_bit_security(),
+        )
+        .unwrap();
+
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut client = LightClient::new(params, 0, genesis);
+
+        let block = mine(&genesis, 0, merkle_root);
+        let main = [vec![block], mine_chain(&block, 2, 0)].concat();
+        let old_witness = covenant.witness(&tx, &proof, &main).unwrap();
+
+        client.accept_headers(&main[..2]).unwrap();
+        client
+            .watch_tx(txid, block.block_hash(), proof.clone())
+            .unwrap();
+
+        assert_eq!(
+            covenant
+                .refresh_witness(&tx, &old_witness, &client)
+                .unwrap(),
+            WitnessRefresh::NotEnoughConfirmations { depth: 2 }
+        );
+
+        client.accept_headers(&main[2..]).unwrap();
+        assert_eq!(
+            covenant
+                .refresh_witness(&tx, &old_witness, &client)
+                .unwrap(),
+            WitnessRefresh::Unchanged
+        );
+
+        // a reorg above the block that includes the transaction
+        let fork = mine_chain(&block, 3, 1);
+        assert!(client.accept_headers(&fork).unwrap().unwrap().is_reorg());
+
+        let refresh = covenant
+            .refresh_witness(&tx, &old_witness, &client)
+            .unwrap();
+        let WitnessRefresh::Rebuilt {
+            witness,
+            headers,
+            changed_headers,
+            ..
+        } = refresh
+        else {
+            panic!("the witness should be rebuilt");
+        };
+        assert_eq!(changed_headers, vec![1, 2]);
+        assert_eq!(headers, vec![block, fork[0], fork[1]]);
+
+        let exec_result = execute_script_with_witness(
+            covenant.locking_script(),
+            convert_to_witness(witness.clone()).unwrap(),
+        );
+        assert!(exec_result.success);
+
+        // a reorg that removes the block that includes the transaction
+        let other = mine_chain(&genesis, 5, 2);
+        assert!(client.accept_headers(&other).unwrap().unwrap().is_reorg());
+        assert_eq!(
+            covenant.refresh_witness(&tx, &witness, &client).unwrap(),
+            WitnessRefresh::NotInBestChain
+        );
+
+        // the transaction is confirmed again in another block
+        let again = mine(&other[4], 2, merkle_root);
+        let new_chain = [vec![again], mine_chain(&again, 2, 2)].concat();
+        client.accept_headers(&new_chain).unwrap();
+        client
+            .watch_tx(txid, again.block_hash(), proof.clone())
+            .unwrap();
+
+        let refresh = covenant.refresh_witness(&tx, &witness, &client).unwrap();
+        let WitnessRefresh::Rebuilt {
+            witness,
+            headers,
+            changed_headers,
+            ..
+        } = refresh
+        else {
+            panic!("the witness should be rebuilt");
+        };
+        assert_eq!(changed_headers, vec![0, 1, 2]);
+        assert_eq!(headers, new_chain);
+
+        let exec_result = execute_script_with_witness(
+            covenant.locking_script(),
+            convert_to_witness(witness.clone()).unwrap(),
+        );
+        assert!(exec_result.success);
+
+        // a transaction that is not watched
+        let mut unwatched = tx.clone();
+        unwatched.lock_time = bitcoin::absolute::LockTime::from_consensus(1);
+        assert!(covenant
+            .refresh_witness(&unwatched, &witness, &client)
+            .is_err());
+
+        // a witness without the headers
+        assert!(covenant
+            .refresh_witness(&tx, &Script::new(), &client)
+            .is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::analysis::LabeledScript;
+use crate::covenant::{
+    check_header_chain, check_header_chain_params, push_header_chain, push_header_chain_hint,
+};
+use crate::hint::HintSource;
+use crate::network::NetworkParams;
+use crate::spv::{TxInclusionProof, TxInclusionProofGadget};
+use crate::treepp::*;
+use crate::tx::{check_tx_size, TxFieldsGadget};
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::{OutPoint, Transaction};
+
+/// The spent-outpoint covenant, which accepts a witness showing that a transaction spending
+/// `outpoint` in one of its first `max_input_index + 1` inputs is included in a block, and that
+/// this block and the blocks after it, `num_headers` in total, each have at least
+/// `min_bit_security` bits of security.
+pub struct SpentOutpointCovenant {
+    pub outpoint: OutPoint,
+    pub max_input_index: usize,
+    pub num_headers: usize,
+    pub min_bit_security: usize,
+    /// The network of the headers, whose proof-of-work limit the witness is checked against.
+    pub params: NetworkParams,
+}
+
+impl SpentOutpointCovenant {
+    /// Create the covenant for the headers of the given network, with the same checks as
+    /// [`crate::covenant::PowSpvCovenant::new`].
+    pub fn new(
+        params: NetworkParams,
+        outpoint: OutPoint,
+        max_input_index: usize,
+        num_headers: usize,
+        min_bit_security: usize,
+    ) -> Result<Self> {
+        check_header_chain_params(num_headers, min_bit_security)?;
+        Ok(Self {
+            outpoint,
+            max_input_index,
+            num_headers,
+            min_bit_security,
+            params,
+        })
+    }
+
+    pub fn locking_script(&self) -> Script {
+        self.labeled_locking_script().compile()
+    }
+
+    /// The locking script split into labeled gadgets, for analysis and tracing.
+    pub fn labeled_locking_script(&self) -> LabeledScript {
+        let mut script = LabeledScript::new();
+
+        script.push(
+            "tx / txid",
+            TxFieldsGadget::compute_txid_spending(
+                &self.outpoint,
+                self.max_input_index,
+                &HintSource::default(),
+            ),
+        );
+        script.push(
+            "spv / compute_merkle_root",
+            TxInclusionProofGadget::compute_merkle_root(&HintSource::default()),
+        );
+        push_header_chain(&mut script, self.num_headers, self.min_bit_security);
+
+        script.push("accept", script! { OP_TRUE });
+        script
+    }
+
+    /// Assemble the witness from the spending transaction, its inclusion proof, and the headers
+    /// starting from the block that includes the transaction.
+    pub fn witness(
+        &self,
+        tx: &Transaction,
+        proof: &TxInclusionProof,
+        headers: &[Header],
+    ) -> Result<Script> {
+        let input_index = tx
+            .input
+            .iter()
+            .position(|input| input.previous_output == self.outpoint)
+            .ok_or_else(|| Error::msg("The transaction does not spend the outpoint."))?;
+        if input_index > self.max_input_index {
+            return Err(Error::msg(format!(
+                "The transaction spends the outpoint in the input {}, but the covenant only looks at the first {} inputs.",
+                input_index,
+                self.max_input_index + 1
+            )));
+        }
+        check_tx_size(tx)?;
+        check_header_chain(
+            &self.params,
+            headers,
+            self.num_headers,
+            self.min_bit_security,
+        )?;
+        proof.verify_tx_inclusion(tx, &headers[0].merkle_root)?;
+
+        Ok(script! {
+            { TxFieldsGadget::push_tx_spending_as_hint(tx, input_index) }
+            { TxInclusionProofGadget::push_tx_inclusion_proof_as_hint(proof) }
+            { push_header_chain_hint(headers, self.min_bit_security) }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::covenant::SpentOutpointCovenant;
+    use crate::network::NetworkParams;
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::block_845797;
+    use crate::treepp::*;
+    use bitcoin::block::Header;
+    use bitcoin::consensus::Decodable;
+    use bitcoin::{Block, Network, OutPoint};
+
+    fn load() -> (Block, Vec<Header>) {
+        let block = block_845797();
+
+        let mut headers = vec![block.header];
+        for header in [
+            "00000028429f8ccc5a6349852c559f0df3dbb26f2d0a569595c2010000000000000000007ef4fd2b9a9520fba80a2d14f8b46d9878508489be73c03119555ac3b6c7673080a35866f055031778e193c2",
+            "000000266a8e17a3277e4f686ca9a94a4fa55b3e71bfdf67423202000000000000000000e61ba13c3fdd44bb5d0460be891f86c083ada120103eb771bc8db4996368e0ceffa85866f055031765f48dc8",
+        ] {
+            let bytes = hex::decode(header).unwrap();
+            headers.push(Header::consensus_decode(&mut bytes.as_slice()).unwrap());
+        }
+
+        (block, headers)
+    }
+
+    #[test]
+    fn test_spent_outpoint_covenant() {
+        let (block, headers) = load();
+
+        // a transaction with two inputs spending legacy outputs
+        let tx = &block.txdata[541];
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+
+        for input_index in 0..2 {
+            let covenant = SpentOutpointCovenant {
+                params: NetworkParams::new(Network::Bitcoin),
+                outpoint: tx.input[input_index].previous_output,
+                max_input_index: 3,
+                num_headers: 3,
+                min_bit_security: 78,
+            };
+
+            let witness = covenant.witness(tx, &proof, &headers).unwrap();
+            let exec_result = execute_script_with_witness(
+                covenant.locking_script(),
+                convert_to_witness(witness).unwrap(),
+            );
+            assert!(exec_result.success);
+        }
+
+        // the witness for one outpoint does not unlock the covenant of another
+        let covenant = SpentOutpointCovenant {
+            params: NetworkParams::new(Network::Bitcoin),
+            outpoint: tx.input[1].previous_output,
+            max_input_index: 3,
+            num_headers: 3,
+            min_bit_security: 78,
+        };
+        let other = SpentOutpointCovenant {
+            params: NetworkParams::new(Network::Bitcoin),
+            outpoint: OutPoint {
+                txid: tx.input[1].previous_output.txid,
+                vout: tx.input[1].previous_output.vout + 1,
+            },
+            ..covenant
+        };
+        let witness = covenant.witness(tx, &proof, &headers).unwrap();
+        let exec_result = execute_script_with_witness(
+            other.locking_script(),
+            convert_to_witness(witness).unwrap(),
+        );
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_spent_outpoint_covenant_witness_errors() {
+        let (block, headers) = load();
+        let tx = &block.txdata[541];
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+
+        let covenant = SpentOutpointCovenant {
+            params: NetworkParams::new(Network::Bitcoin),
+            outpoint: tx.input[1].previous_output,
+            max_input_index: 0,
+            num_headers: 3,
+            min_bit_security: 78,
+        };
+        assert!(covenant.witness(tx, &proof, &headers).is_err());
+
+        let covenant = SpentOutpointCovenant {
+            params: NetworkParams::new(Network::Bitcoin),
+            outpoint: OutPoint::null(),
+            max_input_index: 3,
+            num_headers: 3,
+            min_bit_security: 78,
+        };
+        assert!(covenant.witness(tx, &proof, &headers).is_err());
+
+        let covenant = SpentOutpointCovenant {
+            params: NetworkParams::new(Network::Bitcoin),
+            outpoint: tx.input[1].previous_output,
+            max_input_index: 3,
+            num_headers: 3,
+            min_bit_security: 78,
+        };
+        assert!(covenant.witness(tx, &proof, &headers[..2]).is_err());
+
+        // a transaction of 522 bytes without the witness is too large for `compute_txid_spending`
+        let tx = &block.txdata[54];
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+        let covenant = SpentOutpointCovenant {
+            params: NetworkParams::new(Network::Bitcoin),
+            outpoint: tx.input[0].previous_output,
+            max_input_index: 6,
+            num_headers: 3,
+            min_bit_security: 78,
+        };
+        assert!(covenant.witness(tx, &proof, &headers).is_err());
+    }
+}
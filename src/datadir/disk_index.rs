@@ -0,0 +1,217 @@
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::consensus::Decodable;
+use bitcoin::hashes::Hash;
+use bitcoin::BlockHash;
+
+/// The block data is in a `blk*.dat` file.
+pub const BLOCK_HAVE_DATA: u64 = 8;
+/// The undo data is in a `rev*.dat` file.
+pub const BLOCK_HAVE_UNDO: u64 = 16;
+/// The LevelDB key of the key that the other values of the database are obfuscated with.
+pub const OBFUSCATE_KEY_KEY: &[u8] = b"\x0e\x00obfuscate_key";
+
+/// An entry of the block index that Bitcoin Core keeps in `blocks/index`.
+///
+/// The index is a LevelDB database, which this crate does not read. The entries are stored
+/// under [`DiskBlockIndex::key`], and their values are decoded with [`DiskBlockIndex::decode`],
+/// or with [`DiskBlockIndex::decode_obfuscated`] as they are stored in the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiskBlockIndex {
+    pub height: u32,
+    pub status: u64,
+    pub num_txs: u64,
+    pub file: Option<u32>,
+    pub data_pos: Option<u64>,
+    pub undo_pos: Option<u64>,
+    pub header: Header,
+}
+
+impl DiskBlockIndex {
+    /// Return the LevelDB key of the entry for the block.
+    pub fn key(hash: &BlockHash) -> Vec<u8> {
+        let mut key = vec![b'b'];
+        key.extend_from_slice(hash.as_byte_array());
+        key
+    }
+
+    /// Return the key that the values of the database are obfuscated with, from the value stored
+    /// under [`OBFUSCATE_KEY_KEY`], which is not obfuscated itself.
+    pub fn parse_obfuscate_key(value: &[u8]) -> Result<Vec<u8>> {
+        match value.split_first() {
+            Some((len, key)) if *len as usize == key.len() => Ok(key.to_vec()),
+            _ => Err(Error::msg("The obfuscation key is malformed.")),
+        }
+    }
+
+    /// Decode an entry as stored in the database, where it is obfuscated with the key from
+    /// [`Self::parse_obfuscate_key`].
+    pub fn decode_obfuscated(value: &[u8], obfuscate_key: &[u8]) -> Result<Self> {
+        if obfuscate_key.is_empty() {
+            return Self::decode(value);
+        }
+        let value = value
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ obfuscate_key[i % obfuscate_key.len()])
+            .collect::<Vec<u8>>();
+        Self::decode(&value)
+    }
+
+    pub fn decode(value: &[u8]) -> Result<Self> {
+        let mut reader = value;
+
+        // the client version that wrote the entry
+        let _ = read_varint(&mut reader)?;
+
+        let height = read_varint(&mut reader)? as u32;
+        let status = read_varint(&mut reader)?;
+        let num_txs = read_varint(&mut reader)?;
+
+        let file = if status & (BLOCK_HAVE_DATA | BLOCK_HAVE_UNDO) != 0 {
+            Some(read_varint(&mut reader)? as u32)
+        } else {
+            None
+        };
+        let data_pos = if status & BLOCK_HAVE_DATA != 0 {
+            Some(read_varint(&mut reader)?)
+        } else {
+            None
+        };
+        let undo_pos = if status & BLOCK_HAVE_UNDO != 0 {
+            Some(read_varint(&mut reader)?)
+        } else {
+            None
+        };
+
+        let header = Header::consensus_decode(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(Error::msg("The block index entry has trailing bytes."));
+        }
+
+        Ok(Self {
+            height,
+            status,
+            num_txs,
+            file,
+            data_pos,
+            undo_pos,
+            header,
+        })
+    }
+}
+
+/// Read a variable-length integer in the format of Bitcoin Core's `VARINT`, which is different
+/// from the `CompactSize` used in transactions.
+pub fn read_varint(reader: &mut &[u8]) -> Result<u64> {
+    let mut n = 0u64;
+    loop {
+        let (byte, rest) = reader
+            .split_first()
+            .ok_or_else(|| Error::msg("The integer is truncated."))?;
+        *reader = rest;
+
+        if n > (u64::MAX >> 7) {
+            return Err(Error::msg("The integer is too large."));
+        }
+        n = (n << 7) | (byte & 0x7f) as u64;
+
+        if byte & 0x80 != 0 {
+            n = n
+                .checked_add(1)
+                .ok_or_else(|| Error::msg("The integer is too large."))?;
+        } else {
+            return Ok(n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::datadir::{read_varint, DiskBlockIndex, BLOCK_HAVE_DATA, BLOCK_HAVE_UNDO};
+    use bitcoin::block::Header;
+    use bitcoin::consensus::{Decodable, Encodable};
+
+    fn write_varint(mut n: u64) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            bytes.push((n & 0x7f) as u8 | if bytes.is_empty() { 0 } else { 0x80 });
+            if n <= 0x7f {
+                break;
+            }
+            n = (n >> 7) - 1;
+        }
+        bytes.reverse();
+        bytes
+    }
+
+    #[test]
+    fn test_varint() {
+        let vectors: [(u64, &str); 9] = [
+            (0, "00"),
+            (127, "7f"),
+            (128, "8000"),
+            (255, "807f"),
+            (256, "8100"),
+            (16383, "fe7f"),
+            (16384, "ff00"),
+            (65535, "82fe7f"),
+            (1 << 32, "8efefeff00"),
+        ];
+
+        for (n, expected) in vectors.iter() {
+            let bytes = hex::decode(expected).unwrap();
+            assert_eq!(write_varint(*n), bytes);
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), *n);
+        }
+
+        assert!(read_varint(&mut [0x80u8].as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_disk_block_index() {
+        let header_bytes = hex::decode("00c0232b218e0a0b7edc4abb2087cc813b7d867400c5b9c60b62000000000000000000007ecc6032126c1b6a17652495e28d7d973111764ace8a8219d67c0b00ff41ad299e424f66f05503172fe99011").unwrap();
+        let header = Header::consensus_decode(&mut header_bytes.as_slice()).unwrap();
+
+        let status = BLOCK_HAVE_DATA | BLOCK_HAVE_UNDO | 3;
+
+        let mut value = vec![];
+        value.extend(write_varint(270100));
+        value.extend(write_varint(845531));
+        value.extend(write_varint(status));
+        value.extend(write_varint(4321));
+        value.extend(write_varint(4012));
+        value.extend(write_varint(98765432));
+        value.extend(write_varint(1234567));
+        header.consensus_encode(&mut value).unwrap();
+
+        let entry = DiskBlockIndex::decode(&value).unwrap();
+        assert_eq!(entry.height, 845531);
+        assert_eq!(entry.status, status);
+        assert_eq!(entry.num_txs, 4321);
+        assert_eq!(entry.file, Some(4012));
+        assert_eq!(entry.data_pos, Some(98765432));
+        assert_eq!(entry.undo_pos, Some(1234567));
+        assert_eq!(entry.header, header);
+
+        // as stored in the database
+        let obfuscate_key =
+            DiskBlockIndex::parse_obfuscate_key(&hex::decode("08f1e2d3c4b5a69788").unwrap())
+                .unwrap();
+        let obfuscated = value
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ obfuscate_key[i % 8])
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            DiskBlockIndex::decode_obfuscated(&obfuscated, &obfuscate_key).unwrap(),
+            entry
+        );
+        assert!(DiskBlockIndex::decode(&obfuscated).is_err());
+        assert!(DiskBlockIndex::parse_obfuscate_key(&[8, 0, 0]).is_err());
+
+        let key = DiskBlockIndex::key(&header.block_hash());
+        assert_eq!(key.len(), 33);
+        assert_eq!(key[0], b'b');
+    }
+}
@@ -0,0 +1,477 @@
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::deserialize;
+use bitcoin::{Block, BlockHash, Network, Work};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+mod disk_index;
+pub use disk_index::*;
+
+/// The `blocks` directory of a Bitcoin Core data directory.
+pub struct BlocksDir {
+    pub path: PathBuf,
+    pub network: Network,
+    /// The key that the files are obfuscated with, which is all zeros if there is no `xor.dat`.
+    pub xor_key: [u8; 8],
+}
+
+impl BlocksDir {
+    pub fn open(path: impl AsRef<Path>, network: Network) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let xor_path = path.join("xor.dat");
+        let xor_key = if xor_path.exists() {
+            std::fs::read(&xor_path)?
+                .try_into()
+                .map_err(|_| Error::msg("The key in xor.dat must have 8 bytes."))?
+        } else {
+            [0u8; 8]
+        };
+
+        Ok(Self {
+            path,
+            network,
+            xor_key,
+        })
+    }
+
+    pub fn block_file_path(&self, file: u32) -> PathBuf {
+        self.path.join(format!("blk{:05}.dat", file))
+    }
+
+    /// Return the numbers of the `blk*.dat` files in the directory, in increasing order.
+    pub fn block_files(&self) -> Result<Vec<u32>> {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(&self.path)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if let Some(number) = name
+                .strip_prefix("blk")
+                .and_then(|x| x.strip_suffix(".dat"))
+            {
+                if let Ok(number) = number.parse::<u32>() {
+                    files.push(number);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn read_block_file(&self, file: u32) -> Result<BlockFile> {
+        let bytes = std::fs::read(self.block_file_path(file))?;
+        Ok(BlockFile::from_bytes(bytes, &self.xor_key, self.network))
+    }
+
+    /// Read the block at the given position, such as the one recorded in the block index.
+    ///
+    /// Only the record of the block is read from the file, not the whole file.
+    pub fn read_block(&self, file: u32, data_pos: u64) -> Result<Block> {
+        if data_pos < 8 {
+            return Err(Error::msg("The block position is out of the file."));
+        }
+
+        let mut fs = std::fs::File::open(self.block_file_path(file))?;
+        let prefix = self.read_at(&mut fs, data_pos - 8, 8)?;
+        if prefix[..4] != self.network.magic().to_bytes() {
+            return Err(Error::msg(format!(
+                "The record at position {} does not start with the network magic.",
+                data_pos - 8
+            )));
+        }
+
+        let size = u32::from_le_bytes(prefix[4..].try_into().unwrap());
+        let bytes = self
+            .read_at(&mut fs, data_pos, size as usize)
+            .map_err(|_| Error::msg("The block is truncated."))?;
+        Ok(deserialize(&bytes)?)
+    }
+
+    /// Read `len` bytes at the given position of a block file, deobfuscated with the key.
+    fn read_at(&self, fs: &mut std::fs::File, pos: u64, len: usize) -> Result<Vec<u8>> {
+        let mut bytes = vec![0u8; len];
+        fs.seek(SeekFrom::Start(pos))?;
+        fs.read_exact(&mut bytes)?;
+
+        if self.xor_key != [0u8; 8] {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte ^= self.xor_key[(pos as usize + i) % 8];
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Build an index of all the blocks in the directory by scanning their headers.
+    ///
+    /// The blocks are linked from the genesis block, and so this does not work with pruned
+    /// nodes. The index that Bitcoin Core keeps in `blocks/index` is a LevelDB database, which
+    /// this crate does not open, but whose entries can be decoded with [`DiskBlockIndex`] once
+    /// read by other means.
+    pub fn build_index(&self) -> Result<BlockIndex> {
+        let mut found = HashMap::new();
+        for file in self.block_files()? {
+            let block_file = self.read_block_file(file)?;
+            for (data_pos, _) in block_file.locations()? {
+                let header = block_file.read_header(data_pos)?;
+                found.insert(header.block_hash(), (header, file, data_pos));
+            }
+        }
+
+        BlockIndex::build(found, self.network)
+    }
+}
+
+/// A `blk*.dat` file, which consists of records of the network magic, the size of the block,
+/// and the block.
+pub struct BlockFile {
+    bytes: Vec<u8>,
+    network: Network,
+}
+
+impl BlockFile {
+    /// Take the content of the file, which is deobfuscated with the key.
+    pub fn from_bytes(mut bytes: Vec<u8>, xor_key: &[u8; 8], network: Network) -> Self {
+        if *xor_key != [0u8; 8] {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte ^= xor_key[i % 8];
+            }
+        }
+        Self { bytes, network }
+    }
+
+    /// Return the positions and the sizes of the blocks in the file.
+    pub fn locations(&self) -> Result<Vec<(u64, usize)>> {
+        let magic = self.network.magic().to_bytes();
+
+        let mut locations = vec![];
+        let mut pos = 0usize;
+        while pos + 8 <= self.bytes.len() {
+            // the rest of the file is preallocated but unused
+            if self.bytes[pos..pos + 4] == [0u8; 4] {
+                break;
+            }
+            if self.bytes[pos..pos + 4] != magic {
+                return Err(Error::msg(format!(
+                    "The record at position {} does not start with the network magic.",
+                    pos
+                )));
+            }
+
+            let size = u32::from_le_bytes(self.bytes[pos + 4..pos + 8].try_into().unwrap());
+            let data_pos = pos + 8;
+            if data_pos + size as usize > self.bytes.len() {
+                return Err(Error::msg(format!(
+                    "The block at position {} is truncated.",
+                    data_pos
+                )));
+            }
+
+            locations.push((data_pos as u64, size as usize));
+            pos = data_pos + size as usize;
+        }
+
+        Ok(locations)
+    }
+
+    fn block_bytes(&self, data_pos: u64) -> Result<&[u8]> {
+        let data_pos = data_pos as usize;
+        if data_pos < 8 || data_pos > self.bytes.len() {
+            return Err(Error::msg("The block position is out of the file."));
+        }
+
+        let size = u32::from_le_bytes(self.bytes[data_pos - 4..data_pos].try_into().unwrap());
+        self.bytes
+            .get(data_pos..data_pos + size as usize)
+            .ok_or_else(|| Error::msg("The block is truncated."))
+    }
+
+    pub fn read_block(&self, data_pos: u64) -> Result<Block> {
+        Ok(deserialize(self.block_bytes(data_pos)?)?)
+    }
+
+    pub fn read_header(&self, data_pos: u64) -> Result<Header> {
+        let bytes = self.block_bytes(data_pos)?;
+        if bytes.len() < 80 {
+            return Err(Error::msg("The block is shorter than a header."));
+        }
+        Ok(deserialize(&bytes[..80])?)
+    }
+
+    pub fn blocks(&self) -> Result<Vec<Block>> {
+        self.locations()?
+            .iter()
+            .map(|(data_pos, _)| self.read_block(*data_pos))
+            .collect()
+    }
+}
+
+/// An entry of [`BlockIndex`].
+#[derive(Clone, Debug)]
+pub struct BlockIndexEntry {
+    pub header: Header,
+    pub height: u32,
+    pub chain_work: Work,
+    pub file: u32,
+    pub data_pos: u64,
+}
+
+/// An index of the blocks found in the block files, with the chain of the most work.
+pub struct BlockIndex {
+    pub entries: HashMap<BlockHash, BlockIndexEntry>,
+    pub best_chain: Vec<BlockHash>,
+}
+
+impl BlockIndex {
+    fn build(found: HashMap<BlockHash, (Header, u32, u64)>, network: Network) -> Result<Self> {
+        let genesis_hash = genesis_block(network).block_hash();
+        let (genesis_header, genesis_file, genesis_pos) = found
+            .get(&genesis_hash)
+            .ok_or_else(|| Error::msg("The genesis block is not in the block files."))?;
+
+        let mut children = HashMap::<BlockHash, Vec<BlockHash>>::new();
+        for (hash, (header, _, _)) in found.iter() {
+            children
+                .entry(header.prev_blockhash)
+                .or_default()
+                .push(*hash);
+        }
+
+        let mut entries = HashMap::new();
+        entries.insert(
+            genesis_hash,
+            BlockIndexEntry {
+                header: *genesis_header,
+                height: 0,
+                chain_work: genesis_header.work(),
+                file: *genesis_file,
+                data_pos: *genesis_pos,
+            },
+        );
+
+        let mut best = genesis_hash;
+        let mut queue = vec![genesis_hash];
+        while let Some(parent) = queue.pop() {
+            let (parent_height, parent_work) = {
+                let entry = &entries[&parent];
+                (entry.height, entry.chain_work)
+            };
+
+            for child in children.get(&parent).into_iter().flatten() {
+                let (header, file, data_pos) = found[child];
+                let entry = BlockIndexEntry {
+                    header,
+                    height: parent_height + 1,
+                    chain_work: parent_work + header.work(),
+                    file,
+                    data_pos,
+                };
+                if entry.chain_work > entries[&best].chain_work {
+                    best = *child;
+                }
+                entries.insert(*child, entry);
+                queue.push(*child);
+            }
+        }
+
+        let mut best_chain = vec![best];
+        while best_chain.last() != Some(&genesis_hash) {
+            let prev = entries[best_chain.last().unwrap()].header.prev_blockhash;
+            best_chain.push(prev);
+        }
+        best_chain.reverse();
+
+        Ok(Self {
+            entries,
+            best_chain,
+        })
+    }
+
+    pub fn get(&self, hash: &BlockHash) -> Option<&BlockIndexEntry> {
+        self.entries.get(hash)
+    }
+
+    pub fn tip(&self) -> &BlockIndexEntry {
+        &self.entries[self.best_chain.last().unwrap()]
+    }
+
+    /// Return the entry at the given height of the best chain.
+    pub fn at_height(&self, height: u32) -> Option<&BlockIndexEntry> {
+        self.best_chain
+            .get(height as usize)
+            .map(|hash| &self.entries[hash])
+    }
+
+    /// Return the (up to) `n` headers that follow the given block in the best chain.
+    pub fn headers_after(&self, hash: &BlockHash, n: usize) -> Result<Vec<Header>> {
+        let entry = self
+            .get(hash)
+            .ok_or_else(|| Error::msg("The block is not in the index."))?;
+        if self.best_chain[entry.height as usize] != *hash {
+            return Err(Error::msg("The block is not in the best chain."));
+        }
+
+        Ok(self
+            .best_chain
+            .iter()
+            .skip(entry.height as usize + 1)
+            .take(n)
+            .map(|hash| self.entries[hash].header)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::datadir::BlocksDir;
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::{block_845797, mine_block};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::serialize;
+    use bitcoin::{Block, Network};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use std::path::{Path, PathBuf};
+
+    fn temp_blocks_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "reuse-bitcoin-pow-gadgets-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn write_block_file(path: &Path, blocks: &[Block], network: Network, xor_key: &[u8; 8]) {
+        let mut bytes = vec![];
+        for block in blocks.iter() {
+            let encoded = serialize(block);
+            bytes.extend_from_slice(&network.magic().to_bytes());
+            bytes.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&encoded);
+        }
+        // preallocated space
+        bytes.extend_from_slice(&[0u8; 100]);
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte ^= xor_key[i % 8];
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_block_file() {
+        let block = block_845797();
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let xor_key: [u8; 8] = prng.gen();
+
+        let path = temp_blocks_dir("block-file");
+        std::fs::write(path.join("xor.dat"), xor_key).unwrap();
+        write_block_file(
+            &path.join("blk00000.dat"),
+            std::slice::from_ref(&block),
+            Network::Bitcoin,
+            &xor_key,
+        );
+
+        let blocks_dir = BlocksDir::open(&path, Network::Bitcoin).unwrap();
+        assert_eq!(blocks_dir.xor_key, xor_key);
+        assert_eq!(blocks_dir.block_files().unwrap(), vec![0]);
+
+        let block_file = blocks_dir.read_block_file(0).unwrap();
+        let locations = block_file.locations().unwrap();
+        assert_eq!(locations, vec![(8, serialize(&block).len())]);
+
+        let read_block = blocks_dir.read_block(0, 8).unwrap();
+        assert_eq!(read_block.block_hash(), block.block_hash());
+        assert_eq!(read_block.txdata.len(), block.txdata.len());
+
+        // not the position of a block
+        assert!(blocks_dir.read_block(0, 4).is_err());
+        assert!(blocks_dir.read_block(0, 9).is_err());
+
+        let tx = &read_block.txdata[100];
+        let proof =
+            TxInclusionProof::construct_from_block(&read_block, &tx.compute_txid()).unwrap();
+        proof
+            .verify_tx_inclusion(tx, &read_block.header.merkle_root)
+            .unwrap();
+
+        // the genesis block is missing
+        assert!(blocks_dir.build_index().is_err());
+
+        // the wrong network
+        let blocks_dir = BlocksDir::open(&path, Network::Testnet).unwrap();
+        assert!(blocks_dir.read_block_file(0).unwrap().locations().is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_block_index() {
+        let genesis = genesis_block(Network::Regtest);
+        let block_1 = mine_block(&genesis.header, 0, genesis.txdata.clone());
+        let block_2 = mine_block(&block_1.header, 0, block_1.txdata.clone());
+        let block_3 = mine_block(&block_2.header, 0, block_2.txdata.clone());
+        let fork_1 = mine_block(&genesis.header, 1, genesis.txdata.clone());
+        let fork_2 = mine_block(&fork_1.header, 1, fork_1.txdata.clone());
+
+        let path = temp_blocks_dir("block-index");
+        write_block_file(
+            &path.join("blk00000.dat"),
+            &[genesis.clone(), block_2.clone(), fork_1.clone()],
+            Network::Regtest,
+            &[0u8; 8],
+        );
+        write_block_file(
+            &path.join("blk00001.dat"),
+            &[block_3.clone(), fork_2.clone(), block_1.clone()],
+            Network::Regtest,
+            &[0u8; 8],
+        );
+
+        let blocks_dir = BlocksDir::open(&path, Network::Regtest).unwrap();
+        let index = blocks_dir.build_index().unwrap();
+
+        assert_eq!(index.entries.len(), 6);
+        assert_eq!(index.tip().height, 3);
+        assert_eq!(index.tip().header, block_3.header);
+        assert_eq!(
+            index.best_chain,
+            vec![
+                genesis.block_hash(),
+                block_1.block_hash(),
+                block_2.block_hash(),
+                block_3.block_hash()
+            ]
+        );
+        assert_eq!(index.get(&fork_2.block_hash()).unwrap().height, 2);
+
+        let entry = index.at_height(1).unwrap();
+        assert_eq!(entry.file, 1);
+        assert_eq!(
+            blocks_dir
+                .read_block(entry.file, entry.data_pos)
+                .unwrap()
+                .block_hash(),
+            block_1.block_hash()
+        );
+
+        assert_eq!(
+            index.headers_after(&genesis.block_hash(), 2).unwrap(),
+            vec![block_1.header, block_2.header]
+        );
+        assert_eq!(
+            index.headers_after(&block_2.block_hash(), 5).unwrap(),
+            vec![block_3.header]
+        );
+        assert!(index.headers_after(&fork_1.block_hash(), 1).is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::BlockHash;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// A chain of headers starting from a checkpoint, indexed by height and hash and persisted in a
+/// flat file.
+///
+/// The file consists of the height of the first header (4 bytes, little-endian) followed by the
+/// 80-byte headers in order.
+pub struct HeaderStore {
+    file: File,
+    start_height: u32,
+    headers: Vec<Header>,
+    hashes: Vec<BlockHash>,
+    heights: HashMap<BlockHash, u32>,
+}
+
+impl HeaderStore {
+    /// Create a new store at the path, starting from the given header at the given height.
+    pub fn create(path: impl AsRef<Path>, start_height: u32, start: Header) -> Result<Self> {
+        let mut file = File::options()
+            .create_new(true)
+            .append(true)
+            .open(path.as_ref())?;
+        file.write_all(&start_height.to_le_bytes())?;
+        file.write_all(&serialize(&start))?;
+        file.sync_data()?;
+
+        let hash = start.block_hash();
+        Ok(Self {
+            file,
+            start_height,
+            headers: vec![start],
+            hashes: vec![hash],
+            heights: HashMap::from([(hash, start_height)]),
+        })
+    }
+
+    /// Open an existing store, checking that the headers are linked.
+    ///
+    /// A partially written header at the end of the file, such as after a crash, is discarded.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path.as_ref())?;
+        if bytes.len() < 4 + 80 {
+            return Err(Error::msg("The header store is too short."));
+        }
+
+        let start_height = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let num_headers = (bytes.len() - 4) / 80;
+
+        let file = File::options().append(true).open(path.as_ref())?;
+        if 4 + num_headers * 80 != bytes.len() {
+            file.set_len((4 + num_headers * 80) as u64)?;
+        }
+
+        let start: Header = deserialize(&bytes[4..84])?;
+        let hash = start.block_hash();
+        let mut store = Self {
+            file,
+            start_height,
+            headers: vec![start],
+            hashes: vec![hash],
+            heights: HashMap::from([(hash, start_height)]),
+        };
+
+        for i in 1..num_headers {
+            let header: Header = deserialize(&bytes[4 + i * 80..4 + (i + 1) * 80])?;
+            store.check(&header)?;
+            store.insert(header);
+        }
+
+        Ok(store)
+    }
+
+    fn check(&self, header: &Header) -> Result<()> {
+        if header.prev_blockhash != *self.hashes.last().unwrap() {
+            return Err(Error::msg(format!(
+                "The header at height {} does not extend the tip.",
+                self.tip_height() + 1
+            )));
+        }
+        if header.validate_pow(header.target()).is_err() {
+            return Err(Error::msg(format!(
+                "The header at height {} does not meet its target.",
+                self.tip_height() + 1
+            )));
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, header: Header) {
+        let hash = header.block_hash();
+        self.heights.insert(hash, self.tip_height() + 1);
+        self.headers.push(header);
+        self.hashes.push(hash);
+    }
+
+    /// Append a header, which must extend the tip and meet the target in its `bits`.
+    pub fn append(&mut self, header: Header) -> Result<()> {
+        self.check(&header)?;
+        self.file.write_all(&serialize(&header))?;
+        self.file.sync_data()?;
+        self.insert(header);
+        Ok(())
+    }
+
+    pub fn append_all(&mut self, headers: &[Header]) -> Result<()> {
+        for header in headers.iter() {
+            self.append(*header)?;
+        }
+        Ok(())
+    }
+
+    pub fn start_height(&self) -> u32 {
+        self.start_height
+    }
+
+    pub fn tip_height(&self) -> u32 {
+        self.start_height + self.headers.len() as u32 - 1
+    }
+
+    pub fn tip(&self) -> &Header {
+        self.headers.last().unwrap()
+    }
+
+    pub fn tip_hash(&self) -> BlockHash {
+        *self.hashes.last().unwrap()
+    }
+
+    pub fn get_by_height(&self, height: u32) -> Option<&Header> {
+        self.headers
+            .get(height.checked_sub(self.start_height)? as usize)
+    }
+
+    pub fn get_by_hash(&self, hash: &BlockHash) -> Option<&Header> {
+        self.get_by_height(self.height_of(hash)?)
+    }
+
+    pub fn height_of(&self, hash: &BlockHash) -> Option<u32> {
+        self.heights.get(hash).copied()
+    }
+
+    /// Return the (up to) `n` headers that follow the given block, which are what a witness
+    /// needs after the block that includes a transaction.
+    pub fn headers_after(&self, hash: &BlockHash, n: usize) -> Result<Vec<Header>> {
+        let height = self
+            .height_of(hash)
+            .ok_or_else(|| Error::msg("The block is not in the header store."))?;
+
+        let start = (height - self.start_height) as usize + 1;
+        Ok(self.headers.iter().skip(start).take(n).copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::header_store::HeaderStore;
+    use crate::test_utils::{mine, mine_chain};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Network, TxMerkleNode};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "reuse-bitcoin-pow-gadgets-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_header_store() {
+        let path = temp_path("header-store");
+
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut headers = vec![genesis];
+        headers.extend(mine_chain(&genesis, 10, 0));
+
+        let mut store = HeaderStore::create(&path, 0, genesis).unwrap();
+        store.append_all(&headers[1..6]).unwrap();
+        assert_eq!(store.tip_height(), 5);
+
+        // not linked to the tip
+        assert!(store.append(headers[7]).is_err());
+
+        // not meeting the target
+        let mut invalid = mine(store.tip(), 0, TxMerkleNode::all_zeros());
+        while invalid.validate_pow(invalid.target()).is_ok() {
+            invalid.nonce += 1;
+        }
+        assert!(store.append(invalid).is_err());
+        drop(store);
+
+        // a partial header at the end of the file
+        let mut file = std::fs::File::options().append(true).open(&path).unwrap();
+        file.write_all(&[0u8; 30]).unwrap();
+        drop(file);
+
+        let mut store = HeaderStore::open(&path).unwrap();
+        assert_eq!(store.tip_height(), 5);
+        store.append_all(&headers[6..]).unwrap();
+        drop(store);
+
+        let store = HeaderStore::open(&path).unwrap();
+        assert_eq!(store.tip_height(), 10);
+        assert_eq!(store.tip_hash(), headers[10].block_hash());
+        assert_eq!(store.get_by_height(3), Some(&headers[3]));
+        assert_eq!(
+            store.get_by_hash(&headers[4].block_hash()),
+            Some(&headers[4])
+        );
+        assert_eq!(store.height_of(&headers[9].block_hash()), Some(9));
+        assert_eq!(
+            store.headers_after(&headers[2].block_hash(), 3).unwrap(),
+            headers[3..6].to_vec()
+        );
+        assert_eq!(
+            store.headers_after(&headers[8].block_hash(), 5).unwrap(),
+            headers[9..].to_vec()
+        );
+        assert!(store
+            .headers_after(&genesis_block(Network::Bitcoin).block_hash(), 1)
+            .is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let path = temp_path("header-store-checkpoint");
+
+        let checkpoint = mine(
+            &genesis_block(Network::Regtest).header,
+            0,
+            TxMerkleNode::all_zeros(),
+        );
+        let next = mine(&checkpoint, 0, TxMerkleNode::all_zeros());
+
+        let mut store = HeaderStore::create(&path, 1000, checkpoint).unwrap();
+        store.append(next).unwrap();
+        assert!(HeaderStore::create(&path, 1000, checkpoint).is_err());
+        drop(store);
+
+        let store = HeaderStore::open(&path).unwrap();
+        assert_eq!(store.start_height(), 1000);
+        assert_eq!(store.tip_height(), 1001);
+        assert_eq!(store.get_by_height(999), None);
+        assert_eq!(store.get_by_height(1001), Some(&next));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use crate::treepp::*;
+use crate::utils::pull_hint_from_bottom;
+use bitcoin::script::Instruction;
+
+/// Where a gadget reads its hints from.
+///
+/// Gadgets pull their hints one by one, and the scripts pushing the hints (such as
+/// `BlockHashGadget::push_bit_security_hint`) push them in the same order. [`HintSource::push`]
+/// rearranges these pushes for the source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HintSource {
+    /// The hints sit at the bottom of the main stack, above `offset` unrelated elements, and the
+    /// next hint is the lowest one. With a zero offset, this is what `OP_HINT` does.
+    Bottom { offset: usize },
+    /// The hints sit on the altstack, and the next hint is the topmost one.
+    AltStack,
+    /// The hints sit on the main stack right below the elements that the gadget works on, and
+    /// the next hint is the topmost one.
+    Inline,
+}
+
+impl Default for HintSource {
+    fn default() -> Self {
+        HintSource::Bottom { offset: 0 }
+    }
+}
+
+impl HintSource {
+    /// Pull the next hint onto the top of the main stack.
+    ///
+    /// `main_above` is the number of elements that the gadget keeps on the main stack at this
+    /// point (including its inputs), and `alt_above` is the number that it keeps on the altstack.
+    pub fn pull(&self, main_above: usize, alt_above: usize) -> Script {
+        match self {
+            HintSource::Bottom { offset } => pull_hint_from_bottom(*offset),
+            HintSource::AltStack => script! {
+                for _ in 0..alt_above {
+                    OP_FROMALTSTACK
+                }
+                OP_FROMALTSTACK
+                for _ in 0..alt_above {
+                    OP_SWAP OP_TOALTSTACK
+                }
+            },
+            HintSource::Inline => script! {
+                if main_above > 0 {
+                    { main_above } OP_ROLL
+                }
+            },
+        }
+    }
+
+    /// Rearrange the pushes of hints, given in the order they are pulled, for this source.
+    ///
+    /// For [`HintSource::AltStack`], the result moves the hints to the altstack and therefore
+    /// needs to run as part of the script rather than the witness.
+    pub fn push(&self, hints: Script) -> Script {
+        match self {
+            HintSource::Bottom { .. } => hints,
+            HintSource::AltStack => {
+                let num_hints = hints.instructions().count();
+                script! {
+                    { hints }
+                    for _ in 0..num_hints {
+                        OP_TOALTSTACK
+                    }
+                }
+            }
+            HintSource::Inline => {
+                let mut instructions = hints
+                    .instructions()
+                    .collect::<Result<Vec<Instruction>, _>>()
+                    .unwrap();
+                instructions.reverse();
+
+                let mut script = Script::new();
+                for instruction in instructions {
+                    match instruction {
+                        Instruction::PushBytes(bytes) => script.push_slice(bytes),
+                        Instruction::Op(opcode) => script.push_opcode(opcode),
+                    }
+                }
+                script
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hint::HintSource;
+    use crate::treepp::*;
+    use crate::utils::{push_u32, push_u32_to_u16_limbs_hint, u32_to_u16_limbs};
+
+    #[test]
+    fn test_hint_sources() {
+        let v = 0x8badf00du32;
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            let script = script! {
+                { b"unrelated".to_vec() }
+                { hint.push(push_u32_to_u16_limbs_hint(v)) }
+                { push_u32(v) }
+                { u32_to_u16_limbs(&hint) }
+                { (v >> 16) as usize } OP_EQUALVERIFY
+                { (v & 0xffff) as usize } OP_EQUALVERIFY
+                { b"unrelated".to_vec() } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+}
@@ -11,8 +11,38 @@ pub mod structures;
 
 pub mod spv;
 
+pub mod auxpow;
+
+pub mod tx;
+
+pub mod covenant;
+
+pub mod pow;
+
+pub mod sha256;
+
+pub mod hint;
+
+pub mod analysis;
+
+pub mod datadir;
+
+pub mod header_store;
+
+pub mod light_client;
+
+pub mod network;
+
+pub mod signet;
+
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
 pub mod utils;
 
+#[cfg(test)]
+mod test_utils;
+
 #[macro_export]
 macro_rules! consensus_encode {
     ($x: expr) => {{
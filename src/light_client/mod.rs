@@ -0,0 +1,509 @@
+use crate::network::NetworkParams;
+use crate::spv::TxInclusionProof;
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::hashes::Hash;
+use bitcoin::{BlockHash, TxMerkleNode, Txid, Work};
+use std::collections::HashMap;
+
+/// An entry of [`LightClient`].
+#[derive(Clone, Debug)]
+pub struct LightClientEntry {
+    pub header: Header,
+    pub height: u32,
+    /// The work of the chain from the checkpoint to this block, both included.
+    pub chain_work: Work,
+}
+
+/// How the best chain changed after accepting headers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TipChange {
+    /// The height of the last block that the old and the new best chains have in common.
+    pub fork_height: u32,
+    /// The blocks that left the best chain, from the lowest.
+    pub disconnected: Vec<BlockHash>,
+    /// The blocks that joined the best chain, from the lowest.
+    pub connected: Vec<BlockHash>,
+}
+
+impl TipChange {
+    pub fn is_reorg(&self) -> bool {
+        !self.disconnected.is_empty()
+    }
+}
+
+/// The status of a watched transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TxStatus {
+    /// The block that includes the transaction is in the best chain, and there are
+    /// `depth - 1` blocks after it.
+    Confirmed { height: u32, depth: u32 },
+    /// The block that includes the transaction is not, or no longer, in the best chain.
+    NotInBestChain,
+}
+
+struct WatchedTx {
+    block_hash: BlockHash,
+    proof: TxInclusionProof,
+}
+
+/// A header-only light client, which syncs headers from a checkpoint, follows the chain of the
+/// most work across reorgs, and tracks the confirmation depth of transactions with inclusion
+/// proofs, such as to decide when the headers for a covenant witness are available.
+///
+/// Each header must follow the consensus rules of the network: its target must be within the
+/// proof-of-work limit and met by its hash, and its bits must follow the retarget rules whenever
+/// the headers since the checkpoint are enough to tell. The work of a fork is only compared from
+/// the checkpoint.
+pub struct LightClient {
+    params: NetworkParams,
+    checkpoint_height: u32,
+    entries: HashMap<BlockHash, LightClientEntry>,
+    best_chain: Vec<BlockHash>,
+    watched: HashMap<Txid, WatchedTx>,
+}
+
+impl LightClient {
+    /// Start from the given header at the given height, which is trusted, and check the headers
+    /// after it against the consensus rules of the network.
+    pub fn new(params: NetworkParams, checkpoint_height: u32, checkpoint: Header) -> Self {
+        let hash = checkpoint.block_hash();
+        Self {
+            params,
+            checkpoint_height,
+            entries: HashMap::from([(
+                hash,
+                LightClientEntry {
+                    header: checkpoint,
+                    height: checkpoint_height,
+                    chain_work: checkpoint.work(),
+                },
+            )]),
+            best_chain: vec![hash],
+            watched: HashMap::new(),
+        }
+    }
+
+    /// Accept headers from a peer, such as the response to a `getheaders` message with
+    /// [`Self::locator`], and switch to the chain of the most work.
+    ///
+    /// Each header must extend a known block or an earlier header in `headers` and meet its
+    /// target. Headers that are already known are skipped. If any header is invalid, none of
+    /// them is accepted.
+    ///
+    /// Return how the best chain changed, if it did. A chain with the same work as the best
+    /// chain does not replace it.
+    pub fn accept_headers(&mut self, headers: &[Header]) -> Result<Option<TipChange>> {
+        let mut new_entries = HashMap::<BlockHash, LightClientEntry>::new();
+        let mut order = vec![];
+
+        for header in headers.iter() {
+            let hash = header.block_hash();
+            if self.entries.contains_key(&hash) || new_entries.contains_key(&hash) {
+                continue;
+            }
+
+            let parent = self
+                .entries
+                .get(&header.prev_blockhash)
+                .or_else(|| new_entries.get(&header.prev_blockhash))
+                .ok_or_else(|| {
+                    Error::msg(format!(
+                        "The header {} does not extend a known block.",
+                        hash
+                    ))
+                })?;
+            self.params
+                .check_header(parent.height + 1, header, |height| {
+                    self.ancestor(&header.prev_blockhash, height, &new_entries)
+                })?;
+
+            let entry = LightClientEntry {
+                header: *header,
+                height: parent.height + 1,
+                chain_work: parent.chain_work + header.work(),
+            };
+            new_entries.insert(hash, entry);
+            order.push(hash);
+        }
+
+        let mut best = self.tip_hash();
+        let mut best_work = self.tip().chain_work;
+        for hash in order.iter() {
+            if new_entries[hash].chain_work > best_work {
+                best = *hash;
+                best_work = new_entries[hash].chain_work;
+            }
+        }
+        self.entries.extend(new_entries);
+
+        if best == self.tip_hash() {
+            return Ok(None);
+        }
+
+        let mut connected = vec![];
+        let mut cur = best;
+        while !self.is_in_best_chain(&cur) {
+            connected.push(cur);
+            cur = self.entries[&cur].header.prev_blockhash;
+        }
+        connected.reverse();
+
+        let fork_height = self.entries[&cur].height;
+        let disconnected = self
+            .best_chain
+            .split_off((fork_height - self.checkpoint_height) as usize + 1);
+        self.best_chain.extend(connected.iter().copied());
+
+        Ok(Some(TipChange {
+            fork_height,
+            disconnected,
+            connected,
+        }))
+    }
+
+    /// Return the header at the given height in the chain that ends with the given block.
+    fn ancestor(
+        &self,
+        hash: &BlockHash,
+        height: u32,
+        new_entries: &HashMap<BlockHash, LightClientEntry>,
+    ) -> Option<Header> {
+        let mut cur = *hash;
+        loop {
+            let entry = self.entries.get(&cur).or_else(|| new_entries.get(&cur))?;
+            if entry.height <= height {
+                return (entry.height == height).then_some(entry.header);
+            }
+            cur = entry.header.prev_blockhash;
+        }
+    }
+
+    /// The block locator of the best chain for a `getheaders` message: the tip, then blocks
+    /// further and further back, ending with the checkpoint.
+    pub fn locator(&self) -> Vec<BlockHash> {
+        let mut locator = vec![];
+        let mut idx = self.best_chain.len() - 1;
+        let mut step = 1;
+        while idx > 0 {
+            locator.push(self.best_chain[idx]);
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            idx = idx.saturating_sub(step);
+        }
+        locator.push(self.best_chain[0]);
+        locator
+    }
+
+    pub fn checkpoint_height(&self) -> u32 {
+        self.checkpoint_height
+    }
+
+    pub fn tip(&self) -> &LightClientEntry {
+        &self.entries[self.best_chain.last().unwrap()]
+    }
+
+    pub fn tip_height(&self) -> u32 {
+        self.tip().height
+    }
+
+    pub fn tip_hash(&self) -> BlockHash {
+        *self.best_chain.last().unwrap()
+    }
+
+    /// Return the entry of any known block, in the best chain or not.
+    pub fn get(&self, hash: &BlockHash) -> Option<&LightClientEntry> {
+        self.entries.get(hash)
+    }
+
+    /// Return the entry at the given height of the best chain.
+    pub fn at_height(&self, height: u32) -> Option<&LightClientEntry> {
+        self.best_chain
+            .get(height.checked_sub(self.checkpoint_height)? as usize)
+            .map(|hash| &self.entries[hash])
+    }
+
+    pub fn is_in_best_chain(&self, hash: &BlockHash) -> bool {
+        self.entries.get(hash).is_some_and(|entry| {
+            self.best_chain
+                .get((entry.height - self.checkpoint_height) as usize)
+                == Some(hash)
+        })
+    }
+
+    /// Watch a transaction that the given block includes, checking the inclusion proof against
+    /// the merkle root of the block.
+    pub fn watch_tx(
+        &mut self,
+        txid: Txid,
+        block_hash: BlockHash,
+        proof: TxInclusionProof,
+    ) -> Result<()> {
+        let entry = self
+            .get(&block_hash)
+            .ok_or_else(|| Error::msg("The block is not known to the light client."))?;
+        proof.verify_hash_inclusion(
+            &TxMerkleNode::from_byte_array(txid.to_byte_array()),
+            &entry.header.merkle_root,
+        )?;
+
+        self.watched.insert(txid, WatchedTx { block_hash, proof });
+        Ok(())
+    }
+
+    pub fn unwatch_tx(&mut self, txid: &Txid) {
+        self.watched.remove(txid);
+    }
+
+    /// Return the stored inclusion proof of a watched transaction and the block that includes it.
+    pub fn get_watched_tx(&self, txid: &Txid) -> Option<(&BlockHash, &TxInclusionProof)> {
+        self.watched
+            .get(txid)
+            .map(|watched| (&watched.block_hash, &watched.proof))
+    }
+
+    /// Return the status of a watched transaction.
+    pub fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        let watched = self
+            .watched
+            .get(txid)
+            .ok_or_else(|| Error::msg("The transaction is not watched."))?;
+
+        if !self.is_in_best_chain(&watched.block_hash) {
+            return Ok(TxStatus::NotInBestChain);
+        }
+        let height = self.entries[&watched.block_hash].height;
+        Ok(TxStatus::Confirmed {
+            height,
+            depth: self.tip_height() - height + 1,
+        })
+    }
+
+    /// Return the `num_headers` headers of the best chain starting from the block that includes
+    /// a watched transaction, which a covenant witness needs, or `None` if the transaction does
+    /// not have enough confirmations yet.
+    pub fn witness_headers(&self, txid: &Txid, num_headers: usize) -> Result<Option<Vec<Header>>> {
+        match self.tx_status(txid)? {
+            TxStatus::Confirmed { height, depth } if depth as usize >= num_headers => Ok(Some(
+                (0..num_headers as u32)
+                    .map(|i| self.at_height(height + i).unwrap().header)
+                    .collect(),
+            )),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::light_client::{LightClient, TipChange, TxStatus};
+    use crate::network::NetworkParams;
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::{block_845797, mine, mine_chain};
+    use bitcoin::block::Header;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::Decodable;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{CompactTarget, Network, TxMerkleNode, Txid};
+
+    #[test]
+    fn test_light_client_mainnet() {
+        let block = block_845797();
+
+        let mut headers = vec![];
+        for header in [
+            "00000028429f8ccc5a6349852c559f0df3dbb26f2d0a569595c2010000000000000000007ef4fd2b9a9520fba80a2d14f8b46d9878508489be73c03119555ac3b6c7673080a35866f055031778e193c2",
+            "000000266a8e17a3277e4f686ca9a94a4fa55b3e71bfdf67423202000000000000000000e61ba13c3fdd44bb5d0460be891f86c083ada120103eb771bc8db4996368e0ceffa85866f055031765f48dc8",
+        ] {
+            let bytes = hex::decode(header).unwrap();
+            headers.push(Header::consensus_decode(&mut bytes.as_slice()).unwrap());
+        }
+
+        let mut client =
+            LightClient::new(NetworkParams::new(Network::Bitcoin), 845797, block.header);
+
+        let txid = block.txdata[541].compute_txid();
+        let proof = TxInclusionProof::construct_from_block(&block, &txid).unwrap();
+        assert!(client
+            .watch_tx(block.txdata[540].compute_txid(), block.block_hash(), proof)
+            .is_err());
+
+        let proof = TxInclusionProof::construct_from_block(&block, &txid).unwrap();
+        client.watch_tx(txid, block.block_hash(), proof).unwrap();
+        assert_eq!(
+            client.tx_status(&txid).unwrap(),
+            TxStatus::Confirmed {
+                height: 845797,
+                depth: 1
+            }
+        );
+        assert_eq!(client.witness_headers(&txid, 3).unwrap(), None);
+
+        let change = client.accept_headers(&headers).unwrap().unwrap();
+        assert!(!change.is_reorg());
+        assert_eq!(client.tip_height(), 845799);
+        assert_eq!(
+            client.tx_status(&txid).unwrap(),
+            TxStatus::Confirmed {
+                height: 845797,
+                depth: 3
+            }
+        );
+        assert_eq!(
+            client.witness_headers(&txid, 3).unwrap(),
+            Some(vec![block.header, headers[0], headers[1]])
+        );
+
+        assert!(client.tx_status(&block.txdata[540].compute_txid()).is_err());
+    }
+
+    #[test]
+    fn test_light_client_reorg() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut client = LightClient::new(NetworkParams::new(Network::Regtest), 0, genesis);
+
+        let main = mine_chain(&genesis, 5, 0);
+        let change = client.accept_headers(&main[..3]).unwrap().unwrap();
+        assert_eq!(
+            change,
+            TipChange {
+                fork_height: 0,
+                disconnected: vec![],
+                connected: main[..3].iter().map(|h| h.block_hash()).collect(),
+            }
+        );
+
+        // headers that are already known, and headers out of order
+        assert_eq!(client.accept_headers(&main[..3]).unwrap(), None);
+        assert!(client.accept_headers(&main[4..]).is_err());
+        assert_eq!(client.tip_height(), 3);
+        client.accept_headers(&main[3..]).unwrap().unwrap();
+        assert_eq!(client.tip_hash(), main[4].block_hash());
+
+        // a transaction in the block at height 2
+        let txid = Txid::from_byte_array([7u8; 32]);
+        let block = mine(
+            &main[0],
+            1,
+            TxMerkleNode::from_byte_array(txid.to_byte_array()),
+        );
+        let fork = [vec![block], mine_chain(&block, 4, 1)].concat();
+
+        // a fork with less work does not change the best chain
+        assert_eq!(client.accept_headers(&fork[..3]).unwrap(), None);
+        assert!(!client.is_in_best_chain(&block.block_hash()));
+        assert!(client.get(&block.block_hash()).is_some());
+
+        let proof = TxInclusionProof {
+            idx: 0,
+            siblings: vec![],
+        };
+        client.watch_tx(txid, block.block_hash(), proof).unwrap();
+        assert_eq!(client.tx_status(&txid).unwrap(), TxStatus::NotInBestChain);
+        assert_eq!(client.witness_headers(&txid, 2).unwrap(), None);
+
+        // a fork with the same work does not either
+        assert_eq!(client.accept_headers(&fork[3..4]).unwrap(), None);
+
+        // a fork with more work does
+        let change = client.accept_headers(&fork[4..]).unwrap().unwrap();
+        assert!(change.is_reorg());
+        assert_eq!(change.fork_height, 1);
+        assert_eq!(
+            change.disconnected,
+            main[1..].iter().map(|h| h.block_hash()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            change.connected,
+            fork.iter().map(|h| h.block_hash()).collect::<Vec<_>>()
+        );
+        assert_eq!(client.tip_height(), 6);
+        assert_eq!(
+            client.tx_status(&txid).unwrap(),
+            TxStatus::Confirmed {
+                height: 2,
+                depth: 5
+            }
+        );
+        assert_eq!(
+            client.witness_headers(&txid, 2).unwrap(),
+            Some(fork[..2].to_vec())
+        );
+        assert_eq!(client.at_height(1).unwrap().header, main[0]);
+
+        // the old chain takes over again
+        let change = client
+            .accept_headers(&mine_chain(&main[4], 2, 0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.fork_height, 1);
+        assert_eq!(change.disconnected.len(), 5);
+        assert_eq!(client.tip_height(), 7);
+        assert_eq!(client.tx_status(&txid).unwrap(), TxStatus::NotInBestChain);
+    }
+
+    #[test]
+    fn test_light_client_invalid_headers() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut client = LightClient::new(NetworkParams::new(Network::Regtest), 0, genesis);
+
+        let headers = mine_chain(&genesis, 3, 0);
+        let mut invalid = mine(&headers[1], 0, TxMerkleNode::all_zeros());
+        while invalid.validate_pow(invalid.target()).is_ok() {
+            invalid.nonce += 1;
+        }
+
+        // none of the headers is accepted if one of them is invalid
+        assert!(client
+            .accept_headers(&[headers[0], headers[1], invalid])
+            .is_err());
+        assert_eq!(client.tip_height(), 0);
+        assert!(client.get(&headers[0].block_hash()).is_none());
+
+        client.accept_headers(&headers).unwrap();
+        assert_eq!(client.tip_height(), 3);
+    }
+
+    #[test]
+    fn test_light_client_network_params() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut client = LightClient::new(NetworkParams::new(Network::Regtest), 0, genesis);
+
+        let headers = mine_chain(&genesis, 3, 0);
+        client.accept_headers(&headers).unwrap();
+
+        // a harder target than the one that regtest requires
+        let mut other = mine(&headers[2], 0, TxMerkleNode::all_zeros());
+        other.bits = CompactTarget::from_consensus(0x1f7fffff);
+        while other.validate_pow(other.target()).is_err() {
+            other.nonce += 1;
+        }
+        assert!(client.accept_headers(&[other]).is_err());
+
+        // regtest headers are above the proof-of-work limit of mainnet
+        let mut client = LightClient::new(NetworkParams::new(Network::Bitcoin), 0, genesis);
+        assert!(client.accept_headers(&headers).is_err());
+        assert_eq!(client.tip_height(), 0);
+    }
+
+    #[test]
+    fn test_locator() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut client = LightClient::new(NetworkParams::new(Network::Regtest), 100, genesis);
+        assert_eq!(client.locator(), vec![genesis.block_hash()]);
+
+        let headers = mine_chain(&genesis, 30, 0);
+        client.accept_headers(&headers).unwrap();
+
+        let locator = client.locator();
+        let heights = locator
+            .iter()
+            .map(|hash| client.get(hash).unwrap().height)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            heights,
+            vec![130, 129, 128, 127, 126, 125, 124, 123, 122, 121, 119, 115, 107, 100]
+        );
+    }
+}
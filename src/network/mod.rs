@@ -0,0 +1,327 @@
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::params::Params;
+use bitcoin::{CompactTarget, Network, ScriptBuf};
+
+/// The challenge of the default signet, which is a 1-of-2 multisig.
+const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+/// The consensus rules on headers that differ across networks.
+///
+/// The merkle tree depth of 17, which bounds the index in
+/// [`crate::spv::TxInclusionProofGadget`], is the same on every network, as they all share the
+/// block weight limit.
+#[derive(Clone, Debug)]
+pub struct NetworkParams {
+    /// The proof-of-work limit and the retarget rules.
+    pub params: Params,
+    /// Whether a retarget starts from the target of the first block of the period, rather than
+    /// of the last one, so that a block with the minimum difficulty at the end of a period does
+    /// not reset it (BIP94, testnet4).
+    pub enforce_bip94: bool,
+    /// The script that the signet solution of every block must satisfy, only for signet.
+    pub signet_challenge: Option<ScriptBuf>,
+}
+
+impl NetworkParams {
+    pub fn new(network: Network) -> Self {
+        Self {
+            params: Params::new(network),
+            enforce_bip94: network == Network::Testnet4,
+            signet_challenge: (network == Network::Signet)
+                .then(|| ScriptBuf::from_hex(DEFAULT_SIGNET_CHALLENGE).unwrap()),
+        }
+    }
+
+    /// Use a custom signet with the given challenge.
+    pub fn with_signet_challenge(mut self, challenge: ScriptBuf) -> Self {
+        self.signet_challenge = Some(challenge);
+        self
+    }
+
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    /// The bits of security that every valid header has, which are the leading zeros of the
+    /// proof-of-work limit.
+    ///
+    /// On the networks that allow blocks with the minimum difficulty, such as testnet4 and
+    /// regtest, this is all that a covenant can count on for any single header, regardless of
+    /// the actual difficulty of the chain.
+    pub fn min_bit_security(&self) -> usize {
+        let bytes = self.params.max_attainable_target.to_be_bytes();
+        let zero_bytes = bytes.iter().take_while(|b| **b == 0).count();
+        zero_bytes * 8
+            + bytes
+                .get(zero_bytes)
+                .map_or(0, |b| b.leading_zeros() as usize)
+    }
+
+    /// Return the bits that the header at `height` with the given time must have, like
+    /// `GetNextWorkRequired` in Bitcoin Core, or `None` if `ancestor` does not reach back far
+    /// enough to tell.
+    ///
+    /// `ancestor` returns the header at a given height in the chain that the header extends.
+    pub fn next_bits(
+        &self,
+        height: u32,
+        time: u32,
+        ancestor: impl Fn(u32) -> Option<Header>,
+    ) -> Option<CompactTarget> {
+        let interval = self.params.difficulty_adjustment_interval() as u32;
+        let pow_limit = self.params.max_attainable_target.to_compact_lossy();
+        let last = ancestor(height.checked_sub(1)?)?;
+
+        if height % interval != 0 {
+            if !self.params.allow_min_difficulty_blocks {
+                return Some(last.bits);
+            }
+
+            // the 20-minute exception: a block that is more than twice the target spacing
+            // after the previous one can have the minimum difficulty
+            if time as u64 > last.time as u64 + 2 * self.params.pow_target_spacing {
+                return Some(pow_limit);
+            }
+
+            // otherwise, it has the bits of the last block that did not use the exception
+            let mut cur_height = height - 1;
+            let mut cur = last;
+            while cur_height % interval != 0 && cur.bits == pow_limit {
+                cur_height -= 1;
+                cur = ancestor(cur_height)?;
+            }
+            return Some(cur.bits);
+        }
+
+        if self.params.no_pow_retargeting {
+            return Some(last.bits);
+        }
+
+        let first = ancestor(height - interval)?;
+        let timespan = (last.time as u64).saturating_sub(first.time as u64);
+        let bits = if self.enforce_bip94 {
+            first.bits
+        } else {
+            last.bits
+        };
+        Some(CompactTarget::from_next_work_required(
+            bits,
+            timespan,
+            &self.params,
+        ))
+    }
+
+    /// Check that the target of the header is within the proof-of-work limit and met by its
+    /// hash, which, unlike its bits, does not depend on the headers before it.
+    pub fn check_pow(&self, header: &Header) -> Result<()> {
+        if header.target() > self.params.max_attainable_target {
+            return Err(Error::msg(format!(
+                "The header {} has a target above the proof-of-work limit.",
+                header.block_hash()
+            )));
+        }
+        if header.validate_pow(header.target()).is_err() {
+            return Err(Error::msg(format!(
+                "The header {} does not meet its target.",
+                header.block_hash()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the header at `height`: it must pass [`Self::check_pow`], and its bits must be the
+    /// ones from [`Self::next_bits`] when `ancestor` reaches back far enough.
+    pub fn check_header(
+        &self,
+        height: u32,
+        header: &Header,
+        ancestor: impl Fn(u32) -> Option<Header>,
+    ) -> Result<()> {
+        self.check_pow(header)?;
+        if let Some(bits) = self.next_bits(height, header.time, ancestor) {
+            if header.bits != bits {
+                return Err(Error::msg(format!(
+                    "The header at height {} has bits {:#010x}, but {:#010x} are expected.",
+                    height,
+                    header.bits.to_consensus(),
+                    bits.to_consensus()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Network> for NetworkParams {
+    fn from(network: Network) -> Self {
+        Self::new(network)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::network::NetworkParams;
+    use bitcoin::block::{Header, Version};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, CompactTarget, Network, TxMerkleNode};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_min_bit_security() {
+        assert_eq!(NetworkParams::new(Network::Bitcoin).min_bit_security(), 32);
+        assert_eq!(NetworkParams::new(Network::Testnet4).min_bit_security(), 32);
+        assert_eq!(NetworkParams::new(Network::Signet).min_bit_security(), 22);
+        assert_eq!(NetworkParams::new(Network::Regtest).min_bit_security(), 1);
+
+        // every genesis block meets the limit
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Testnet4,
+            Network::Signet,
+            Network::Regtest,
+        ] {
+            let params = NetworkParams::new(network);
+            let hash = genesis_block(network).block_hash();
+            let bytes = hash.to_byte_array();
+            let leading_zeros = bytes
+                .iter()
+                .rev()
+                .map(|b| b.leading_zeros() as usize)
+                .scan(true, |go, zeros| {
+                    let count = if *go { zeros } else { 0 };
+                    *go = *go && zeros == 8;
+                    Some(count)
+                })
+                .sum::<usize>();
+            assert!(leading_zeros >= params.min_bit_security());
+        }
+
+        assert!(NetworkParams::new(Network::Signet)
+            .signet_challenge
+            .is_some());
+        assert!(NetworkParams::new(Network::Bitcoin)
+            .signet_challenge
+            .is_none());
+    }
+
+    fn header(time: u32, bits: u32) -> Header {
+        Header {
+            version: Version::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(bits),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_next_bits() {
+        let mainnet = NetworkParams::new(Network::Bitcoin);
+        let testnet4 = NetworkParams::new(Network::Testnet4);
+        let regtest = NetworkParams::new(Network::Regtest);
+
+        // a period that took half of the target timespan, whose last block is at the minimum
+        // difficulty
+        let mut chain = HashMap::new();
+        for height in 0..2016u32 {
+            chain.insert(height, header(height * 300, 0x1c00ffff));
+        }
+        chain.insert(2015, header(2015 * 300, 0x1d00ffff));
+        let ancestor = |height: u32| chain.get(&height).copied();
+
+        // within a period, the bits stay the same on mainnet
+        assert_eq!(
+            mainnet.next_bits(2015, 0, ancestor),
+            Some(CompactTarget::from_consensus(0x1c00ffff))
+        );
+
+        // at a retarget, mainnet starts from the last block, and testnet4 from the first one
+        let timespan = 2015 * 300;
+        assert_eq!(
+            mainnet.next_bits(2016, 0, ancestor),
+            Some(CompactTarget::from_next_work_required(
+                CompactTarget::from_consensus(0x1d00ffff),
+                timespan,
+                &mainnet.params
+            ))
+        );
+        assert_eq!(
+            testnet4.next_bits(2016, 0, ancestor),
+            Some(CompactTarget::from_next_work_required(
+                CompactTarget::from_consensus(0x1c00ffff),
+                timespan,
+                &testnet4.params
+            ))
+        );
+
+        // the 20-minute exception on testnet4
+        assert_eq!(
+            testnet4.next_bits(2015, 2014 * 300 + 1201, ancestor),
+            Some(CompactTarget::from_consensus(0x1d00ffff))
+        );
+        assert_eq!(
+            testnet4.next_bits(2015, 2014 * 300 + 1200, ancestor),
+            Some(CompactTarget::from_consensus(0x1c00ffff))
+        );
+        // after a block with the exception, the bits go back to those before it
+        assert_eq!(
+            testnet4.next_bits(2016 + 2015, 0, |height| {
+                if height >= 2016 + 2010 {
+                    Some(header(0, 0x1d00ffff))
+                } else {
+                    Some(header(0, 0x1c00ffff))
+                }
+            }),
+            Some(CompactTarget::from_consensus(0x1c00ffff))
+        );
+        // unless the whole period uses it
+        assert_eq!(
+            testnet4.next_bits(2016 + 2015, 0, |_| Some(header(0, 0x1d00ffff))),
+            Some(CompactTarget::from_consensus(0x1d00ffff))
+        );
+
+        // regtest does not retarget
+        assert_eq!(
+            regtest.next_bits(2016, 0, |_| Some(header(0, 0x207fffff))),
+            Some(CompactTarget::from_consensus(0x207fffff))
+        );
+
+        // not enough ancestors
+        assert_eq!(
+            mainnet.next_bits(2016, 0, |height| ancestor(height).filter(|_| height > 0)),
+            None
+        );
+        assert_eq!(mainnet.next_bits(0, 0, ancestor), None);
+    }
+
+    #[test]
+    fn test_check_header() {
+        let regtest = NetworkParams::new(Network::Regtest);
+        let genesis = genesis_block(Network::Regtest).header;
+
+        let mut next = genesis;
+        next.prev_blockhash = genesis.block_hash();
+        next.time += 600;
+        while next.validate_pow(next.target()).is_err() {
+            next.nonce += 1;
+        }
+        regtest.check_header(1, &next, |_| Some(genesis)).unwrap();
+
+        // bits other than those expected, even if the header meets them
+        let mut other = next;
+        other.bits = CompactTarget::from_consensus(0x1f7fffff);
+        while other.validate_pow(other.target()).is_err() {
+            other.nonce += 1;
+        }
+        assert!(regtest.check_header(1, &other, |_| Some(genesis)).is_err());
+
+        // a target above the limit
+        let mut easy = next;
+        easy.bits = CompactTarget::from_consensus(0x2100ffff);
+        assert!(regtest.check_header(1, &easy, |_| None).is_err());
+    }
+}
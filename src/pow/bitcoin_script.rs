@@ -0,0 +1,843 @@
+use crate::hint::HintSource;
+use crate::pow::{count_leading_zeros, HashByteOrder};
+use crate::treepp::*;
+use crate::utils::byte_to_byte_string;
+
+/// Gadget for counting the leading zeros of an arbitrary 32-byte hash, such as a block hash, a
+/// txid, a tapleaf hash, or a custom commitment.
+pub struct HashPowGadget;
+
+impl HashPowGadget {
+    /// Push the hint for counting the leading zeros of the hash.
+    ///
+    /// This is adapted from `bitcoin-circle-stark`, @victorkstarkware
+    /// https://github.com/Bitcoin-Wildlife-Sanctuary/bitcoin-circle-stark/blob/main/src/pow/bitcoin_script.rs
+    ///
+    pub fn push_leading_zeros_hint(hash: &[u8; 32], order: HashByteOrder) -> Script {
+        let leading_zeros = count_leading_zeros(hash, order);
+        let num_zero_bytes = leading_zeros / 8;
+
+        let (remainder, msb) = match order {
+            HashByteOrder::LittleEndian => {
+                if leading_zeros % 8 == 0 {
+                    (hash[..32 - num_zero_bytes].to_vec(), 0)
+                } else {
+                    (
+                        hash[..31 - num_zero_bytes].to_vec(),
+                        hash[31 - num_zero_bytes],
+                    )
+                }
+            }
+            HashByteOrder::BigEndian => {
+                if leading_zeros % 8 == 0 {
+                    (hash[num_zero_bytes..].to_vec(), 0)
+                } else {
+                    (hash[num_zero_bytes + 1..].to_vec(), hash[num_zero_bytes])
+                }
+            }
+        };
+
+        script! {
+            { leading_zeros / 8 }
+            { leading_zeros % 8 }
+            { remainder }
+            if leading_zeros % 8 != 0 {
+                { msb }
+            }
+        }
+    }
+
+    /// Get the number of leading zeros of the hash, using a hint.
+    ///
+    /// hint:
+    ///   leading zeros // 8 (must be non-negative and smaller or equal to 32)
+    ///   leading zeros % 8 (must be non-negative and smaller or equal to 7)
+    ///   remainder (the bytes below the most significant nonzero byte)
+    ///   msb (only if leading zeros % 8 is not zero, must be a positive 1-byte number)
+    ///
+    /// input:
+    ///   hash
+    ///
+    /// output:
+    ///   leading zeros
+    ///
+    /// This is adapted from `bitcoin-circle-stark`, @victorkstarkware
+    /// https://github.com/Bitcoin-Wildlife-Sanctuary/bitcoin-circle-stark/blob/main/src/pow/bitcoin_script.rs
+    ///
+    pub fn get_leading_zeros(order: HashByteOrder, hint: &HintSource) -> Script {
+        script! {
+            // pull the leading_zeros / 8
+            { hint.pull(1, 0) }
+
+            // check its format
+            OP_DUP OP_DUP OP_ABS OP_EQUALVERIFY // enforce that it is nonnegative
+            OP_DUP 32 OP_LESSTHANOREQUAL OP_VERIFY // enforce that it is smaller or equal to 32
+
+            // pull the leading_zeros % 8
+            { hint.pull(2, 0) }
+
+            // check its format
+            OP_DUP OP_DUP OP_ABS OP_EQUALVERIFY // enforce that it is nonnegative
+            OP_DUP 7 OP_LESSTHANOREQUAL OP_VERIFY // enforce that it is smaller or equal to 7
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8
+
+            // compute the expected length, which is (32 - ceil(leading_zeros / 8))
+            OP_OVER 32 OP_SWAP OP_SUB OP_TOALTSTACK
+            OP_DUP OP_0NOTEQUAL
+            OP_IF
+                OP_FROMALTSTACK OP_1SUB OP_TOALTSTACK
+            OP_ENDIF
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8
+            // altstack: expected length
+
+            // pull the remainder
+            { hint.pull(3, 1) }
+
+            // check the remainder length
+            OP_SIZE OP_FROMALTSTACK OP_EQUALVERIFY
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8, remainder
+            // check if msb is needed
+            OP_OVER OP_0NOTEQUAL
+            OP_IF
+                { hint.pull(4, 0) }
+
+                // check its size to be 1 (it would not be 2 because its most significant bit must be zero)
+                // and it is not 0 because in that case, it would have more leading zeros
+                //
+                // note: only the upper bound of the msb is checked below, so the leading zeros may be
+                // underestimated but never overestimated, see `get_exact_leading_zeros` otherwise.
+                OP_SIZE 1 OP_EQUALVERIFY
+
+                // enforce that it is positive, since a byte with the most significant bit set
+                // would be read as a negative number and pass the upper bound below
+                OP_DUP 0 OP_GREATERTHAN OP_VERIFY
+
+                OP_DUP OP_TOALTSTACK
+
+                OP_ROT
+
+                // stack: h, leading_zeros / 8, remainder, msb, leading_zeros % 8
+                // altstack: msb
+
+                OP_DUP OP_TOALTSTACK
+
+                { Self::pow2_of_8_minus() }
+
+                // stack: h, leading_zeros / 8, remainder, msb, 1 << (8 - leading_zeros % 8)
+                // altstack: msb, leading_zeros % 8
+
+                OP_LESSTHAN OP_VERIFY
+
+                // stack: h, leading_zeros / 8, remainder
+                // altstack: msb, leading_zeros % 8
+            OP_ELSE
+                OP_SWAP OP_TOALTSTACK
+                OP_PUSHBYTES_0 OP_TOALTSTACK
+
+                // stack: h, leading_zeros / 8, remainder
+                // altstack: msb, leading_zeros % 8
+            OP_ENDIF
+
+            // generate the zeros
+            OP_OVER
+            // stack: h, leading_zeros / 8, remainder, leading_zeros / 8
+            // altstack: msb, leading_zeros % 8
+
+            { Self::zero_bytes() }
+            // stack: h, leading_zeros / 8, remainder, zeros
+            // altstack: msb, leading_zeros % 8
+
+            OP_FROMALTSTACK OP_FROMALTSTACK
+            OP_ROT
+            if order == HashByteOrder::BigEndian {
+                OP_SWAP
+            }
+            OP_CAT
+
+            // stack: h, leading_zeros / 8, remainder, leading_zeros % 8, msb and zeros
+
+            OP_ROT
+            if order == HashByteOrder::LittleEndian {
+                OP_SWAP
+            }
+            OP_CAT
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8, hash
+
+            OP_2SWAP OP_TOALTSTACK
+            OP_EQUALVERIFY OP_FROMALTSTACK
+
+            // stack: leading_zeros % 8, leading_zeros / 8
+
+            OP_DUP OP_ADD OP_DUP OP_ADD OP_DUP OP_ADD
+            OP_ADD
+
+            // stack: leading zeros
+        }
+    }
+
+    /// Push the hint for counting the leading zeros of the hash exactly.
+    ///
+    /// Unlike [`Self::push_leading_zeros_hint`], the most significant nonzero byte is always
+    /// given separately, as a number, so that it can be range-checked.
+    pub fn push_exact_leading_zeros_hint(hash: &[u8; 32], order: HashByteOrder) -> Script {
+        let leading_zeros = count_leading_zeros(hash, order);
+        assert!(leading_zeros < 256);
+        let num_zero_bytes = leading_zeros / 8;
+
+        let (remainder, msb) = match order {
+            HashByteOrder::LittleEndian => (
+                hash[..31 - num_zero_bytes].to_vec(),
+                hash[31 - num_zero_bytes],
+            ),
+            HashByteOrder::BigEndian => (hash[num_zero_bytes + 1..].to_vec(), hash[num_zero_bytes]),
+        };
+
+        script! {
+            { leading_zeros / 8 }
+            { leading_zeros % 8 }
+            { remainder }
+            { msb }
+        }
+    }
+
+    /// Get the exact number of leading zeros of the hash, using a hint.
+    ///
+    /// hint:
+    ///   leading zeros // 8 (must be non-negative and smaller or equal to 31)
+    ///   leading zeros % 8 (must be non-negative and smaller or equal to 7)
+    ///   remainder (the bytes below the most significant nonzero byte)
+    ///   msb (the most significant nonzero byte, as a number between 1 and 255)
+    ///
+    /// input:
+    ///   hash
+    ///
+    /// output:
+    ///   leading zeros
+    ///
+    /// The msb is checked to be in [2^(7 - leading zeros % 8), 2^(8 - leading zeros % 8)), so it
+    /// has exactly `leading zeros % 8` leading zeros, and, since the hash is rebuilt from the
+    /// zeros, the msb, and the remainder, the count can be neither over- nor underestimated. A
+    /// hash that is all zeros is not supported.
+    pub fn get_exact_leading_zeros(order: HashByteOrder, hint: &HintSource) -> Script {
+        script! {
+            // pull the leading_zeros / 8
+            { hint.pull(1, 0) }
+
+            // check its format
+            OP_DUP OP_DUP OP_ABS OP_EQUALVERIFY // enforce that it is nonnegative
+            OP_DUP 31 OP_LESSTHANOREQUAL OP_VERIFY // enforce that it is smaller or equal to 31
+
+            // pull the leading_zeros % 8
+            { hint.pull(2, 0) }
+
+            // check its format
+            OP_DUP OP_DUP OP_ABS OP_EQUALVERIFY // enforce that it is nonnegative
+            OP_DUP 7 OP_LESSTHANOREQUAL OP_VERIFY // enforce that it is smaller or equal to 7
+
+            // pull the remainder
+            { hint.pull(3, 0) }
+
+            // check that the remainder has (31 - leading_zeros / 8) bytes
+            OP_SIZE 3 OP_PICK 31 OP_SWAP OP_SUB OP_EQUALVERIFY
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8, remainder
+
+            // pull the msb
+            { hint.pull(4, 0) }
+
+            // check that 2^(7 - leading_zeros % 8) <= msb < 2^(8 - leading_zeros % 8)
+            2 OP_PICK
+            { Self::pow2_of_8_minus() }
+            OP_2DUP OP_LESSTHAN OP_VERIFY
+            OP_OVER OP_DUP OP_ADD OP_LESSTHANOREQUAL OP_VERIFY
+
+            { byte_to_byte_string() }
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8, remainder, msb
+
+            // generate the zeros
+            3 OP_PICK
+            { Self::zero_bytes() }
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8, remainder, msb, zeros
+
+            if order == HashByteOrder::BigEndian {
+                OP_SWAP
+            }
+            OP_CAT
+            if order == HashByteOrder::BigEndian {
+                OP_SWAP
+            }
+            OP_CAT
+
+            // stack: h, leading_zeros / 8, leading_zeros % 8, hash
+
+            3 OP_ROLL OP_EQUALVERIFY
+
+            // stack: leading_zeros / 8, leading_zeros % 8
+
+            OP_SWAP
+            OP_DUP OP_ADD OP_DUP OP_ADD OP_DUP OP_ADD
+            OP_ADD
+
+            // stack: leading zeros
+        }
+    }
+
+    /// Push the hint for checking that the hash has at least `min_leading_zeros` leading zeros.
+    ///
+    /// The hint can be generated for any hash, but the check only passes if the hash has enough
+    /// leading zeros.
+    pub fn push_min_leading_zeros_hint(
+        hash: &[u8; 32],
+        min_leading_zeros: usize,
+        order: HashByteOrder,
+    ) -> Script {
+        assert!(min_leading_zeros <= 256);
+        let num_zero_bytes = min_leading_zeros / 8;
+
+        if min_leading_zeros == 0 {
+            return script! {};
+        }
+
+        let be_bytes = order.to_be_bytes(hash);
+        let len = if min_leading_zeros % 8 == 0 {
+            32 - num_zero_bytes
+        } else {
+            31 - num_zero_bytes
+        };
+
+        let mut remainder = be_bytes[32 - len..].to_vec();
+        if order == HashByteOrder::LittleEndian {
+            remainder.reverse();
+        }
+
+        script! {
+            { remainder }
+            if min_leading_zeros % 8 != 0 {
+                { be_bytes[num_zero_bytes] }
+            }
+        }
+    }
+
+    /// Check that the hash has at least `min_leading_zeros` leading zeros, using a hint.
+    ///
+    /// hint:
+    ///   remainder (the bytes below the byte that the threshold falls in)
+    ///   partial byte (only if min_leading_zeros % 8 is not zero, as a number)
+    ///
+    /// input:
+    ///   hash
+    ///
+    /// output:
+    ///
+    /// This is much cheaper than [`Self::get_leading_zeros`] followed by a comparison, as the
+    /// zeros are a constant and only the byte that the threshold falls in needs a range check.
+    pub fn check_min_leading_zeros(
+        min_leading_zeros: usize,
+        order: HashByteOrder,
+        hint: &HintSource,
+    ) -> Script {
+        Self::check_min_leading_zeros_above(min_leading_zeros, order, hint, 0)
+    }
+
+    /// Same as [`Self::check_min_leading_zeros`], for a hash that sits above `main_below`
+    /// elements of the caller's own gadget, which the inline hints need to skip.
+    pub(crate) fn check_min_leading_zeros_above(
+        min_leading_zeros: usize,
+        order: HashByteOrder,
+        hint: &HintSource,
+        main_below: usize,
+    ) -> Script {
+        assert!(min_leading_zeros <= 256);
+        let num_zero_bytes = min_leading_zeros / 8;
+        let num_zero_bits = min_leading_zeros % 8;
+
+        if min_leading_zeros == 0 {
+            return script! {
+                OP_DROP
+            };
+        }
+
+        let len = if num_zero_bits == 0 {
+            32 - num_zero_bytes
+        } else {
+            31 - num_zero_bytes
+        };
+
+        script! {
+            // pull the remainder
+            { hint.pull(1 + main_below, 0) }
+            OP_SIZE { len } OP_EQUALVERIFY
+
+            if num_zero_bits != 0 {
+                // pull the partial byte and check that it has the remaining zeros
+                { hint.pull(2 + main_below, 0) }
+                OP_DUP 0 { 1 << (8 - num_zero_bits) } OP_WITHIN OP_VERIFY
+                { byte_to_byte_string() }
+
+                if order == HashByteOrder::BigEndian {
+                    OP_SWAP
+                }
+                OP_CAT
+            }
+
+            if num_zero_bytes != 0 {
+                { vec![0u8; num_zero_bytes] }
+                if order == HashByteOrder::BigEndian {
+                    OP_SWAP
+                }
+                OP_CAT
+            }
+
+            OP_EQUALVERIFY
+        }
+    }
+
+    /// Compute 2^(8 - v) for v between 0 and 7.
+    ///
+    /// It computes 2^(7 - v) and doubles it, as 7 - v, unlike 8 - v, is covered by the bits
+    /// 4, 2, and 1.
+    fn pow2_of_8_minus() -> Script {
+        script! {
+            7 OP_SWAP OP_SUB
+
+            OP_DUP
+            4 OP_GREATERTHANOREQUAL OP_IF
+                4 OP_SUB
+                16
+            OP_ELSE
+                1
+            OP_ENDIF
+
+            OP_SWAP
+
+            // stack: (2^4 or 2^0), (7 - v) % 4
+
+            OP_DUP
+            2 OP_GREATERTHANOREQUAL OP_IF
+                2 OP_SUB
+                OP_SWAP OP_DUP OP_ADD OP_DUP OP_ADD OP_SWAP
+            OP_ENDIF
+
+            // stack: (2^6, 2^4, 2^2 or 2^0), (7 - v) % 2
+
+            OP_IF
+                OP_DUP OP_ADD
+            OP_ENDIF
+
+            // stack: 2^(7 - v)
+
+            OP_DUP OP_ADD
+        }
+    }
+
+    /// Generate n zero bytes, where n must be smaller than 32.
+    fn zero_bytes() -> Script {
+        script! {
+            OP_DUP
+            16 OP_GREATERTHANOREQUAL OP_IF
+                16 OP_SUB
+                OP_PUSHBYTES_4 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0
+                OP_DUP OP_CAT
+                OP_DUP OP_CAT
+            OP_ELSE
+                OP_PUSHBYTES_0
+            OP_ENDIF
+
+            OP_SWAP
+            // stack: zeros (pending), n % 16
+
+            OP_DUP
+            8 OP_GREATERTHANOREQUAL OP_IF
+                8 OP_SUB
+
+                OP_SWAP
+
+                OP_PUSHBYTES_4 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0
+                OP_DUP OP_CAT
+
+                OP_CAT OP_SWAP
+            OP_ENDIF
+            // stack: zeros (pending), n % 8
+
+            OP_DUP
+            4 OP_GREATERTHANOREQUAL OP_IF
+                4 OP_SUB
+
+                OP_SWAP
+
+                OP_PUSHBYTES_4 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0
+
+                OP_CAT OP_SWAP
+            OP_ENDIF
+            // stack: zeros (pending), n % 4
+
+            OP_DUP
+            2 OP_GREATERTHANOREQUAL OP_IF
+                2 OP_SUB
+
+                OP_SWAP
+
+                OP_PUSHBYTES_2 OP_PUSHBYTES_0 OP_PUSHBYTES_0
+
+                OP_CAT OP_SWAP
+            OP_ENDIF
+            // stack: zeros (pending), n % 2
+
+            OP_IF
+                OP_PUSHBYTES_1 OP_PUSHBYTES_0
+                OP_CAT
+            OP_ENDIF
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hint::HintSource;
+    use crate::pow::{count_leading_zeros, HashByteOrder, HashPowGadget};
+    use crate::treepp::*;
+    use proptest::prelude::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_leading_zeros() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for order in [HashByteOrder::LittleEndian, HashByteOrder::BigEndian] {
+            for leading_zeros in [0usize, 1, 7, 8, 9, 15, 16, 17, 63, 64, 80, 255] {
+                let mut be_bytes = [0u8; 32];
+                prng.fill(&mut be_bytes[..]);
+
+                // clear the leading bits and set the next one
+                for i in 0..leading_zeros {
+                    be_bytes[i / 8] &= !(0x80 >> (i % 8));
+                }
+                be_bytes[leading_zeros / 8] |= 0x80 >> (leading_zeros % 8);
+
+                let mut hash = be_bytes;
+                if order == HashByteOrder::LittleEndian {
+                    hash.reverse();
+                }
+                assert_eq!(count_leading_zeros(&hash, order), leading_zeros);
+
+                // an unrelated element at the bottom of the stack
+                let hint = HintSource::Bottom { offset: 1 };
+                let script = script! {
+                    { b"unrelated".to_vec() }
+                    { HashPowGadget::push_leading_zeros_hint(&hash, order) }
+                    { hash.to_vec() }
+                    { HashPowGadget::get_leading_zeros(order, &hint) }
+                    { leading_zeros } OP_EQUALVERIFY
+                    { b"unrelated".to_vec() } OP_EQUAL
+                };
+
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+        }
+    }
+
+    fn encode_num(v: usize) -> Vec<u8> {
+        assert!(v < 0x80);
+        if v == 0 {
+            vec![]
+        } else {
+            vec![v as u8]
+        }
+    }
+
+    fn is_zero_num(bytes: &[u8]) -> bool {
+        bytes
+            .iter()
+            .enumerate()
+            .all(|(i, &b)| b == 0 || (i == bytes.len() - 1 && b == 0x80))
+    }
+
+    /// Split the hash as if it had `8 * q + r` leading zeros, which is how a cheating prover
+    /// would construct the hint for the claim.
+    fn split_hint(hash: &[u8; 32], order: HashByteOrder, q: usize, r: usize) -> Vec<Vec<u8>> {
+        let be_bytes = order.to_be_bytes(hash);
+        let start = (q + (r != 0) as usize).min(32);
+
+        let mut remainder = be_bytes[start..].to_vec();
+        if order == HashByteOrder::LittleEndian {
+            remainder.reverse();
+        }
+
+        let mut hint = vec![encode_num(q), encode_num(r), remainder];
+        if r != 0 {
+            hint.push(vec![be_bytes[q.min(31)]]);
+        }
+        hint
+    }
+
+    /// Run the gadget with the hint as the witness, and return whether it reports more than the
+    /// actual number of leading zeros.
+    fn over_reports(hash: &[u8; 32], order: HashByteOrder, hint: Vec<Vec<u8>>) -> bool {
+        let script = script! {
+            { hash.to_vec() }
+            { HashPowGadget::get_leading_zeros(order, &HintSource::default()) }
+            { count_leading_zeros(hash, order) } OP_GREATERTHAN
+        };
+        execute_script_with_witness(script, hint).success
+    }
+
+    #[test]
+    fn test_leading_zeros_negative_msb() {
+        // a byte with the most significant bit set has no leading zeros, but it would be read
+        // as a negative number
+        for byte in 0x80..=0xffu8 {
+            let mut be_bytes = [0x55u8; 32];
+            be_bytes[2] = 0;
+            be_bytes[3] = byte;
+
+            for order in [HashByteOrder::LittleEndian, HashByteOrder::BigEndian] {
+                let mut hash = be_bytes;
+                if order == HashByteOrder::LittleEndian {
+                    hash.reverse();
+                }
+
+                for r in 1..8 {
+                    assert!(!over_reports(&hash, order, split_hint(&hash, order, 3, r)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_leading_zeros_exhaustive_msb() {
+        // the msb is the only part of the hint that is not pinned down by the hash, so check
+        // every byte with every claimed leading_zeros % 8
+        for order in [HashByteOrder::LittleEndian, HashByteOrder::BigEndian] {
+            for byte in 0..=0xffu8 {
+                let mut be_bytes = [0xaau8; 32];
+                be_bytes[0] = 0;
+                be_bytes[1] = byte;
+
+                let mut hash = be_bytes;
+                if order == HashByteOrder::LittleEndian {
+                    hash.reverse();
+                }
+
+                for r in 0..8 {
+                    assert!(!over_reports(&hash, order, split_hint(&hash, order, 1, r)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_leading_zeros() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for order in [HashByteOrder::LittleEndian, HashByteOrder::BigEndian] {
+            for leading_zeros in [0usize, 1, 7, 8, 9, 15, 16, 17, 63, 64, 80, 248, 255] {
+                let mut be_bytes = [0u8; 32];
+                prng.fill(&mut be_bytes[..]);
+
+                for i in 0..leading_zeros {
+                    be_bytes[i / 8] &= !(0x80 >> (i % 8));
+                }
+                be_bytes[leading_zeros / 8] |= 0x80 >> (leading_zeros % 8);
+
+                let mut hash = be_bytes;
+                if order == HashByteOrder::LittleEndian {
+                    hash.reverse();
+                }
+
+                for hint in [
+                    HintSource::Bottom { offset: 1 },
+                    HintSource::AltStack,
+                    HintSource::Inline,
+                ] {
+                    let script = script! {
+                        { b"unrelated".to_vec() }
+                        { hint.push(HashPowGadget::push_exact_leading_zeros_hint(&hash, order)) }
+                        { hash.to_vec() }
+                        { HashPowGadget::get_exact_leading_zeros(order, &hint) }
+                        { leading_zeros } OP_EQUALVERIFY
+                        { b"unrelated".to_vec() } OP_EQUAL
+                    };
+
+                    let exec_result = execute_script(script);
+                    assert!(exec_result.success);
+                }
+
+                // any other claim, split consistently with the hash, is rejected
+                for claimed in 0..256 {
+                    if claimed == leading_zeros {
+                        continue;
+                    }
+                    let (q, r) = (claimed / 8, claimed % 8);
+                    let mut remainder = be_bytes[q + 1..].to_vec();
+                    if order == HashByteOrder::LittleEndian {
+                        remainder.reverse();
+                    }
+
+                    let script = script! {
+                        { q }
+                        { r }
+                        { remainder }
+                        { be_bytes[q] }
+                        { hash.to_vec() }
+                        { HashPowGadget::get_exact_leading_zeros(order, &HintSource::default()) }
+                        OP_DROP OP_TRUE
+                    };
+
+                    let exec_result = execute_script(script);
+                    assert!(!exec_result.success);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_leading_zeros() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for order in [HashByteOrder::LittleEndian, HashByteOrder::BigEndian] {
+            for leading_zeros in [0usize, 1, 7, 8, 9, 15, 16, 17, 63, 64, 78, 80, 255] {
+                let mut be_bytes = [0u8; 32];
+                prng.fill(&mut be_bytes[..]);
+
+                for i in 0..leading_zeros {
+                    be_bytes[i / 8] &= !(0x80 >> (i % 8));
+                }
+                be_bytes[leading_zeros / 8] |= 0x80 >> (leading_zeros % 8);
+
+                let mut hash = be_bytes;
+                if order == HashByteOrder::LittleEndian {
+                    hash.reverse();
+                }
+
+                for min_leading_zeros in [0usize, 1, 7, 8, 9, 16, 64, 78, 79, 80, 81, 255, 256] {
+                    for hint in [
+                        HintSource::Bottom { offset: 1 },
+                        HintSource::AltStack,
+                        HintSource::Inline,
+                    ] {
+                        let script = script! {
+                            { b"unrelated".to_vec() }
+                            { hint.push(HashPowGadget::push_min_leading_zeros_hint(&hash, min_leading_zeros, order)) }
+                            { hash.to_vec() }
+                            { HashPowGadget::check_min_leading_zeros(min_leading_zeros, order, &hint) }
+                            { b"unrelated".to_vec() } OP_EQUAL
+                        };
+
+                        let exec_result = execute_script(script);
+                        assert_eq!(exec_result.success, leading_zeros >= min_leading_zeros);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_leading_zeros_script_size() {
+        for min_leading_zeros in [64, 78, 80] {
+            let check = HashPowGadget::check_min_leading_zeros(
+                min_leading_zeros,
+                HashByteOrder::LittleEndian,
+                &HintSource::default(),
+            );
+            let count = script! {
+                { HashPowGadget::get_leading_zeros(HashByteOrder::LittleEndian, &HintSource::default()) }
+                { min_leading_zeros } OP_GREATERTHANOREQUAL OP_VERIFY
+            };
+            assert!(check.len() * 2 < count.len());
+        }
+
+        // apart from the zero bytes, the check is a few pushes and comparisons for any threshold
+        for min_leading_zeros in 0..=256 {
+            let check = HashPowGadget::check_min_leading_zeros(
+                min_leading_zeros,
+                HashByteOrder::LittleEndian,
+                &HintSource::default(),
+            );
+            assert!(check.len() <= min_leading_zeros / 8 + 64);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(512))]
+
+        #[test]
+        fn prop_leading_zeros_never_over_reports(
+            random in any::<[u8; 32]>(),
+            forced_zeros in 0usize..=200,
+            big_endian in any::<bool>(),
+            q in 0usize..=33,
+            r in 0usize..=8,
+            tampered in 0usize..5,
+            random_element in proptest::collection::vec(any::<u8>(), 0..=33),
+        ) {
+            let order = if big_endian {
+                HashByteOrder::BigEndian
+            } else {
+                HashByteOrder::LittleEndian
+            };
+
+            let mut be_bytes = random;
+            for i in 0..forced_zeros {
+                be_bytes[i / 8] &= !(0x80 >> (i % 8));
+            }
+            let mut hash = be_bytes;
+            if order == HashByteOrder::LittleEndian {
+                hash.reverse();
+            }
+
+            // a split of the hash for the claimed count, with one element possibly replaced
+            let mut hint = split_hint(&hash, order, q, r);
+            if tampered < hint.len() {
+                hint[tampered] = random_element;
+            }
+
+            // the gadget pulls the msb only if leading_zeros % 8 is nonzero
+            if hint.len() == 4 && is_zero_num(&hint[1]) {
+                hint.pop();
+            } else if hint.len() == 3 && !is_zero_num(&hint[1]) {
+                hint.push(vec![be_bytes[q.min(31)]]);
+            }
+
+            prop_assert!(!over_reports(&hash, order, hint));
+        }
+
+        #[test]
+        fn prop_leading_zeros_honest_hint(
+            random in any::<[u8; 32]>(),
+            forced_zeros in 0usize..=200,
+            big_endian in any::<bool>(),
+        ) {
+            let order = if big_endian {
+                HashByteOrder::BigEndian
+            } else {
+                HashByteOrder::LittleEndian
+            };
+
+            let mut be_bytes = random;
+            for i in 0..forced_zeros {
+                be_bytes[i / 8] &= !(0x80 >> (i % 8));
+            }
+            be_bytes[31] |= 1;
+            let mut hash = be_bytes;
+            if order == HashByteOrder::LittleEndian {
+                hash.reverse();
+            }
+
+            let script = script! {
+                { HashPowGadget::push_leading_zeros_hint(&hash, order) }
+                { hash.to_vec() }
+                { HashPowGadget::get_leading_zeros(order, &HintSource::default()) }
+                { count_leading_zeros(&hash, order) } OP_EQUAL
+            };
+            prop_assert!(execute_script(script).success);
+        }
+    }
+}
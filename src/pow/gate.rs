@@ -0,0 +1,418 @@
+use crate::consensus_encode;
+use crate::hint::HintSource;
+use crate::pow::{count_leading_zeros, HashByteOrder, HashPowGadget};
+use crate::treepp::*;
+use crate::utils::byte_to_byte_string;
+use anyhow::{Error, Result};
+use bitcoin::consensus::Encodable;
+use bitcoin::secp256k1::constants::GENERATOR_X;
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::{TapLeafHash, TapSighashType, Transaction, TxOut};
+use covenants_gadgets::wizards::tx::step5_output::{Step1AmountGadget, Step2ScriptPubKeyGadget};
+use sha2::{Digest, Sha256};
+
+/// The size of the BIP-341 sighash preimage before `sha_outputs`, from the epoch to
+/// `sha_sequences`.
+const SIGHASH_PREFIX_SIZE: usize = 138;
+
+/// The size of the BIP-341 sighash preimage of a tapscript spend without annex after
+/// `sha_outputs`, from the spend type to the code separator position.
+const SIGHASH_SUFFIX_SIZE: usize = 42;
+
+/// A PoW gate, which requires the spender to grind a nonce such that
+///
+///   SHA256(SHA256(commitment || outputs || nonce))
+///
+/// has at least `min_leading_zeros` leading zeros, where `outputs` is the serialization of the
+/// outputs of the spending transaction (without the output count) and `nonce` is 8 bytes.
+///
+/// The gadget rebuilds the outputs from the witness and binds them to the spending transaction
+/// through `OP_CHECKSIG`. It hashes them into `sha_outputs` of the BIP-341 sighash, whose other
+/// fields come from the witness, and checks a signature with the generator as both the public
+/// key and the nonce, which is `e + 1` for the challenge `e` of the sighash. Any other outputs
+/// give another sighash, for which the signature does not verify. The last byte of `e` must not
+/// be 0xff, so that `e + 1` is only a change of that byte, which holds for all transactions but
+/// one in 256, and otherwise the spender changes the transaction, e.g., in its lock time.
+///
+/// The gate must be a tapscript leaf, spent without annex.
+///
+/// The preimage is subject to the 520-byte limit of stack elements.
+pub struct PowGate {
+    pub commitment: Vec<u8>,
+    pub min_leading_zeros: usize,
+    pub order: HashByteOrder,
+}
+
+impl PowGate {
+    /// Serialize the outputs as in a transaction, without the output count.
+    pub fn serialize_outputs(outputs: &[TxOut]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for output in outputs.iter() {
+            bytes.extend(consensus_encode!(output));
+        }
+        bytes
+    }
+
+    /// Compute the hash that the nonce is ground for.
+    pub fn hash(&self, outputs: &[TxOut], nonce: u64) -> [u8; 32] {
+        let mut sha256 = Sha256::new();
+        Digest::update(&mut sha256, &self.commitment);
+        Digest::update(&mut sha256, Self::serialize_outputs(outputs));
+        Digest::update(&mut sha256, nonce.to_le_bytes());
+        Sha256::digest(sha256.finalize()).into()
+    }
+
+    /// Search for a nonce, starting from `start_nonce`, that satisfies the gate, trying at most
+    /// `max_attempts` nonces.
+    ///
+    /// The state of SHA256 after the commitment and the outputs is computed once and reused for
+    /// every nonce.
+    pub fn grind(&self, outputs: &[TxOut], start_nonce: u64, max_attempts: u64) -> Option<u64> {
+        let mut prefix = Sha256::new();
+        Digest::update(&mut prefix, &self.commitment);
+        Digest::update(&mut prefix, Self::serialize_outputs(outputs));
+
+        for i in 0..max_attempts {
+            let nonce = start_nonce.wrapping_add(i);
+
+            let mut sha256 = prefix.clone();
+            Digest::update(&mut sha256, nonce.to_le_bytes());
+            let hash: [u8; 32] = Sha256::digest(sha256.finalize()).into();
+
+            if count_leading_zeros(&hash, self.order) >= self.min_leading_zeros {
+                return Some(nonce);
+            }
+        }
+        None
+    }
+
+    /// Push the hint for spending through the gate with the input at `input_index` of the
+    /// transaction, in the order that the gadget pulls it. The `prevouts` are the outputs spent
+    /// by all the inputs, and `leaf_hash` is the hash of the tapscript leaf of the gate.
+    ///
+    /// hint:
+    ///   for each output: amount, script pubkey
+    ///   nonce
+    ///   hint of [`HashPowGadget::check_min_leading_zeros`]
+    ///   sighash preimage before sha_outputs
+    ///   sighash preimage after sha_outputs
+    ///   challenge without its last byte
+    ///   last byte of the challenge, as a number
+    pub fn push_hint(
+        &self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        input_index: usize,
+        leaf_hash: TapLeafHash,
+        nonce: u64,
+    ) -> Result<Script> {
+        let mut preimage = vec![];
+        SighashCache::new(tx)
+            .taproot_encode_signing_data_to(
+                &mut preimage,
+                input_index,
+                &Prevouts::All(prevouts),
+                None,
+                Some((leaf_hash, 0xffffffff)),
+                TapSighashType::Default,
+            )
+            .map_err(|_| Error::msg("The sighash of the transaction cannot be computed."))?;
+        assert_eq!(
+            preimage.len(),
+            SIGHASH_PREFIX_SIZE + 32 + SIGHASH_SUFFIX_SIZE
+        );
+
+        let mut sighash = Self::tag(b"TapSighash");
+        sighash.extend(&preimage);
+        let mut challenge = Self::tag(b"BIP0340/challenge");
+        challenge.extend(GENERATOR_X);
+        challenge.extend(GENERATOR_X);
+        challenge.extend(Sha256::digest(&sighash));
+        let challenge = Sha256::digest(&challenge);
+        if challenge[31] == 0xff {
+            return Err(Error::msg(
+                "The signature challenge of the transaction ends with 0xff, so the transaction has to be changed, e.g., in its lock time.",
+            ));
+        }
+
+        let hash = self.hash(&tx.output, nonce);
+        Ok(script! {
+            for output in tx.output.iter() {
+                { consensus_encode!(output.value) }
+                { output.script_pubkey.as_bytes().to_vec() }
+            }
+            { nonce.to_le_bytes().to_vec() }
+            { HashPowGadget::push_min_leading_zeros_hint(&hash, self.min_leading_zeros, self.order) }
+            { preimage[..SIGHASH_PREFIX_SIZE].to_vec() }
+            { preimage[SIGHASH_PREFIX_SIZE + 32..].to_vec() }
+            { challenge[..31].to_vec() }
+            { challenge[31] }
+        })
+    }
+
+    /// The prefix of a BIP-340 tagged hash, which is the SHA256 of the tag twice.
+    fn tag(tag: &[u8]) -> Vec<u8> {
+        let tag_hash = Sha256::digest(tag);
+        [tag_hash, tag_hash].concat()
+    }
+
+    /// Check that the nonce satisfies the gate for a spending transaction with `num_outputs`
+    /// outputs, and that these are the outputs of the spending transaction, using a hint.
+    ///
+    /// input:
+    ///     (nothing, the outputs, the nonce, and the sighash preimage come from the hint of
+    ///     [`Self::push_hint`])
+    ///
+    /// output:
+    ///     (nothing, the gadget fails unless the nonce meets the gate for the outputs and the
+    ///     outputs are those that the signature of the spending transaction commits to)
+    pub fn script(&self, num_outputs: usize, hint: &HintSource) -> Script {
+        assert!(num_outputs > 0);
+
+        script! {
+            { self.commitment.clone() }
+
+            for i in 0..num_outputs {
+                { hint.pull(1 + 2 * i, 0) }
+                { Step1AmountGadget::from_provided() }
+                { hint.pull(2 + 2 * i, 0) }
+                { Step2ScriptPubKeyGadget::from_provided() }
+            }
+            for _ in 1..2 * num_outputs {
+                OP_CAT
+            }
+
+            // stack: commitment, outputs
+            OP_TUCK OP_CAT
+
+            // stack: outputs, commitment || outputs
+            { hint.pull(2, 0) }
+            OP_SIZE 8 OP_EQUALVERIFY
+            OP_CAT
+
+            OP_SHA256 OP_SHA256
+            { HashPowGadget::check_min_leading_zeros_above(self.min_leading_zeros, self.order, hint, 1) }
+
+            // stack: outputs
+            OP_SHA256
+            { hint.pull(1, 0) }
+            OP_SIZE { SIGHASH_PREFIX_SIZE } OP_EQUALVERIFY
+            OP_SWAP OP_CAT
+            { hint.pull(1, 0) }
+            OP_SIZE { SIGHASH_SUFFIX_SIZE } OP_EQUALVERIFY
+            OP_CAT
+
+            // stack: sighash preimage
+            { Self::tag(b"TapSighash") } OP_SWAP OP_CAT OP_SHA256
+            { [Self::tag(b"BIP0340/challenge"), GENERATOR_X.to_vec(), GENERATOR_X.to_vec()].concat() }
+            OP_SWAP OP_CAT OP_SHA256
+
+            // stack: challenge
+            { hint.pull(1, 0) }
+            OP_SIZE 31 OP_EQUALVERIFY
+            { hint.pull(2, 0) }
+            OP_DUP 0 255 OP_WITHIN OP_VERIFY
+            OP_2DUP { byte_to_byte_string() } OP_CAT
+            3 OP_ROLL OP_EQUALVERIFY
+
+            // the signature is the generator and the challenge plus one
+            OP_1ADD { byte_to_byte_string() } OP_CAT
+            { GENERATOR_X.to_vec() } OP_SWAP OP_CAT
+            { GENERATOR_X.to_vec() } OP_CHECKSIGVERIFY
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::consensus_encode;
+    use crate::hint::HintSource;
+    use crate::pow::gate::PowGate;
+    use crate::pow::{count_leading_zeros, HashByteOrder, HashPowGadget};
+    use crate::treepp::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::consensus::Encodable;
+    use bitcoin::hashes::Hash;
+    use bitcoin::taproot::LeafVersion;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TapLeafHash, Transaction, TxIn, TxOut, WScriptHash,
+        Witness,
+    };
+    use bitcoin_scriptexec::{Exec, ExecCtx, Options, TxTemplate};
+
+    fn example() -> (PowGate, Vec<TxOut>) {
+        let gate = PowGate {
+            commitment: b"pow gate".to_vec(),
+            min_leading_zeros: 12,
+            order: HashByteOrder::LittleEndian,
+        };
+        let outputs = vec![
+            TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+            },
+            TxOut {
+                value: Amount::from_sat(4_321),
+                script_pubkey: ScriptBuf::new_op_return([0x42u8; 20]),
+            },
+        ];
+        (gate, outputs)
+    }
+
+    /// A transaction with the outputs that spends the gate, and the output that it spends.
+    fn spending_tx(outputs: &[TxOut], lock_time: u32) -> (Transaction, Vec<TxOut>) {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: outputs.to_vec(),
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(200_000),
+            script_pubkey: ScriptBuf::from_bytes([vec![0x51, 0x20], vec![0x37; 32]].concat()),
+        }];
+        (tx, prevouts)
+    }
+
+    /// Run the script as the tapscript leaf `leaf_hash` spent by the first input.
+    fn execute_in_tx(
+        script: Script,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        leaf_hash: TapLeafHash,
+    ) -> bool {
+        let mut exec = Exec::new(
+            ExecCtx::Tapscript,
+            Options::default(),
+            TxTemplate {
+                tx: tx.clone(),
+                prevouts: prevouts.to_vec(),
+                input_idx: 0,
+                taproot_annex_scriptleaf: Some((leaf_hash, None)),
+            },
+            script,
+            vec![],
+        )
+        .unwrap();
+        loop {
+            if let Err(res) = exec.exec_next() {
+                return res.success;
+            }
+        }
+    }
+
+    #[test]
+    fn test_grind() {
+        let (gate, outputs) = example();
+
+        let nonce = gate.grind(&outputs, 0, 1 << 20).unwrap();
+        assert!(count_leading_zeros(&gate.hash(&outputs, nonce), gate.order) >= 12);
+        for earlier in 0..nonce {
+            assert!(count_leading_zeros(&gate.hash(&outputs, earlier), gate.order) < 12);
+        }
+
+        assert_eq!(gate.grind(&outputs, nonce, 1), Some(nonce));
+        assert_eq!(gate.grind(&outputs, nonce + 1, 0), None);
+    }
+
+    #[test]
+    fn test_pow_gate() {
+        let (gate, outputs) = example();
+        let nonce = gate.grind(&outputs, 0, 1 << 20).unwrap();
+
+        for hint in [
+            HintSource::default(),
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            let locking_script = script! {
+                { gate.script(outputs.len(), &hint) }
+                OP_TRUE
+            };
+            let leaf_hash = TapLeafHash::from_script(&locking_script, LeafVersion::TapScript);
+
+            // about one transaction in 256 has to change its lock time
+            let (tx, prevouts, witness) = (0..)
+                .find_map(|lock_time| {
+                    let (tx, prevouts) = spending_tx(&outputs, lock_time);
+                    let witness = gate.push_hint(&tx, &prevouts, 0, leaf_hash, nonce).ok()?;
+                    Some((tx, prevouts, witness))
+                })
+                .unwrap();
+
+            let script = script! {
+                { hint.push(witness) }
+                { locking_script }
+            };
+            assert!(execute_in_tx(script, &tx, &prevouts, leaf_hash));
+        }
+
+        let locking_script = script! {
+            { gate.script(outputs.len(), &HintSource::default()) }
+            OP_TRUE
+        };
+        let leaf_hash = TapLeafHash::from_script(&locking_script, LeafVersion::TapScript);
+        let (tx, prevouts, lock_time) = (0..)
+            .find_map(|lock_time| {
+                let (tx, prevouts) = spending_tx(&outputs, lock_time);
+                gate.push_hint(&tx, &prevouts, 0, leaf_hash, nonce)
+                    .ok()
+                    .map(|_| (tx, prevouts, lock_time))
+            })
+            .unwrap();
+
+        // a nonce that does not satisfy the gate
+        let bad_nonce = (nonce + 1..)
+            .find(|n| count_leading_zeros(&gate.hash(&outputs, *n), gate.order) < 12)
+            .unwrap();
+        let script = script! {
+            { gate.push_hint(&tx, &prevouts, 0, leaf_hash, bad_nonce).unwrap() }
+            { locking_script.clone() }
+        };
+        assert!(!execute_in_tx(script, &tx, &prevouts, leaf_hash));
+
+        // the nonce is bound to the outputs
+        let mut other_outputs = outputs.clone();
+        other_outputs[1].value = Amount::from_sat(4_322);
+        assert!(count_leading_zeros(&gate.hash(&other_outputs, nonce), gate.order) < 12);
+
+        let (other_tx, _) = spending_tx(&other_outputs, lock_time);
+        let script = script! {
+            for output in other_outputs.iter() {
+                { consensus_encode!(output.value) }
+                { output.script_pubkey.as_bytes().to_vec() }
+            }
+            { nonce.to_le_bytes().to_vec() }
+            { HashPowGadget::push_min_leading_zeros_hint(&gate.hash(&outputs, nonce), 12, gate.order) }
+            { locking_script.clone() }
+        };
+        assert!(!execute_in_tx(script, &other_tx, &prevouts, leaf_hash));
+
+        // the hinted outputs, for which the nonce is ground, must be the outputs of the spending
+        // transaction
+        let witness = gate.push_hint(&tx, &prevouts, 0, leaf_hash, nonce).unwrap();
+        let script = script! {
+            { witness }
+            { locking_script.clone() }
+        };
+        assert!(execute_in_tx(script.clone(), &tx, &prevouts, leaf_hash));
+        assert!(!execute_in_tx(script, &other_tx, &prevouts, leaf_hash));
+
+        // the gate only accepts the sighash of its own leaf
+        let other_leaf_hash = TapLeafHash::from_script(
+            &script! { { gate.script(outputs.len(), &HintSource::AltStack) } OP_TRUE },
+            LeafVersion::TapScript,
+        );
+        let script = script! {
+            { gate.push_hint(&tx, &prevouts, 0, leaf_hash, nonce).unwrap() }
+            { locking_script.clone() }
+        };
+        assert!(!execute_in_tx(script, &tx, &prevouts, other_leaf_hash));
+    }
+}
@@ -0,0 +1,65 @@
+mod bitcoin_script;
+pub use bitcoin_script::*;
+
+pub mod gate;
+
+/// The order in which the 32 bytes of a hash are read as a number for counting leading zeros.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashByteOrder {
+    /// The last byte is the most significant one, as in block hashes and txids.
+    LittleEndian,
+    /// The first byte is the most significant one.
+    BigEndian,
+}
+
+impl HashByteOrder {
+    /// Return the bytes with the most significant byte first.
+    pub fn to_be_bytes(&self, hash: &[u8; 32]) -> [u8; 32] {
+        let mut bytes = *hash;
+        if *self == HashByteOrder::LittleEndian {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+/// Count the number of leading zero bits of a hash, read in the given byte order.
+pub fn count_leading_zeros(hash: &[u8; 32], order: HashByteOrder) -> usize {
+    let bytes = order.to_be_bytes(hash);
+
+    let mut leading_zeros = 0usize;
+    for byte in bytes.iter() {
+        if *byte == 0u8 {
+            leading_zeros += 8;
+        } else {
+            leading_zeros += byte.leading_zeros() as usize;
+            break;
+        }
+    }
+    leading_zeros
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pow::{count_leading_zeros, HashByteOrder};
+
+    #[test]
+    fn test_count_leading_zeros() {
+        let mut hash = [0xffu8; 32];
+        assert_eq!(count_leading_zeros(&hash, HashByteOrder::LittleEndian), 0);
+        assert_eq!(count_leading_zeros(&hash, HashByteOrder::BigEndian), 0);
+
+        hash[31] = 0;
+        hash[30] = 0x1f;
+        assert_eq!(count_leading_zeros(&hash, HashByteOrder::LittleEndian), 11);
+        assert_eq!(count_leading_zeros(&hash, HashByteOrder::BigEndian), 0);
+
+        hash[0] = 0x01;
+        assert_eq!(count_leading_zeros(&hash, HashByteOrder::BigEndian), 7);
+
+        assert_eq!(
+            count_leading_zeros(&[0u8; 32], HashByteOrder::LittleEndian),
+            256
+        );
+    }
+}
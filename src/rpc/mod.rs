@@ -0,0 +1,450 @@
+use crate::spv::TxInclusionProof;
+use anyhow::{Error, Result};
+use bitcoin::base64::prelude::{Engine, BASE64_STANDARD};
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::{BlockHash, MerkleBlock, Transaction, Txid};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+/// A minimal JSON-RPC client for Bitcoin Core, which only supports the calls needed for sourcing
+/// the PoW SPV witness.
+pub struct RpcClient {
+    addr: String,
+    auth: Option<String>,
+    timeout: Duration,
+}
+
+/// Everything that the PoW SPV witness needs for a transaction.
+pub struct PowSpvProof {
+    pub tx: Transaction,
+    pub proof: TxInclusionProof,
+    pub header: Header,
+    pub height: u32,
+    pub next_headers: Vec<Header>,
+}
+
+impl RpcClient {
+    /// Create a client for the node at `addr`, which is in the form of `host:port`.
+    ///
+    /// Connecting, sending a request, and waiting for each read of the response time out after
+    /// 30 seconds by default, see [`Self::with_timeout`].
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            auth: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the timeout of connecting to the node and of each read and write, so that a node
+    /// that stops responding makes the call fail rather than hang.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_auth(mut self, user: &str, password: &str) -> Self {
+        self.auth = Some(BASE64_STANDARD.encode(format!("{}:{}", user, password)));
+        self
+    }
+
+    /// Authenticate with the `.cookie` file in the data directory of the node.
+    pub fn with_cookie_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let cookie = std::fs::read_to_string(path)?;
+        self.auth = Some(BASE64_STANDARD.encode(cookie.trim()));
+        Ok(self)
+    }
+
+    pub fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": method,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let mut request = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            self.addr,
+            body.len()
+        );
+        if let Some(auth) = &self.auth {
+            request.push_str(&format!("Authorization: Basic {}\r\n", auth));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        let mut stream = self.connect()?;
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        let response = String::from_utf8(response)?;
+
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| Error::msg("The RPC response is malformed."))?;
+        let status = head
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .ok_or_else(|| Error::msg("The RPC response is malformed."))?;
+
+        // Bitcoin Core reports RPC errors with a non-200 status but still a JSON body
+        let reply: Value = serde_json::from_str(body).map_err(|_| {
+            Error::msg(format!(
+                "The RPC call {} failed with status {}.",
+                method, status
+            ))
+        })?;
+
+        if !reply["error"].is_null() {
+            return Err(Error::msg(format!(
+                "The RPC call {} failed: {}",
+                method, reply["error"]
+            )));
+        }
+
+        Ok(reply["result"].clone())
+    }
+
+    /// Connect to the first address of the node that accepts within the timeout.
+    fn connect(&self) -> Result<TcpStream> {
+        let mut last_error = None;
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(match last_error {
+            Some(e) => e.into(),
+            None => Error::msg(format!("The address {} cannot be resolved.", self.addr)),
+        })
+    }
+
+    fn call_str(&self, method: &str, params: Value) -> Result<String> {
+        self.call(method, params)?
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| Error::msg(format!("The RPC call {} returned a non-string.", method)))
+    }
+
+    pub fn get_block_hash(&self, height: u32) -> Result<BlockHash> {
+        Ok(self.call_str("getblockhash", json!([height]))?.parse()?)
+    }
+
+    pub fn get_block_header(&self, hash: &BlockHash) -> Result<Header> {
+        let hex = self.call_str("getblockheader", json!([hash.to_string(), false]))?;
+        Ok(deserialize_hex(&hex)?)
+    }
+
+    /// Return the height of the block, or an error if it is not in the active chain.
+    pub fn get_block_height(&self, hash: &BlockHash) -> Result<u32> {
+        let header = self.call("getblockheader", json!([hash.to_string(), true]))?;
+        if header["confirmations"].as_i64().unwrap_or(-1) < 0 {
+            return Err(Error::msg("The block is not in the active chain."));
+        }
+        header["height"]
+            .as_u64()
+            .map(|height| height as u32)
+            .ok_or_else(|| Error::msg("The RPC call getblockheader returned no height."))
+    }
+
+    /// Fetch a transaction. Without `txindex`, the node can only find it with the block hash.
+    pub fn get_raw_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> Result<Transaction> {
+        let params = match block_hash {
+            Some(block_hash) => json!([txid.to_string(), false, block_hash.to_string()]),
+            None => json!([txid.to_string(), false]),
+        };
+        let hex = self.call_str("getrawtransaction", params)?;
+        Ok(deserialize_hex(&hex)?)
+    }
+
+    pub fn get_tx_out_proof(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> Result<MerkleBlock> {
+        let params = match block_hash {
+            Some(block_hash) => json!([[txid.to_string()], block_hash.to_string()]),
+            None => json!([[txid.to_string()]]),
+        };
+        let hex = self.call_str("gettxoutproof", params)?;
+        Ok(deserialize_hex(&hex)?)
+    }
+
+    /// Return the `n` headers that follow the given block in the active chain.
+    pub fn get_headers_after(&self, hash: &BlockHash, n: usize) -> Result<Vec<Header>> {
+        let height = self.get_block_height(hash)?;
+
+        let mut headers = vec![];
+        let mut prev_hash = *hash;
+        for i in 1..=n {
+            let header = self.get_block_header(&self.get_block_hash(height + i as u32)?)?;
+            if header.prev_blockhash != prev_hash {
+                return Err(Error::msg(
+                    "The chain was reorganized while fetching headers.",
+                ));
+            }
+            prev_hash = header.block_hash();
+            headers.push(header);
+        }
+        Ok(headers)
+    }
+
+    /// Gather the transaction, its inclusion proof, the header of the block that includes it,
+    /// and the `num_headers` headers after that block.
+    pub fn get_pow_spv_proof(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+        num_headers: usize,
+    ) -> Result<PowSpvProof> {
+        let merkle_block = self.get_tx_out_proof(txid, block_hash)?;
+        let header = merkle_block.header;
+        let block_hash = header.block_hash();
+
+        let proof = TxInclusionProof::construct_from_partial_merkle_tree(&merkle_block.txn, txid)?;
+        let tx = self.get_raw_transaction(txid, Some(&block_hash))?;
+        if tx.compute_txid() != *txid {
+            return Err(Error::msg("The node returned a different transaction."));
+        }
+        proof.verify_tx_inclusion(&tx, &header.merkle_root)?;
+
+        let height = self.get_block_height(&block_hash)?;
+        let next_headers = self.get_headers_after(&block_hash, num_headers)?;
+
+        Ok(PowSpvProof {
+            tx,
+            proof,
+            header,
+            height,
+            next_headers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rpc::RpcClient;
+    use crate::test_utils::mine_block;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::block::Header;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        Amount, Block, MerkleBlock, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+        TxOut, Txid, Witness,
+    };
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    fn txdata(num_txs: usize, seed: u32) -> Vec<Transaction> {
+        (0..num_txs)
+            .map(|i| Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::from_bytes(vec![4, seed as u8, i as u8, 0, 0]),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value: Amount::from_sat(1000 * i as u64),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            })
+            .collect()
+    }
+
+    fn respond(chain: &[Block], auth: &str, request: &str, body: &str) -> (u16, Value) {
+        if !request.contains(&format!("Authorization: Basic {}", auth)) {
+            return (401, Value::Null);
+        }
+
+        let request: Value = serde_json::from_str(body).unwrap();
+        let params = &request["params"];
+
+        let find_block = |hash: &Value| {
+            chain
+                .iter()
+                .position(|block| block.block_hash().to_string() == hash.as_str().unwrap())
+        };
+        let find_tx = |txid: &Value| {
+            chain.iter().position(|block| {
+                block
+                    .txdata
+                    .iter()
+                    .any(|tx| tx.compute_txid().to_string() == txid.as_str().unwrap())
+            })
+        };
+
+        let result = match request["method"].as_str().unwrap() {
+            "getblockhash" => chain
+                .get(params[0].as_u64().unwrap() as usize)
+                .map(|block| json!(block.block_hash().to_string())),
+            "getblockheader" => find_block(&params[0]).map(|height| {
+                if params[1].as_bool().unwrap() {
+                    json!({ "height": height, "confirmations": chain.len() - height })
+                } else {
+                    json!(serialize_hex(&chain[height].header))
+                }
+            }),
+            "getrawtransaction" => find_block(&params[2]).and_then(|height| {
+                chain[height]
+                    .txdata
+                    .iter()
+                    .find(|tx| tx.compute_txid().to_string() == params[0].as_str().unwrap())
+                    .map(|tx| json!(serialize_hex(tx)))
+            }),
+            "gettxoutproof" => find_tx(&params[0][0]).map(|height| {
+                let merkle_block = MerkleBlock::from_block_with_predicate(&chain[height], |txid| {
+                    txid.to_string() == params[0][0].as_str().unwrap()
+                });
+                json!(serialize_hex(&merkle_block))
+            }),
+            _ => None,
+        };
+
+        match result {
+            Some(result) => (
+                200,
+                json!({ "result": result, "error": null, "id": request["id"] }),
+            ),
+            None => (
+                500,
+                json!({
+                    "result": null,
+                    "error": { "code": -5, "message": "not found" },
+                    "id": request["id"]
+                }),
+            ),
+        }
+    }
+
+    fn start_mock_server(chain: Vec<Block>, auth: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(len) = line.strip_prefix("Content-Length: ") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, reply) =
+                    respond(&chain, &auth, &request, &String::from_utf8(body).unwrap());
+                let reply = if reply.is_null() {
+                    String::new()
+                } else {
+                    reply.to_string()
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                )
+                .unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_rpc_pow_spv_proof() {
+        let mut chain = vec![genesis_block(Network::Regtest)];
+        for i in 0..8 {
+            let block = mine_block(&chain.last().unwrap().header, 0, txdata(3 + i, i as u32));
+            chain.push(block);
+        }
+
+        let addr = start_mock_server(chain.clone(), "dXNlcjpwYXNz".to_string());
+        let client = RpcClient::new(&addr).with_auth("user", "pass");
+
+        let txid = chain[3].txdata[4].compute_txid();
+        let proof = client.get_pow_spv_proof(&txid, None, 4).unwrap();
+        assert_eq!(proof.tx, chain[3].txdata[4]);
+        assert_eq!(proof.header, chain[3].header);
+        assert_eq!(proof.height, 3);
+        assert_eq!(proof.proof.idx, 4);
+        assert_eq!(
+            proof.next_headers,
+            chain[4..8]
+                .iter()
+                .map(|block| block.header)
+                .collect::<Vec<Header>>()
+        );
+
+        // given the block hash
+        let proof = client
+            .get_pow_spv_proof(&txid, Some(&chain[3].block_hash()), 5)
+            .unwrap();
+        assert_eq!(proof.next_headers.len(), 5);
+
+        // not enough confirmations
+        assert!(client.get_pow_spv_proof(&txid, None, 6).is_err());
+
+        // unknown transaction
+        let unknown = Txid::all_zeros();
+        assert!(client.get_pow_spv_proof(&unknown, None, 1).is_err());
+
+        // wrong credentials
+        let client = RpcClient::new(&addr).with_auth("user", "wrong");
+        assert!(client.get_pow_spv_proof(&txid, None, 1).is_err());
+    }
+
+    #[test]
+    fn test_rpc_timeout() {
+        // a node that accepts the connection but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let mut streams = vec![];
+            for stream in listener.incoming() {
+                streams.push(stream);
+            }
+        });
+
+        let client = RpcClient::new(&addr).with_timeout(Duration::from_millis(200));
+        let start = Instant::now();
+        assert!(client.get_block_hash(0).is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
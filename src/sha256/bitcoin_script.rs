@@ -0,0 +1,553 @@
+use crate::hint::HintSource;
+use crate::sha256::{SHA256_IV, SHA256_K};
+use crate::treepp::*;
+use crate::utils::byte_to_byte_string;
+
+/// Gadget for the SHA-256 compression function in script, for messages that do not fit in a
+/// stack element and therefore cannot go through `OP_SHA256`.
+///
+/// Script numbers are signed and at most 32 bits, and tapscript has no bitwise opcodes, so a
+/// 32-bit word is held as two u16 limbs, high limb first, for the additions, and as 32 bits,
+/// most significant first, for the bitwise functions. A state is the 8 words, first word deepest.
+pub struct Sha256Gadget;
+
+impl Sha256Gadget {
+    /// Push the initial state of SHA-256.
+    pub fn push_initial_state() -> Script {
+        script! {
+            for word in SHA256_IV {
+                { (word >> 16) as usize }
+                { (word & 0xffff) as usize }
+            }
+        }
+    }
+
+    /// Push the hint for [`Self::compress`], which is the block as big-endian u16 limbs.
+    pub fn push_compress_hint(block: &[u8]) -> Script {
+        assert_eq!(block.len(), 64);
+        script! {
+            for limb in block.chunks(2) {
+                { u16::from_be_bytes([limb[0], limb[1]]) as usize }
+            }
+        }
+    }
+
+    /// Compress a 64-byte block into the state.
+    ///
+    /// The words of the block are pulled as limbs and checked against the block, and the 64
+    /// rounds then work on the bits of the working variables, keeping only the 16 words of the
+    /// message schedule that are still needed. The script is about 300 KB.
+    ///
+    /// hint:
+    ///   [each u16 limb of the block, as a big-endian number, in order]
+    ///
+    /// input:
+    ///   state
+    ///   block
+    ///
+    /// output:
+    ///   state
+    ///
+    pub fn compress(hint: &HintSource) -> Script {
+        Compression::default().run(hint)
+    }
+
+    /// Turn the state into the 32-byte digest, which is its words in big-endian.
+    ///
+    /// input:
+    ///   state
+    ///
+    /// output:
+    ///   digest
+    ///
+    pub fn state_to_digest() -> Script {
+        script! {
+            { limb_to_byte_string() }
+            for _ in 1..16 {
+                OP_SWAP { limb_to_byte_string() } OP_SWAP OP_CAT
+            }
+        }
+    }
+}
+
+/// Split a u16 limb into its high and low bytes, as numbers.
+fn limb_to_bytes() -> Script {
+    script! {
+        0 OP_SWAP
+        for i in (8..16usize).rev() {
+            OP_SWAP OP_DUP OP_ADD OP_SWAP
+            OP_DUP { 1usize << i } OP_GREATERTHANOREQUAL OP_IF
+                { 1usize << i } OP_SUB
+                OP_SWAP OP_1ADD OP_SWAP
+            OP_ENDIF
+        }
+    }
+}
+
+/// Turn a u16 limb into its two bytes, high byte first.
+fn limb_to_byte_string() -> Script {
+    script! {
+        { limb_to_bytes() }
+        { byte_to_byte_string() }
+        OP_SWAP
+        { byte_to_byte_string() }
+        OP_SWAP OP_CAT
+    }
+}
+
+/// Split a u16 limb into its bits, the most significant deepest.
+fn limb_to_bits() -> Script {
+    script! {
+        for i in (1..16usize).rev() {
+            OP_DUP { 1usize << i } OP_GREATERTHANOREQUAL
+            OP_DUP OP_ROT OP_SWAP
+            OP_IF
+                { 1usize << i } OP_SUB
+            OP_ENDIF
+        }
+    }
+}
+
+/// Copy the element at the given depth to the top.
+fn pick_at(depth: usize) -> Script {
+    match depth {
+        0 => script! { OP_DUP },
+        1 => script! { OP_OVER },
+        _ => script! { { depth } OP_PICK },
+    }
+}
+
+/// An element that the compression keeps on the main stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Item {
+    /// A bit of a word, 0 being the least significant.
+    Bit(usize, usize),
+    /// The high limb of a word.
+    High(usize),
+    /// The low limb of a word.
+    Low(usize),
+    Block,
+    Temp,
+}
+
+/// The generator of the compression script, which follows where each word is on the main stack
+/// so that the script can pick it by name as the stack changes.
+#[derive(Default)]
+struct Compression {
+    stack: Vec<Item>,
+    script: Vec<u8>,
+    num_words: usize,
+}
+
+impl Compression {
+    fn run(mut self, hint: &HintSource) -> Script {
+        let state: [usize; 8] = std::array::from_fn(|_| self.new_word());
+        for word in state {
+            self.stack.extend([Item::High(word), Item::Low(word)]);
+        }
+        self.stack.push(Item::Block);
+
+        // pull the block as limbs, and check that they make it up
+        let mut w = (0..16).map(|_| self.new_word()).collect::<Vec<usize>>();
+        for word in w.iter() {
+            for limb in [Item::High(*word), Item::Low(*word)] {
+                let main_above = self.stack.len();
+                self.op(hint.pull(main_above, 0), 0, 1);
+                self.op(script! { OP_DUP 0 65536 OP_WITHIN OP_VERIFY }, 1, 1);
+                self.name(&[limb]);
+            }
+        }
+        self.op(script! { OP_PUSHBYTES_0 }, 0, 1);
+        for word in w.iter() {
+            for limb in [Item::High(*word), Item::Low(*word)] {
+                self.pick(limb);
+                self.op(script! { { limb_to_byte_string() } OP_CAT }, 2, 1);
+            }
+        }
+        self.roll(Item::Block);
+        self.op(script! { OP_EQUALVERIFY }, 2, 0);
+
+        // the working variables start as the bits of the state
+        for word in state {
+            self.split_bits(word, false);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+
+        // the last round that reads each word of the message schedule
+        let last_use = |j: usize| {
+            [j + 2, j + 7, j + 15, j + 16]
+                .into_iter()
+                .filter(|t| (16..64).contains(t))
+                .fold(j, usize::max)
+        };
+
+        for t in 0..64 {
+            if t >= 16 {
+                let s1 = self.small_sigma(w[t - 2], last_use(t - 2) == t, [17, 19, 10]);
+                let s0 = self.small_sigma(w[t - 15], last_use(t - 15) == t, [7, 18, 3]);
+                let wt = self.add(&[
+                    (s1, true),
+                    (w[t - 7], last_use(t - 7) == t),
+                    (s0, true),
+                    (w[t - 16], last_use(t - 16) == t),
+                ]);
+                w.push(wt);
+            }
+
+            let big_s1 = self.compose(|s, j| s.rotations(e, j, [6, 11, 25], false));
+            let ch = self.compose(|s, j| s.ch(e, f, g, j));
+            let old_h = self.compose(|s, j| s.roll(Item::Bit(h, j)));
+            let k = self.push_word(SHA256_K[t]);
+            let t1 = self.add(&[
+                (old_h, true),
+                (big_s1, true),
+                (ch, true),
+                (k, true),
+                (w[t], last_use(t) == t),
+            ]);
+
+            let big_s0 = self.compose(|s, j| s.rotations(a, j, [2, 13, 22], false));
+            let maj = self.compose(|s, j| s.maj(a, b, c, j));
+            let new_a = self.add(&[(t1, false), (big_s0, true), (maj, true)]);
+
+            let old_d = self.compose(|s, j| s.roll(Item::Bit(d, j)));
+            let new_e = self.add(&[(old_d, true), (t1, true)]);
+
+            self.split_bits(new_e, true);
+            self.split_bits(new_a, true);
+            (h, g, f, e, d, c, b, a) = (g, f, e, new_e, c, b, a, new_a);
+        }
+
+        // add the working variables to the state
+        for (word, var) in state.into_iter().zip([a, b, c, d, e, f, g, h]) {
+            let var = self.compose(|s, j| s.roll(Item::Bit(var, j)));
+            self.add(&[(word, true), (var, true)]);
+        }
+        assert_eq!(self.stack.len(), 16);
+
+        Script::from_bytes(self.script)
+    }
+
+    fn new_word(&mut self) -> usize {
+        self.num_words += 1;
+        self.num_words - 1
+    }
+
+    fn depth(&self, item: Item) -> usize {
+        let position = self.stack.iter().rposition(|x| *x == item).unwrap();
+        self.stack.len() - 1 - position
+    }
+
+    /// Run a script that consumes the `num_in` top elements and leaves `num_out` new ones.
+    fn op(&mut self, script: Script, num_in: usize, num_out: usize) {
+        self.script.extend_from_slice(script.as_bytes());
+        self.stack.truncate(self.stack.len() - num_in);
+        self.stack.extend(std::iter::repeat_n(Item::Temp, num_out));
+    }
+
+    /// Name the top elements, the deepest first.
+    fn name(&mut self, items: &[Item]) {
+        let start = self.stack.len() - items.len();
+        self.stack[start..].copy_from_slice(items);
+    }
+
+    /// Copy an element to the top.
+    fn pick(&mut self, item: Item) {
+        let depth = self.depth(item);
+        self.op(pick_at(depth), 0, 1);
+    }
+
+    /// Move an element to the top.
+    fn roll(&mut self, item: Item) {
+        let depth = self.depth(item);
+        self.script.extend_from_slice(
+            match depth {
+                0 => script! {},
+                1 => script! { OP_SWAP },
+                2 => script! { OP_ROT },
+                _ => script! { { depth } OP_ROLL },
+            }
+            .as_bytes(),
+        );
+        self.stack.remove(self.stack.len() - 1 - depth);
+        self.stack.push(Item::Temp);
+    }
+
+    /// Bring an element to the top, moving it on its last use and copying it otherwise.
+    fn fetch(&mut self, item: Item, last: bool) {
+        if last {
+            self.roll(item);
+        } else {
+            self.pick(item);
+        }
+    }
+
+    /// Push a constant word.
+    fn push_word(&mut self, value: u32) -> usize {
+        self.op(
+            script! {
+                { (value >> 16) as usize }
+                { (value & 0xffff) as usize }
+            },
+            0,
+            2,
+        );
+        let word = self.new_word();
+        self.name(&[Item::High(word), Item::Low(word)]);
+        word
+    }
+
+    /// Split the limbs of a word into its bits, moving the limbs on their last use.
+    fn split_bits(&mut self, word: usize, last: bool) {
+        for (limb, offset) in [(Item::High(word), 16), (Item::Low(word), 0)] {
+            self.fetch(limb, last);
+            self.op(limb_to_bits(), 1, 16);
+            let bits = (0..16)
+                .rev()
+                .map(|j| Item::Bit(word, offset + j))
+                .collect::<Vec<Item>>();
+            self.name(&bits);
+        }
+    }
+
+    /// Compose a new word into its limbs, most significant bit first, where `bit` brings its
+    /// bit `j` to the top.
+    fn compose(&mut self, bit: impl Fn(&mut Self, usize)) -> usize {
+        let word = self.new_word();
+        for (limb, offset) in [(Item::High(word), 16), (Item::Low(word), 0)] {
+            for j in (0..16).rev() {
+                if j < 15 {
+                    self.op(script! { OP_DUP OP_ADD }, 1, 1);
+                }
+                bit(self, offset + j);
+                if j < 15 {
+                    self.op(script! { OP_ADD }, 2, 1);
+                }
+            }
+            self.name(&[limb]);
+        }
+        word
+    }
+
+    /// Drop the bits of a word, which sit right below the two limbs on top.
+    fn drop_bits(&mut self, word: usize) {
+        for j in 0..32 {
+            assert_eq!(self.depth(Item::Bit(word, j)), 2 + j);
+        }
+        self.script.extend_from_slice(
+            script! {
+                OP_TOALTSTACK OP_TOALTSTACK
+                for _ in 0..16 {
+                    OP_2DROP
+                }
+                OP_FROMALTSTACK OP_FROMALTSTACK
+            }
+            .as_bytes(),
+        );
+        let len = self.stack.len();
+        self.stack.drain(len - 34..len - 2);
+    }
+
+    /// Add words modulo 2^32 into a new word, moving each of them on its last use.
+    fn add(&mut self, words: &[(usize, bool)]) -> usize {
+        for (i, (word, last)) in words.iter().enumerate() {
+            self.fetch(Item::Low(*word), *last);
+            self.fetch(Item::High(*word), *last);
+            if i > 0 {
+                self.op(
+                    script! { OP_ROT OP_ADD OP_SWAP OP_ROT OP_ADD OP_SWAP },
+                    4,
+                    2,
+                );
+            }
+        }
+
+        // each sum is below 2^16 times the number of words, and so is each carry below it
+        let carries = words.len() - 1;
+        self.op(
+            script! {
+                // stack: sum of the low limbs, sum of the high limbs
+                OP_SWAP
+                for _ in 0..carries {
+                    OP_DUP 65536 OP_GREATERTHANOREQUAL OP_IF
+                        65536 OP_SUB
+                        OP_SWAP OP_1ADD OP_SWAP
+                    OP_ENDIF
+                }
+                OP_SWAP
+                for _ in 0..carries {
+                    OP_DUP 65536 OP_GREATERTHANOREQUAL OP_IF
+                        65536 OP_SUB
+                    OP_ENDIF
+                }
+                OP_SWAP
+            },
+            2,
+            2,
+        );
+        let word = self.new_word();
+        self.name(&[Item::High(word), Item::Low(word)]);
+        word
+    }
+
+    /// Bring the XOR of the bits to the top.
+    fn xor(&mut self, bits: &[Item]) {
+        self.pick(bits[0]);
+        for bit in bits[1..].iter() {
+            self.pick(*bit);
+            self.op(script! { OP_NUMNOTEQUAL }, 2, 1);
+        }
+    }
+
+    /// Bring the bit `j` of `ROTR(x, r[0]) ^ ROTR(x, r[1]) ^ ROTR(x, r[2])` to the top, or of
+    /// `SHR(x, r[2])` as the last term if `shift`.
+    fn rotations(&mut self, x: usize, j: usize, r: [usize; 3], shift: bool) {
+        let mut bits = vec![Item::Bit(x, (j + r[0]) % 32), Item::Bit(x, (j + r[1]) % 32)];
+        if !shift || j + r[2] < 32 {
+            bits.push(Item::Bit(x, (j + r[2]) % 32));
+        }
+        self.xor(&bits);
+    }
+
+    /// Bring the bit `j` of `Ch(e, f, g)`, which is the bit of `f` if that of `e` is set and
+    /// the bit of `g` otherwise, to the top.
+    fn ch(&mut self, e: usize, f: usize, g: usize, j: usize) {
+        self.pick(Item::Bit(e, j));
+        self.stack.pop();
+        let (depth_f, depth_g) = (self.depth(Item::Bit(f, j)), self.depth(Item::Bit(g, j)));
+        self.op(
+            script! {
+                OP_IF
+                    { pick_at(depth_f) }
+                OP_ELSE
+                    { pick_at(depth_g) }
+                OP_ENDIF
+            },
+            0,
+            1,
+        );
+    }
+
+    /// Bring the bit `j` of `Maj(a, b, c)`, which is set if two of the bits are, to the top.
+    fn maj(&mut self, a: usize, b: usize, c: usize, j: usize) {
+        self.pick(Item::Bit(a, j));
+        self.pick(Item::Bit(b, j));
+        self.pick(Item::Bit(c, j));
+        self.op(script! { OP_ADD OP_ADD 2 OP_GREATERTHANOREQUAL }, 3, 1);
+    }
+
+    /// Compute `σ0` or `σ1` of a word of the message schedule, with the rotations and the
+    /// shift in `r`, into a new word.
+    fn small_sigma(&mut self, x: usize, last: bool, r: [usize; 3]) -> usize {
+        self.split_bits(x, last);
+        let sigma = self.compose(|s, j| s.rotations(x, j, r, true));
+        self.drop_bits(x);
+        sigma
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hint::HintSource;
+    use crate::sha256::{sha256_pad, Sha256Gadget};
+    use crate::treepp::*;
+    use bitcoin::hashes::{sha256, Hash, HashEngine};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_compress() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let state: [u32; 8] = prng.gen();
+        let mut block = [0u8; 64];
+        prng.fill(&mut block[..]);
+
+        let mut midstate = [0u8; 32];
+        for (bytes, word) in midstate.chunks_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        let mut engine = sha256::HashEngine::from_midstate(sha256::Midstate(midstate), 64);
+        engine.input(&block);
+        let expected = engine
+            .midstate()
+            .to_byte_array()
+            .chunks(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<u32>>();
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            let compress = Sha256Gadget::compress(&hint);
+
+            let script = script! {
+                { b"unrelated".to_vec() }
+                { hint.push(Sha256Gadget::push_compress_hint(&block)) }
+                for word in state {
+                    { (word >> 16) as usize }
+                    { (word & 0xffff) as usize }
+                }
+                { block.to_vec() }
+                { compress }
+                for word in expected.iter().rev() {
+                    { (word & 0xffff) as usize } OP_EQUALVERIFY
+                    { (word >> 16) as usize } OP_EQUALVERIFY
+                }
+                { b"unrelated".to_vec() } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // the limbs must make up the block
+        let mut other = block;
+        other[0] ^= 1;
+        let script = script! {
+            { Sha256Gadget::push_compress_hint(&other) }
+            for word in state {
+                { (word >> 16) as usize }
+                { (word & 0xffff) as usize }
+            }
+            { block.to_vec() }
+            { Sha256Gadget::compress(&HintSource::default()) }
+            for _ in 0..8 {
+                OP_2DROP
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_sha256() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        // a message of two blocks with the padding
+        let mut message = [0u8; 100];
+        prng.fill(&mut message[..]);
+        let padded = sha256_pad(&message);
+        assert_eq!(padded.len(), 128);
+
+        let script = script! {
+            for block in padded.chunks(64) {
+                { Sha256Gadget::push_compress_hint(block) }
+            }
+            { Sha256Gadget::push_initial_state() }
+            for block in padded.chunks(64) {
+                { block.to_vec() }
+                { Sha256Gadget::compress(&HintSource::default()) }
+            }
+            { Sha256Gadget::state_to_digest() }
+            { sha256::Hash::hash(&message).to_byte_array().to_vec() }
+            OP_EQUAL
+        };
+
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+}
@@ -0,0 +1,575 @@
+use crate::consensus_encode;
+use crate::network::NetworkParams;
+use crate::spv::TxInclusionProof;
+use anyhow::{Error, Result};
+use bitcoin::block::Header;
+use bitcoin::consensus::{Decodable, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::{
+    OP_CHECKMULTISIG, OP_CHECKSIG, OP_PUSHNUM_1, OP_PUSHNUM_16, OP_RETURN,
+};
+use bitcoin::opcodes::OP_0;
+use bitcoin::script::{Builder, Instruction, PushBytesBuf};
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1};
+use bitcoin::sighash::SighashCache;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, Amount, Block, OutPoint, PublicKey, Script, ScriptBuf, Sequence, Transaction, TxIn,
+    TxMerkleNode, TxOut, Witness,
+};
+
+/// The 4 bytes that start the push of the signet solution in the coinbase.
+pub const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// The start of the script pubkey of the witness commitment in the coinbase.
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+/// A proof that a signet block is authorized by the signers of the network (BIP325), which
+/// consists of the header, the coinbase, and the inclusion proof of the coinbase.
+///
+/// The signet solution is a push in the witness commitment output of the coinbase that starts
+/// with [`SIGNET_HEADER`], followed by the scriptSig and the witness that satisfy the challenge
+/// of the network. They spend a virtual transaction whose output is locked by the challenge and
+/// whose input commits to the header without the nonce and the bits, and to the merkle root of
+/// the block with the solution taken out of the coinbase, so only the left-most path of the
+/// merkle tree is needed to check it.
+///
+/// This only checks the signature, as the proof of work of signet is checked like on the other
+/// networks, such as with [`NetworkParams::check_header`].
+pub struct SignetBlockProof {
+    pub header: Header,
+    pub coinbase: Transaction,
+    pub proof: TxInclusionProof,
+}
+
+impl SignetBlockProof {
+    pub fn construct(block: &Block) -> Result<Self> {
+        let coinbase = block
+            .txdata
+            .first()
+            .filter(|tx| tx.is_coinbase())
+            .cloned()
+            .ok_or_else(|| Error::msg("The block does not start with a coinbase."))?;
+        let proof = TxInclusionProof::construct_from_block(block, &coinbase.compute_txid())?;
+
+        Ok(Self {
+            header: block.header,
+            coinbase,
+            proof,
+        })
+    }
+
+    /// Return the coinbase with the signet solution taken out, and the solution, if any.
+    ///
+    /// Like Bitcoin Core, only the first push in the last witness commitment output that starts
+    /// with [`SIGNET_HEADER`] and has more bytes after it is the solution, and only the header of
+    /// this push is left in the coinbase.
+    fn clear_solution(&self) -> Result<(Transaction, Option<Vec<u8>>)> {
+        let mut coinbase = self.coinbase.clone();
+        let output = coinbase
+            .output
+            .iter_mut()
+            .rev()
+            .find(|output| {
+                output.script_pubkey.len() >= 38
+                    && output.script_pubkey.as_bytes()[..6] == WITNESS_COMMITMENT_HEADER
+            })
+            .ok_or_else(|| Error::msg("The coinbase has no witness commitment."))?;
+
+        let mut builder = Builder::new();
+        let mut solution = None;
+        for instruction in output.script_pubkey.instructions() {
+            match instruction.map_err(|_| Error::msg("The witness commitment cannot be parsed."))? {
+                Instruction::PushBytes(bytes) => {
+                    let bytes = bytes.as_bytes();
+                    if solution.is_none()
+                        && bytes.len() > SIGNET_HEADER.len()
+                        && bytes[..SIGNET_HEADER.len()] == SIGNET_HEADER
+                    {
+                        solution = Some(bytes[SIGNET_HEADER.len()..].to_vec());
+                        builder = builder.push_slice(SIGNET_HEADER);
+                    } else {
+                        builder = builder.push_slice(PushBytesBuf::try_from(bytes.to_vec())?);
+                    }
+                }
+                Instruction::Op(opcode) => builder = builder.push_opcode(opcode),
+            }
+        }
+
+        if solution.is_some() {
+            output.script_pubkey = builder.into_script();
+        }
+        Ok((coinbase, solution))
+    }
+
+    /// Return the scriptSig and the witness of the signet solution, or `None` if the block has
+    /// none, which only a challenge that needs no signature accepts.
+    pub fn solution(&self) -> Result<Option<(ScriptBuf, Witness)>> {
+        let Some(solution) = self.clear_solution()?.1 else {
+            return Ok(None);
+        };
+
+        let mut reader = solution.as_slice();
+        let script_sig = ScriptBuf::consensus_decode(&mut reader)?;
+        let witness = Witness::consensus_decode(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(Error::msg("The signet solution has extra bytes."));
+        }
+        Ok(Some((script_sig, witness)))
+    }
+
+    /// Return the virtual transaction locked by the challenge and the transaction that spends
+    /// it with the signet solution, which is what the signers sign.
+    pub fn signet_txs(&self, challenge: &Script) -> Result<(Transaction, Transaction)> {
+        self.proof
+            .verify_tx_inclusion(&self.coinbase, &self.header.merkle_root)?;
+        if self.proof.idx != 0 {
+            return Err(Error::msg("The proof is not for the coinbase."));
+        }
+
+        let (modified_coinbase, _) = self.clear_solution()?;
+        let signet_merkle_root = self
+            .proof
+            .compute_merkle_root(&TxMerkleNode::from_byte_array(
+                modified_coinbase.compute_txid().to_byte_array(),
+            ))?;
+
+        let mut block_data = vec![];
+        block_data.extend(consensus_encode!(self.header.version));
+        block_data.extend(consensus_encode!(self.header.prev_blockhash));
+        block_data.extend(consensus_encode!(signet_merkle_root));
+        block_data.extend(consensus_encode!(self.header.time));
+
+        let to_spend = Transaction {
+            version: Version(0),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new()
+                    .push_opcode(OP_0)
+                    .push_slice(PushBytesBuf::try_from(block_data)?)
+                    .into_script(),
+                sequence: Sequence::ZERO,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: challenge.to_owned(),
+            }],
+        };
+
+        let (script_sig, witness) = self.solution()?.unwrap_or_default();
+        let to_sign = Transaction {
+            version: Version(0),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+                script_sig,
+                sequence: Sequence::ZERO,
+                witness,
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+            }],
+        };
+
+        Ok((to_spend, to_sign))
+    }
+
+    /// Verify the signet solution against the challenge of the network.
+    ///
+    /// Only the challenges of the form `<m> <pubkey>... <n> OP_CHECKMULTISIG`, which the default
+    /// signet uses, and `<pubkey> OP_CHECKSIG` are supported, as there is no script interpreter
+    /// to check other challenges with. For those, [`Self::signet_txs`] gives the transactions to
+    /// check.
+    pub fn verify(&self, params: &NetworkParams) -> Result<()> {
+        let challenge = params
+            .signet_challenge
+            .as_ref()
+            .ok_or_else(|| Error::msg("The network has no signet challenge."))?;
+        let (pubkeys, threshold) = parse_challenge(challenge)?;
+
+        let (to_spend, to_sign) = self.signet_txs(challenge)?;
+        if !to_sign.input[0].witness.is_empty() {
+            return Err(Error::msg(
+                "The signet solution of a bare challenge cannot have a witness.",
+            ));
+        }
+
+        let mut pushes = vec![];
+        for instruction in to_sign.input[0].script_sig.instructions() {
+            match instruction.map_err(|_| Error::msg("The scriptSig cannot be parsed."))? {
+                Instruction::PushBytes(bytes) => pushes.push(bytes.as_bytes().to_vec()),
+                Instruction::Op(_) => {
+                    return Err(Error::msg(
+                        "The scriptSig of the signet solution is not push-only.",
+                    ))
+                }
+            }
+        }
+
+        // OP_CHECKMULTISIG pops one more element, which must be empty
+        let signatures = match threshold {
+            Some(threshold) => {
+                if pushes.len() != threshold + 1 || !pushes[0].is_empty() {
+                    return Err(Error::msg(format!(
+                        "The signet solution must have an empty element and {} signatures.",
+                        threshold
+                    )));
+                }
+                &pushes[1..]
+            }
+            None => {
+                if pushes.len() != 1 {
+                    return Err(Error::msg("The signet solution must have one signature."));
+                }
+                &pushes[..]
+            }
+        };
+
+        // like OP_CHECKMULTISIG, the signatures must be in the order of the public keys
+        let secp = Secp256k1::verification_only();
+        let cache = SighashCache::new(&to_sign);
+        let mut remaining = pubkeys.iter();
+        for signature in signatures.iter() {
+            let (sighash_type, der) = signature
+                .split_last()
+                .ok_or_else(|| Error::msg("A signature of the signet solution is empty."))?;
+            let mut signature = ecdsa::Signature::from_der(der)?;
+            signature.normalize_s();
+
+            let sighash = cache.legacy_signature_hash(
+                0,
+                &to_spend.output[0].script_pubkey,
+                *sighash_type as u32,
+            )?;
+            let message = Message::from_digest(sighash.to_byte_array());
+
+            if !remaining.by_ref().any(|pubkey| {
+                secp.verify_ecdsa(&message, &signature, &pubkey.inner)
+                    .is_ok()
+            }) {
+                return Err(Error::msg(
+                    "The signet solution is not signed by the signers of the challenge.",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the public keys of the challenge, and the threshold if it is a multisig.
+fn parse_challenge(challenge: &Script) -> Result<(Vec<PublicKey>, Option<usize>)> {
+    let unsupported = || Error::msg("The signet challenge is not a supported multisig.");
+
+    let instructions = challenge
+        .instructions()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| unsupported())?;
+
+    let small_number = |instruction: &Instruction| match instruction.opcode() {
+        Some(opcode)
+            if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&opcode.to_u8()) =>
+        {
+            Some((opcode.to_u8() - OP_PUSHNUM_1.to_u8() + 1) as usize)
+        }
+        _ => None,
+    };
+    let pubkey = |instruction: &Instruction| {
+        instruction
+            .push_bytes()
+            .and_then(|bytes| PublicKey::from_slice(bytes.as_bytes()).ok())
+    };
+
+    match instructions.as_slice() {
+        [key, last] if last.opcode() == Some(OP_CHECKSIG) => {
+            Ok((vec![pubkey(key).ok_or_else(unsupported)?], None))
+        }
+        [first, keys @ .., n, last] if last.opcode() == Some(OP_CHECKMULTISIG) => {
+            let threshold = small_number(first).ok_or_else(unsupported)?;
+            if small_number(n) != Some(keys.len()) || threshold > keys.len() {
+                return Err(unsupported());
+            }
+            let pubkeys = keys
+                .iter()
+                .map(|key| pubkey(key).ok_or_else(unsupported))
+                .collect::<Result<Vec<_>>>()?;
+            Ok((pubkeys, Some(threshold)))
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::consensus_encode;
+    use crate::network::NetworkParams;
+    use crate::signet::{parse_challenge, SignetBlockProof, SIGNET_HEADER};
+    use crate::spv::TxInclusionProof;
+    use crate::test_utils::block_845797;
+    use bitcoin::consensus::Encodable;
+    use bitcoin::hashes::{sha256d, Hash};
+    use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_CHECKSIG, OP_PUSHNUM_1, OP_PUSHNUM_2};
+    use bitcoin::opcodes::OP_0;
+    use bitcoin::script::{Builder, PushBytesBuf};
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::{
+        merkle_tree, Block, Network, PublicKey, ScriptBuf, Transaction, TxMerkleNode, Witness,
+    };
+
+    fn keys() -> Vec<(SecretKey, PublicKey)> {
+        let secp = Secp256k1::new();
+        (1..=3u8)
+            .map(|i| {
+                let sk = SecretKey::from_slice(&[i; 32]).unwrap();
+                (sk, PublicKey::new(sk.public_key(&secp)))
+            })
+            .collect()
+    }
+
+    fn multisig(threshold: usize, pubkeys: &[PublicKey]) -> ScriptBuf {
+        let mut builder = Builder::new().push_int(threshold as i64);
+        for pubkey in pubkeys.iter() {
+            builder = builder.push_key(pubkey);
+        }
+        builder
+            .push_int(pubkeys.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    /// Put the solution push in the witness commitment of the coinbase and update the header.
+    fn with_solution(proof: &SignetBlockProof, solution: &[u8]) -> SignetBlockProof {
+        let mut coinbase = proof.coinbase.clone();
+        let output = coinbase
+            .output
+            .iter_mut()
+            .rev()
+            .find(|output| output.script_pubkey.as_bytes().starts_with(&[0x6a, 0x24]))
+            .unwrap();
+
+        let mut push = SIGNET_HEADER.to_vec();
+        push.extend_from_slice(solution);
+        let mut bytes = output.script_pubkey.to_bytes();
+        bytes.extend(
+            Builder::new()
+                .push_slice(PushBytesBuf::try_from(push).unwrap())
+                .into_script()
+                .to_bytes(),
+        );
+        output.script_pubkey = ScriptBuf::from_bytes(bytes);
+
+        let mut header = proof.header;
+        header.merkle_root = proof
+            .proof
+            .compute_merkle_root(&TxMerkleNode::from_byte_array(
+                coinbase.compute_txid().to_byte_array(),
+            ))
+            .unwrap();
+
+        SignetBlockProof {
+            header,
+            coinbase,
+            proof: proof.proof.clone(),
+        }
+    }
+
+    /// Sign the block like a signet miner, with the keys in the order of the challenge.
+    ///
+    /// The message is computed from the bytes of the two transactions of BIP325 and of the
+    /// legacy sighash, with the merkle root of all the transactions of the block, rather than
+    /// with [`SignetBlockProof::signet_txs`].
+    fn sign(block: &Block, challenge: &ScriptBuf, sks: &[SecretKey]) -> SignetBlockProof {
+        let base = SignetBlockProof::construct(block).unwrap();
+
+        // the push with only the header is what is left after taking out the solution
+        let cleared = with_solution(&base, &[]);
+        let signet_merkle_root = merkle_tree::calculate_root(
+            std::iter::once(cleared.coinbase.compute_txid())
+                .chain(block.txdata[1..].iter().map(|tx| tx.compute_txid()))
+                .map(|txid| TxMerkleNode::from_byte_array(txid.to_byte_array())),
+        )
+        .unwrap();
+
+        let mut block_data = vec![];
+        block_data.extend(block.header.version.to_consensus().to_le_bytes());
+        block_data.extend(block.header.prev_blockhash.to_byte_array());
+        block_data.extend(signet_merkle_root.to_byte_array());
+        block_data.extend(block.header.time.to_le_bytes());
+        assert_eq!(block_data.len(), 72);
+        // the length of the challenge is a single byte
+        assert!(challenge.len() < 0xfd);
+
+        let mut to_spend = vec![];
+        to_spend.extend([0, 0, 0, 0]);
+        to_spend.push(1);
+        to_spend.extend([0; 32]);
+        to_spend.extend([0xff; 4]);
+        // OP_0 and the push of the 72 bytes
+        to_spend.extend([74, 0x00, 72]);
+        to_spend.extend(&block_data);
+        to_spend.extend([0, 0, 0, 0]);
+        to_spend.push(1);
+        to_spend.extend([0; 8]);
+        to_spend.push(challenge.len() as u8);
+        to_spend.extend(challenge.as_bytes());
+        to_spend.extend([0, 0, 0, 0]);
+
+        // the spending transaction with the challenge as the script code, and SIGHASH_ALL
+        let mut to_sign = vec![];
+        to_sign.extend([0, 0, 0, 0]);
+        to_sign.push(1);
+        to_sign.extend(sha256d::Hash::hash(&to_spend).to_byte_array());
+        to_sign.extend([0, 0, 0, 0]);
+        to_sign.push(challenge.len() as u8);
+        to_sign.extend(challenge.as_bytes());
+        to_sign.extend([0, 0, 0, 0]);
+        to_sign.push(1);
+        to_sign.extend([0; 8]);
+        // OP_RETURN
+        to_sign.extend([1, 0x6a]);
+        to_sign.extend([0, 0, 0, 0]);
+        to_sign.extend([1, 0, 0, 0]);
+        let message = Message::from_digest(sha256d::Hash::hash(&to_sign).to_byte_array());
+
+        let secp = Secp256k1::new();
+        let mut builder = Builder::new();
+        if parse_challenge(challenge).unwrap().1.is_some() {
+            builder = builder.push_opcode(OP_0);
+        }
+        for sk in sks.iter() {
+            let mut signature = secp.sign_ecdsa(&message, sk).serialize_der().to_vec();
+            signature.push(1);
+            builder = builder.push_slice(PushBytesBuf::try_from(signature).unwrap());
+        }
+
+        let mut solution = consensus_encode!(builder.into_script());
+        solution.extend(consensus_encode!(Witness::new()));
+        with_solution(&base, &solution)
+    }
+
+    #[test]
+    fn test_signet_block_proof() {
+        let block = block_845797();
+        let base = SignetBlockProof::construct(&block).unwrap();
+        assert_eq!(base.solution().unwrap(), None);
+
+        let keys = keys();
+        let pubkeys = keys.iter().map(|(_, pk)| *pk).collect::<Vec<_>>();
+
+        // a 1-of-2 challenge like the default signet
+        let challenge = multisig(1, &pubkeys[..2]);
+        let params = NetworkParams::new(Network::Signet).with_signet_challenge(challenge.clone());
+
+        for sk in [keys[0].0, keys[1].0] {
+            let proof = sign(&block, &challenge, &[sk]);
+            assert!(proof.solution().unwrap().is_some());
+            proof.verify(&params).unwrap();
+        }
+
+        // a key that is not in the challenge
+        let proof = sign(&block, &challenge, &[keys[2].0]);
+        assert!(proof.verify(&params).is_err());
+
+        // the signature commits to the header
+        let mut proof = sign(&block, &challenge, &[keys[0].0]);
+        proof.header.time += 1;
+        assert!(proof.verify(&params).is_err());
+
+        // but not to the nonce, which is found after signing
+        let mut proof = sign(&block, &challenge, &[keys[0].0]);
+        proof.header.nonce += 1;
+        proof.verify(&params).unwrap();
+
+        // the signature commits to the other transactions of the block
+        let mut proof = sign(&block, &challenge, &[keys[0].0]);
+        proof.proof.siblings[3] = TxMerkleNode::all_zeros();
+        proof.header.merkle_root = proof
+            .proof
+            .compute_merkle_root(&TxMerkleNode::from_byte_array(
+                proof.coinbase.compute_txid().to_byte_array(),
+            ))
+            .unwrap();
+        assert!(proof.verify(&params).is_err());
+
+        // a block without a solution
+        assert!(base.verify(&params).is_err());
+
+        // a 2-of-3 challenge, whose signatures must follow the order of the keys
+        let challenge = multisig(2, &pubkeys);
+        let params = NetworkParams::new(Network::Signet).with_signet_challenge(challenge.clone());
+        sign(&block, &challenge, &[keys[0].0, keys[2].0])
+            .verify(&params)
+            .unwrap();
+        assert!(sign(&block, &challenge, &[keys[2].0, keys[0].0])
+            .verify(&params)
+            .is_err());
+        assert!(sign(&block, &challenge, &[keys[1].0])
+            .verify(&params)
+            .is_err());
+
+        // a single key
+        let challenge = Builder::new()
+            .push_key(&pubkeys[1])
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let params = NetworkParams::new(Network::Signet).with_signet_challenge(challenge.clone());
+        sign(&block, &challenge, &[keys[1].0])
+            .verify(&params)
+            .unwrap();
+
+        // no challenge on mainnet
+        assert!(sign(&block, &challenge, &[keys[1].0])
+            .verify(&NetworkParams::new(Network::Bitcoin))
+            .is_err());
+    }
+
+    #[test]
+    fn test_signet_solution() {
+        let block = block_845797();
+        let base = SignetBlockProof::construct(&block).unwrap();
+
+        // extra bytes after the witness
+        let mut solution = consensus_encode!(ScriptBuf::new());
+        solution.extend(consensus_encode!(Witness::new()));
+        solution.push(0);
+        assert!(with_solution(&base, &solution).solution().is_err());
+
+        // a coinbase without a witness commitment
+        let mut coinbase: Transaction = base.coinbase.clone();
+        coinbase
+            .output
+            .retain(|output| !output.script_pubkey.as_bytes().starts_with(&[0x6a, 0x24]));
+        let proof = SignetBlockProof {
+            header: base.header,
+            coinbase,
+            proof: TxInclusionProof {
+                idx: 0,
+                siblings: base.proof.siblings.clone(),
+            },
+        };
+        assert!(proof.solution().is_err());
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        // the default signet challenge is a 1-of-2 multisig
+        let params = NetworkParams::new(Network::Signet);
+        let (pubkeys, threshold) =
+            parse_challenge(params.signet_challenge.as_ref().unwrap()).unwrap();
+        assert_eq!(pubkeys.len(), 2);
+        assert_eq!(threshold, Some(1));
+
+        let keys = keys();
+        let invalid = Builder::new()
+            .push_opcode(OP_PUSHNUM_2)
+            .push_key(&keys[0].1)
+            .push_opcode(OP_PUSHNUM_1)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        assert!(parse_challenge(&invalid).is_err());
+        assert!(parse_challenge(&ScriptBuf::new()).is_err());
+    }
+}
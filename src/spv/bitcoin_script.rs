@@ -1,3 +1,4 @@
+use crate::hint::HintSource;
 use crate::spv::TxInclusionProof;
 use crate::treepp::*;
 use crate::utils::limb_to_be_bits_toaltstack;
@@ -29,15 +30,15 @@ impl TxInclusionProofGadget {
     /// output:
     ///     merkle root
     ///
-    pub fn compute_merkle_root() -> Script {
+    pub fn compute_merkle_root(hint: &HintSource) -> Script {
         script! {
             // pull the number of siblings
-            OP_DEPTH OP_1SUB OP_ROLL
+            { hint.pull(1, 0) }
             OP_DUP 0 OP_GREATERTHANOREQUAL OP_VERIFY
             OP_DUP 17 OP_LESSTHANOREQUAL OP_VERIFY
 
             // pull the idx
-            OP_DEPTH OP_1SUB OP_ROLL
+            { hint.pull(2, 0) }
             OP_DUP 131072 OP_LESSTHAN OP_VERIFY
 
             // bit decompose idx (to 17 bits, as there is no way to include more than 2^17 txs per block)
@@ -47,12 +48,12 @@ impl TxInclusionProofGadget {
             // alstack: <bits>
 
             // do 17 rounds
-            for _ in 0..17 {
+            for i in 0..17 {
                 OP_DUP OP_0NOTEQUAL OP_IF
                     OP_SWAP
 
                     // pull the sibling
-                    OP_DEPTH OP_1SUB OP_ROLL
+                    { hint.pull(2, 17 - i) }
                     OP_SIZE 32 OP_EQUALVERIFY
 
                     // stack: number of siblings, leaf_hash, sibling
@@ -76,7 +77,9 @@ impl TxInclusionProofGadget {
 #[cfg(test)]
 mod test {
     use crate::consensus_encode;
+    use crate::hint::HintSource;
     use crate::spv::{TxInclusionProof, TxInclusionProofGadget};
+    use crate::test_utils::{block_845797, given_tx};
     use crate::treepp::*;
     use bitcoin::block::Header;
     use bitcoin::consensus::{Decodable, Encodable};
@@ -84,24 +87,14 @@ mod test {
     use bitcoin::opcodes::all::{OP_PUSHBYTES_32, OP_RETURN};
     use bitcoin::opcodes::Ordinary::OP_GREATERTHANOREQUAL;
     use bitcoin::transaction::Version;
-    use bitcoin::{
-        Address, Block, BlockHash, Network, ScriptBuf, Transaction, Txid, WitnessProgram,
-    };
+    use bitcoin::{Address, BlockHash, Network, ScriptBuf, Txid, WitnessProgram};
     use covenants_gadgets::utils::pseudo::{OP_CAT6, OP_HINT};
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
-    use std::io::Read;
 
     #[test]
     fn test_spv() {
-        let mut fs = std::fs::File::open("./src/spv/block_845797").unwrap();
-        let mut bytes = vec![];
-        fs.read_to_end(&mut bytes).unwrap();
-        drop(fs);
-
-        let encoded_block = hex::decode(&bytes).unwrap();
-
-        let block = Block::consensus_decode(&mut encoded_block.as_slice()).unwrap();
+        let block = block_845797();
 
         let computed_merkle_root = block.compute_merkle_root().unwrap();
         let expected_merkle_root = block.header.merkle_root;
@@ -121,7 +114,7 @@ mod test {
 
             { block.txdata[100].compute_txid().as_byte_array().to_vec() }
 
-            { TxInclusionProofGadget::compute_merkle_root() }
+            { TxInclusionProofGadget::compute_merkle_root(&HintSource::default()) }
             { computed_merkle_root.as_byte_array().to_vec() }
             OP_EQUAL
         };
@@ -130,6 +123,40 @@ mod test {
         assert!(exec_result.success);
     }
 
+    #[test]
+    fn test_spv_hint_sources() {
+        let block = block_845797();
+
+        let txids = block
+            .txdata
+            .iter()
+            .map(|obj| obj.compute_txid())
+            .collect::<Vec<Txid>>();
+
+        let spv = TxInclusionProof::construct_from_txids(&txids, 100);
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            let script = script! {
+                { b"unrelated".to_vec() }
+                { hint.push(TxInclusionProofGadget::push_tx_inclusion_proof_as_hint(&spv)) }
+
+                { txids[100].as_byte_array().to_vec() }
+
+                { TxInclusionProofGadget::compute_merkle_root(&hint) }
+                { block.header.merkle_root.as_byte_array().to_vec() }
+                OP_EQUALVERIFY
+                { b"unrelated".to_vec() } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
     #[test]
     fn test_actual_pow_spv() {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
@@ -145,17 +172,9 @@ mod test {
         let address = Address::from_script(&script_pub_key, Network::Bitcoin).unwrap();
         println!("Address on Bitcoin mainnet: {}", address);
 
-        let given_tx_bytes = hex::decode("0200000000010152c0ef39e255fbe3858282c59ed3a3747b71bc17632daf1029e5f86e19761f290000000000fdffffff02e803000000000000220020ba714b93459645d8c931819b567a75b304eb8a69a3f71432f6ad3be9780b639c0085070000000000160014ba3cde39438c04d6645b8c130d36bb0c7cbf2fbd0247304402203d99f19bb84c2c8b60f6495b0851ff68d42527600735a521efcffe9549bcaa4002203b6cf74de4a1d36a4eb3cf8dd3e61d359dedd708153862501f9b439ab1da49d9012102113f09ba5f346c77205630298995acbe1f95c77c882b2c0e1408277e5290db4f9de70c00").unwrap();
-        let given_tx = Transaction::consensus_decode(&mut given_tx_bytes.as_slice()).unwrap();
-
-        let mut fs = std::fs::File::open("./src/spv/block_845797").unwrap();
-        let mut bytes = vec![];
-        fs.read_to_end(&mut bytes).unwrap();
-        drop(fs);
-
-        let encoded_block = hex::decode(&bytes).unwrap();
+        let given_tx = given_tx();
 
-        let block = Block::consensus_decode(&mut encoded_block.as_slice()).unwrap();
+        let block = block_845797();
 
         let computed_merkle_root = block.compute_merkle_root().unwrap();
         let expected_merkle_root = block.header.merkle_root;
@@ -323,7 +342,7 @@ mod test {
 
             OP_SHA256 OP_SHA256
 
-            { TxInclusionProofGadget::compute_merkle_root() }
+            { TxInclusionProofGadget::compute_merkle_root(&HintSource::default()) }
             OP_HINT OP_DUP OP_TOALTSTACK // save a copy of tx merkle root to the altstack
             OP_EQUALVERIFY
 
@@ -336,7 +355,7 @@ mod test {
 
             { crate::structures::header::HeaderGadget::compute_hash_from_stack() }
             OP_DUP OP_TOALTSTACK
-            { crate::structures::hash::BlockHashGadget::get_bit_security() }
+            { crate::structures::hash::BlockHashGadget::get_bit_security(&HintSource::default()) }
 
             for _ in 0..5 {
                 OP_HINT { crate::structures::version::VersionGadget::from_provided() }
@@ -348,7 +367,7 @@ mod test {
 
                 { crate::structures::header::HeaderGadget::compute_hash_from_stack() }
                 OP_DUP OP_TOALTSTACK
-                { crate::structures::hash::BlockHashGadget::get_bit_security() }
+                { crate::structures::hash::BlockHashGadget::get_bit_security(&HintSource::default()) }
             }
 
             for _ in 0..6 {
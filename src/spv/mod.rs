@@ -1,11 +1,17 @@
 use anyhow::Result;
 use bitcoin::hashes::Hash;
-use bitcoin::{Transaction, TxMerkleNode, Txid};
+use bitcoin::merkle_tree::PartialMerkleTree;
+use bitcoin::{Block, Transaction, TxMerkleNode, Txid};
 use sha2::Digest;
+use std::collections::HashMap;
 
 mod bitcoin_script;
 pub use bitcoin_script::*;
 
+mod tx_count;
+pub use tx_count::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TxInclusionProof {
     pub idx: usize,
     pub siblings: Vec<TxMerkleNode>,
@@ -64,11 +70,69 @@ impl TxInclusionProof {
         Self { idx, siblings }
     }
 
-    pub fn verify_hash_inclusion(
-        &self,
-        leaf_hash: &TxMerkleNode,
-        root: &TxMerkleNode,
-    ) -> Result<()> {
+    pub fn construct_from_block(block: &Block, txid: &Txid) -> Result<Self> {
+        let txids = block
+            .txdata
+            .iter()
+            .map(|obj| obj.compute_txid())
+            .collect::<Vec<Txid>>();
+
+        let idx = txids
+            .iter()
+            .position(|x| x == txid)
+            .ok_or_else(|| anyhow::Error::msg("The transaction is not in the block."))?;
+
+        Ok(Self::construct_from_txids(&txids, idx))
+    }
+
+    /// Convert a partial merkle tree, such as the one returned by `gettxoutproof`, into the
+    /// inclusion proof of one of its matched transactions.
+    pub fn construct_from_partial_merkle_tree(
+        pmt: &PartialMerkleTree,
+        txid: &Txid,
+    ) -> Result<Self> {
+        let mut matches = vec![];
+        let mut indexes = vec![];
+        pmt.extract_matches(&mut matches, &mut indexes)
+            .map_err(|e| {
+                anyhow::Error::msg(format!("The partial merkle tree is invalid: {}", e))
+            })?;
+
+        let idx = matches
+            .iter()
+            .position(|x| x == txid)
+            .map(|pos| indexes[pos] as usize)
+            .ok_or_else(|| anyhow::Error::msg("The transaction is not matched in the proof."))?;
+
+        let num_transactions = pmt.num_transactions() as usize;
+
+        let mut tree_height = 0;
+        while tree_width(num_transactions, tree_height) > 1 {
+            tree_height += 1;
+        }
+
+        // replay the depth-first traversal of the partial merkle tree and record every node
+        // that it reveals, which includes both children of every node on a matched path
+        let mut nodes = HashMap::new();
+        traverse_partial_merkle_tree(pmt, tree_height, 0, &mut 0, &mut 0, &mut nodes);
+
+        let mut siblings = vec![];
+        let mut cur = idx;
+        for height in 0..tree_height {
+            let sibling = if (cur ^ 1) < tree_width(num_transactions, height) {
+                cur ^ 1
+            } else {
+                cur
+            };
+            siblings.push(nodes[&(height, sibling)]);
+            cur >>= 1;
+        }
+
+        Ok(Self { idx, siblings })
+    }
+
+    /// Compute the merkle root from the leaf and the siblings.
+    pub fn compute_merkle_root(&self, leaf_hash: &TxMerkleNode) -> Result<TxMerkleNode> {
         let mut hash = leaf_hash.clone();
 
         let mut cur = self.idx;
@@ -98,7 +162,15 @@ impl TxInclusionProof {
             ));
         }
 
-        if hash != *root {
+        Ok(hash)
+    }
+
+    pub fn verify_hash_inclusion(
+        &self,
+        leaf_hash: &TxMerkleNode,
+        root: &TxMerkleNode,
+    ) -> Result<()> {
+        if self.compute_merkle_root(leaf_hash)? != *root {
             return Err(anyhow::Error::msg("The root does not match."));
         }
 
@@ -111,23 +183,64 @@ impl TxInclusionProof {
     }
 }
 
+fn tree_width(num_transactions: usize, height: usize) -> usize {
+    (num_transactions + (1 << height) - 1) >> height
+}
+
+fn traverse_partial_merkle_tree(
+    pmt: &PartialMerkleTree,
+    height: usize,
+    pos: usize,
+    bits_used: &mut usize,
+    hashes_used: &mut usize,
+    nodes: &mut HashMap<(usize, usize), TxMerkleNode>,
+) -> TxMerkleNode {
+    let parent_of_match = pmt.bits()[*bits_used];
+    *bits_used += 1;
+
+    let hash = if height == 0 || !parent_of_match {
+        *hashes_used += 1;
+        pmt.hashes()[*hashes_used - 1]
+    } else {
+        let left =
+            traverse_partial_merkle_tree(pmt, height - 1, pos * 2, bits_used, hashes_used, nodes);
+        let right = if pos * 2 + 1 < tree_width(pmt.num_transactions() as usize, height - 1) {
+            traverse_partial_merkle_tree(
+                pmt,
+                height - 1,
+                pos * 2 + 1,
+                bits_used,
+                hashes_used,
+                nodes,
+            )
+        } else {
+            left
+        };
+
+        let mut sha256 = sha2::Sha256::new();
+        Digest::update(&mut sha256, left.as_byte_array());
+        Digest::update(&mut sha256, right.as_byte_array());
+        let first_hash = sha256.finalize().to_vec();
+
+        let mut sha256 = sha2::Sha256::new();
+        Digest::update(&mut sha256, first_hash);
+        TxMerkleNode::from_slice(&sha256.finalize()).unwrap()
+    };
+
+    nodes.insert((height, pos), hash);
+    hash
+}
+
 #[cfg(test)]
 mod test {
     use crate::spv::TxInclusionProof;
-    use bitcoin::consensus::Decodable;
-    use bitcoin::{Block, Txid};
-    use std::io::Read;
+    use crate::test_utils::block_845797;
+    use bitcoin::merkle_tree::PartialMerkleTree;
+    use bitcoin::Txid;
 
     #[test]
     fn test_spv() {
-        let mut fs = std::fs::File::open("./src/spv/block_845797").unwrap();
-        let mut bytes = vec![];
-        fs.read_to_end(&mut bytes).unwrap();
-        drop(fs);
-
-        let encoded_block = hex::decode(&bytes).unwrap();
-
-        let block = Block::consensus_decode(&mut encoded_block.as_slice()).unwrap();
+        let block = block_845797();
 
         let computed_merkle_root = block.compute_merkle_root().unwrap();
         let expected_merkle_root = block.header.merkle_root;
@@ -144,4 +257,32 @@ mod test {
         spv.verify_tx_inclusion(&block.txdata[100], &computed_merkle_root)
             .unwrap();
     }
+
+    #[test]
+    fn test_spv_from_partial_merkle_tree() {
+        let block = block_845797();
+
+        let txids = block
+            .txdata
+            .iter()
+            .map(|obj| obj.compute_txid())
+            .collect::<Vec<Txid>>();
+
+        for idx in [0, 1, 100, txids.len() - 1] {
+            let matches = (0..txids.len()).map(|i| i == idx).collect::<Vec<bool>>();
+            let pmt = PartialMerkleTree::from_txids(&txids, &matches);
+
+            let spv =
+                TxInclusionProof::construct_from_partial_merkle_tree(&pmt, &txids[idx]).unwrap();
+            let expected = TxInclusionProof::construct_from_txids(&txids, idx);
+            assert_eq!(spv.idx, expected.idx);
+            assert_eq!(spv.siblings, expected.siblings);
+
+            spv.verify_tx_inclusion(&block.txdata[idx], &block.header.merkle_root)
+                .unwrap();
+        }
+
+        let pmt = PartialMerkleTree::from_txids(&txids, &vec![false; txids.len()]);
+        assert!(TxInclusionProof::construct_from_partial_merkle_tree(&pmt, &txids[0]).is_err());
+    }
 }
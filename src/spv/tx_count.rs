@@ -0,0 +1,304 @@
+use crate::consensus_encode;
+use crate::hint::HintSource;
+use crate::spv::{TxInclusionProof, TxInclusionProofGadget};
+use crate::treepp::*;
+use crate::tx::check_tx_size;
+use crate::utils::limb_to_be_bits_toaltstack;
+use anyhow::{Error, Result};
+use bitcoin::consensus::Encodable;
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, Transaction, TxMerkleNode, Txid};
+
+/// A proof that a block has a certain number of transactions, which consists of the last
+/// transaction of the block and its inclusion proof, that is, the right-most path of the merkle
+/// tree.
+///
+/// The path shows that the leaf is the last one: whenever the path goes through a left child,
+/// the sibling is the same node, as the merkle tree duplicates the last node of a layer that has
+/// an odd number of nodes, and whenever the path goes through a right child, the sibling is a
+/// different node. The latter rules out the trees that duplicate transactions at the end to get
+/// the same merkle root (CVE-2012-2459), which are never valid blocks.
+///
+/// The last transaction is provided in full, rather than by its txid, so that an inner node of
+/// the merkle tree cannot be passed off as a leaf, which would shorten the path and change the
+/// count.
+///
+/// The last transaction without the witness is hashed as a single stack element, so it must be
+/// at most 520 bytes, see [`check_tx_size`]. The count of a block whose last transaction is
+/// larger cannot be proven: hashing it in 64-byte blocks, as
+/// [`crate::tx::TxFieldsGadget::compute_txid_with_last_output`] does, takes about 311 KB of
+/// script for each block.
+pub struct TxCountProof {
+    pub last_tx: Transaction,
+    pub proof: TxInclusionProof,
+}
+
+impl TxCountProof {
+    pub fn construct_from_txs(txs: &[Transaction]) -> Result<Self> {
+        let last_tx = txs
+            .last()
+            .cloned()
+            .ok_or_else(|| Error::msg("The block has no transactions."))?;
+        check_tx_size(&last_tx)?;
+
+        let txids = txs
+            .iter()
+            .map(|obj| obj.compute_txid())
+            .collect::<Vec<Txid>>();
+        let proof = TxInclusionProof::construct_from_txids(&txids, txids.len() - 1);
+
+        Ok(Self { last_tx, proof })
+    }
+
+    pub fn construct_from_block(block: &Block) -> Result<Self> {
+        Self::construct_from_txs(&block.txdata)
+    }
+
+    /// The number of transactions of the block, if the proof is valid.
+    pub fn num_transactions(&self) -> usize {
+        self.proof.idx + 1
+    }
+
+    pub fn verify(&self, root: &TxMerkleNode) -> Result<()> {
+        check_tx_size(&self.last_tx)?;
+
+        if self.proof.siblings.len() > 17 || (self.proof.idx >> self.proof.siblings.len()) != 0 {
+            return Err(Error::msg(
+                "The proof doesn't include the right number of siblings.",
+            ));
+        }
+
+        let mut hash = TxMerkleNode::from_byte_array(self.last_tx.compute_txid().to_byte_array());
+        let mut cur = self.proof.idx;
+        for sibling in self.proof.siblings.iter() {
+            if (cur % 2 == 1) == (*sibling == hash) {
+                return Err(Error::msg(
+                    "The proof is not the right-most path of the tree.",
+                ));
+            }
+
+            let mut bytes = vec![];
+            if cur % 2 == 1 {
+                bytes.extend_from_slice(sibling.as_byte_array());
+                bytes.extend_from_slice(hash.as_byte_array());
+            } else {
+                bytes.extend_from_slice(hash.as_byte_array());
+                bytes.extend_from_slice(sibling.as_byte_array());
+            }
+            hash = TxMerkleNode::hash(&bytes);
+            cur >>= 1;
+        }
+
+        self.proof.verify_tx_inclusion(&self.last_tx, root)
+    }
+
+    /// The last transaction without the witness, whose hash is the txid.
+    fn last_tx_bytes(&self) -> Vec<u8> {
+        let mut tx = self.last_tx.clone();
+        for input in tx.input.iter_mut() {
+            input.witness.clear();
+        }
+        consensus_encode!(tx)
+    }
+}
+
+pub struct TxCountGadget;
+
+impl TxCountGadget {
+    pub fn push_tx_count_proof_as_hint(proof: &TxCountProof) -> Script {
+        script! {
+            { proof.last_tx_bytes() }
+            { TxInclusionProofGadget::push_tx_inclusion_proof_as_hint(&proof.proof) }
+        }
+    }
+
+    /// Verify the count proof and compute the merkle root.
+    ///
+    /// The index of the last transaction is decomposed into 17 bits, like in
+    /// [`TxInclusionProofGadget::compute_merkle_root`], and the bits beyond the number of
+    /// siblings must be zero, so that the index is exactly the position of the leaf.
+    ///
+    /// hint:
+    ///     last transaction without the witness
+    ///     num of siblings
+    ///     idx, which is the number of transactions minus one
+    ///     [each sibling]
+    ///
+    /// input:
+    ///
+    /// output:
+    ///     number of transactions
+    ///     merkle root
+    ///
+    /// The last transaction must be at most 520 bytes, see [`TxCountProof`].
+    pub fn compute_merkle_root(hint: &HintSource) -> Script {
+        script! {
+            // pull the last transaction, which cannot be an inner node of 64 bytes
+            { hint.pull(0, 0) }
+            OP_SIZE 64 OP_EQUAL OP_NOT OP_VERIFY
+            OP_SHA256 OP_SHA256
+
+            // pull the number of siblings
+            { hint.pull(1, 0) }
+            OP_DUP 0 OP_GREATERTHANOREQUAL OP_VERIFY
+            OP_DUP 17 OP_LESSTHANOREQUAL OP_VERIFY
+
+            // pull the idx and keep a copy of it
+            { hint.pull(2, 0) }
+            OP_DUP 0 OP_GREATERTHANOREQUAL OP_VERIFY
+            OP_DUP 131072 OP_LESSTHAN OP_VERIFY
+            OP_DUP OP_TOALTSTACK
+
+            { limb_to_be_bits_toaltstack(17) }
+
+            // stack: leaf hash, number of siblings
+            // alstack: idx, <bits>
+
+            for i in 0..17 {
+                OP_DUP OP_0NOTEQUAL OP_IF
+                    OP_SWAP
+
+                    // pull the sibling
+                    { hint.pull(2, 18 - i) }
+                    OP_SIZE 32 OP_EQUALVERIFY
+
+                    // stack: number of siblings, hash, sibling
+
+                    OP_FROMALTSTACK OP_IF
+                        // a right child, whose sibling must be a different node
+                        OP_2DUP OP_EQUAL OP_NOT OP_VERIFY
+                        OP_SWAP
+                    OP_ELSE
+                        // a left child, which must be the last node of its layer
+                        OP_2DUP OP_EQUALVERIFY
+                    OP_ENDIF
+
+                    OP_CAT OP_SHA256 OP_SHA256
+
+                    OP_SWAP OP_1SUB
+                OP_ELSE
+                    // the idx has no more bits
+                    OP_FROMALTSTACK OP_NOT OP_VERIFY
+                OP_ENDIF
+            }
+
+            // drop the number of siblings, which would be zero
+            OP_DROP
+
+            OP_FROMALTSTACK OP_1ADD OP_SWAP
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hint::HintSource;
+    use crate::spv::{TxCountGadget, TxCountProof, TxInclusionProof};
+    use crate::test_utils::block_845797;
+    use crate::treepp::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::merkle_tree::calculate_root;
+    use bitcoin::TxMerkleNode;
+
+    fn root_of(txs: &[bitcoin::Transaction]) -> TxMerkleNode {
+        calculate_root(
+            txs.iter()
+                .map(|tx| TxMerkleNode::from_byte_array(tx.compute_txid().to_byte_array())),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tx_count() {
+        let block = block_845797();
+
+        let proof = TxCountProof::construct_from_block(&block).unwrap();
+        assert_eq!(proof.num_transactions(), 3576);
+        proof.verify(&block.header.merkle_root).unwrap();
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            let script = script! {
+                { b"unrelated".to_vec() }
+                { hint.push(TxCountGadget::push_tx_count_proof_as_hint(&proof)) }
+
+                { TxCountGadget::compute_merkle_root(&hint) }
+                { block.header.merkle_root.as_byte_array().to_vec() } OP_EQUALVERIFY
+                3576 OP_EQUALVERIFY
+                { b"unrelated".to_vec() } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // trees of other shapes, using the first transactions of the block
+        for num_transactions in [1, 2, 3, 4, 7, 9, 10, 13, 100, 1025] {
+            let txs = &block.txdata[..num_transactions];
+            let root = root_of(txs);
+
+            let proof = TxCountProof::construct_from_txs(txs).unwrap();
+            assert_eq!(proof.num_transactions(), num_transactions);
+            proof.verify(&root).unwrap();
+
+            let script = script! {
+                { TxCountGadget::push_tx_count_proof_as_hint(&proof) }
+                { TxCountGadget::compute_merkle_root(&HintSource::default()) }
+                { root.as_byte_array().to_vec() } OP_EQUALVERIFY
+                { num_transactions } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // the fifth transaction of the block is too large to be hashed in script
+        assert!(TxCountProof::construct_from_txs(&block.txdata[..5]).is_err());
+    }
+
+    #[test]
+    fn test_tx_count_rejects() {
+        let block = block_845797();
+        let txs = &block.txdata[..11];
+        let root = root_of(txs);
+
+        let check = |proof: &TxCountProof| {
+            assert!(proof.verify(&root).is_err());
+
+            let script = script! {
+                { TxCountGadget::push_tx_count_proof_as_hint(proof) }
+                { TxCountGadget::compute_merkle_root(&HintSource::default()) }
+                { root.as_byte_array().to_vec() } OP_EQUALVERIFY
+                OP_DROP OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        };
+
+        // the inclusion proof of a transaction that is not the last one
+        for idx in [9, 8] {
+            let mut proof = TxCountProof::construct_from_txs(&txs[..idx + 1]).unwrap();
+            proof.proof = TxInclusionProof::construct_from_txids(
+                &txs.iter().map(|tx| tx.compute_txid()).collect::<Vec<_>>(),
+                idx,
+            );
+            check(&proof);
+        }
+
+        // a larger index with the same siblings
+        let mut proof = TxCountProof::construct_from_txs(txs).unwrap();
+        proof.proof.idx += 16;
+        check(&proof);
+
+        // the same merkle root from duplicating the last transaction (CVE-2012-2459)
+        let mut mutated = txs.to_vec();
+        mutated.push(txs[10].clone());
+        assert_eq!(root_of(&mutated), root);
+        let proof = TxCountProof::construct_from_txs(&mutated).unwrap();
+        assert_eq!(proof.num_transactions(), 12);
+        check(&proof);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::consensus_encode;
+use crate::hint::HintSource;
+use crate::treepp::*;
+use crate::utils::{le_bytes_to_u16_limbs, push_le_bytes_to_u16_limbs_hint};
+use bitcoin::consensus::Encodable;
+use bitcoin::Amount;
+
+pub struct AmountGadget;
+
+impl AmountGadget {
+    pub fn from_constant(amount: Amount) -> Script {
+        script! {
+            { consensus_encode!(amount) }
+        }
+    }
+
+    pub fn from_provided() -> Script {
+        script! {
+            OP_SIZE 8 OP_EQUALVERIFY
+        }
+    }
+
+    /// Push the hint for [`Self::check_at_least`].
+    pub fn push_at_least_hint(amount: Amount) -> Script {
+        push_le_bytes_to_u16_limbs_hint(&consensus_encode!(amount))
+    }
+
+    /// Check that the 8-byte little-endian amount, such as the value of an output, is at least
+    /// `min`.
+    ///
+    /// The amount is split into four u16 limbs, as it does not fit in a script number, and the
+    /// limbs are compared with those of `min` from the least significant one upward, where
+    /// a higher limb decides unless it is equal.
+    ///
+    /// hint:
+    ///   [each byte of the amount, least significant first, as a number]
+    ///
+    /// input:
+    ///   amount
+    ///
+    /// output:
+    ///
+    pub fn check_at_least(min: Amount, hint: &HintSource) -> Script {
+        let min = min.to_sat();
+        let min_limb = |i: usize| ((min >> (16 * i)) & 0xffff) as usize;
+
+        script! {
+            { le_bytes_to_u16_limbs(4, hint) }
+
+            // stack: limb 0, limb 1, limb 2, limb 3
+            3 OP_ROLL { min_limb(0) } OP_GREATERTHANOREQUAL
+
+            for i in 1..4 {
+                // stack: limb i, ..., limb 3, whether the lower limbs are at least those of min
+                { 4 - i } OP_ROLL
+                OP_DUP { min_limb(i) } OP_GREATERTHAN
+                OP_SWAP { min_limb(i) } OP_EQUAL
+                OP_ROT OP_BOOLAND
+                OP_BOOLOR
+            }
+
+            OP_VERIFY
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hint::HintSource;
+    use crate::structures::amount::AmountGadget;
+    use crate::treepp::*;
+    use bitcoin::Amount;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_check_at_least() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let mut pairs = vec![
+            (0u64, 0u64),
+            (1000, 1000),
+            (999, 1000),
+            (1001, 1000),
+            (0x1_0000, 0xffff),
+            (0xffff, 0x1_0000),
+            (0x1_0000_0000, 0xffff_ffff),
+            (0x0001_0000_0000_0000, 0x0000_ffff_ffff_ffff),
+            (0x0000_ffff_ffff_ffff, 0x0001_0000_0000_0000),
+            (21_000_000 * 100_000_000, 21_000_000 * 100_000_000),
+            (u64::MAX, u64::MAX - 1),
+            (u64::MAX - 1, u64::MAX),
+        ];
+        for _ in 0..20 {
+            let amount: u64 = prng.gen_range(0..21_000_000 * 100_000_000);
+            let min = match prng.gen_range(0..3) {
+                0 => amount,
+                1 => amount ^ (1 << prng.gen_range(0..51)),
+                _ => prng.gen_range(0..21_000_000 * 100_000_000),
+            };
+            pairs.push((amount, min));
+        }
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            for (amount, min) in pairs.iter() {
+                let (amount, min) = (Amount::from_sat(*amount), Amount::from_sat(*min));
+
+                let script = script! {
+                    { b"unrelated".to_vec() }
+                    { hint.push(AmountGadget::push_at_least_hint(amount)) }
+                    { AmountGadget::from_constant(amount) }
+                    { AmountGadget::check_at_least(min, &hint) }
+                    { b"unrelated".to_vec() } OP_EQUAL
+                };
+
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, amount >= min);
+            }
+        }
+    }
+}
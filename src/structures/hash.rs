@@ -1,4 +1,7 @@
+use crate::hint::HintSource;
+use crate::pow::{HashByteOrder, HashPowGadget};
 use crate::treepp::*;
+use bitcoin::hashes::Hash;
 use bitcoin::BlockHash;
 
 pub struct BlockHashGadget;
@@ -28,28 +31,7 @@ impl BlockHashGadget {
     /// https://github.com/Bitcoin-Wildlife-Sanctuary/bitcoin-circle-stark/blob/main/src/pow/bitcoin_script.rs
     ///
     pub fn push_bit_security_hint(hash: &BlockHash) -> Script {
-        let bytes = AsRef::<[u8]>::as_ref(&hash).to_vec();
-
-        let mut leading_zeros = 0usize;
-        for i in 0..32 {
-            if bytes[31 - i] == 0u8 {
-                leading_zeros += 8;
-            } else {
-                leading_zeros += bytes[31 - i].leading_zeros() as usize;
-                break;
-            }
-        }
-
-        script! {
-            { leading_zeros / 8 }
-            { leading_zeros % 8 }
-            if leading_zeros % 8 == 0 {
-                { bytes[..31 - (leading_zeros / 8) + 1].to_vec() }
-            } else {
-                { bytes[..31 - (leading_zeros + 8 - 1) / 8 + 1].to_vec() }
-                { bytes[31 - leading_zeros / 8] }
-            }
-        }
+        HashPowGadget::push_leading_zeros_hint(hash.as_byte_array(), HashByteOrder::LittleEndian)
     }
 
     /// Get the bits of security for the hash, using a hint.
@@ -60,204 +42,59 @@ impl BlockHashGadget {
     ///   prefix
     ///   msb
     ///
-    /// This is adapted from `bitcoin-circle-stark`, @victorkstarkware
-    /// https://github.com/Bitcoin-Wildlife-Sanctuary/bitcoin-circle-stark/blob/main/src/pow/bitcoin_script.rs
+    /// See [`HashPowGadget::get_leading_zeros`], which this specializes to block hashes.
     ///
-    pub fn get_bit_security() -> Script {
-        script! {
-            // pull the leading_zeros / 8
-            OP_DEPTH OP_1SUB OP_ROLL
-
-            // check its format
-            OP_DUP OP_DUP OP_ABS OP_EQUALVERIFY // enforce that it is nonnegative
-            OP_DUP 32 OP_LESSTHANOREQUAL OP_VERIFY // enforce that it is smaller or equal to 32
-
-            // pull the leading_zeros % 8
-            OP_DEPTH OP_1SUB OP_ROLL
-
-            // check its format
-            OP_DUP OP_DUP OP_ABS OP_EQUALVERIFY // enforce that it is nonnegative
-            OP_DUP 7 OP_LESSTHANOREQUAL OP_VERIFY // enforce that it is smaller or equal to 7
-
-            // stack: h, leading_zeros / 8, leading_zeros % 8
-
-            // compute the expected length, which is (32 - ceil(leading_zeros / 8))
-            OP_OVER 32 OP_SWAP OP_SUB OP_TOALTSTACK
-            OP_DUP OP_0NOTEQUAL
-            OP_IF
-                OP_FROMALTSTACK OP_1SUB OP_TOALTSTACK
-            OP_ENDIF
-
-            // stack: h, leading_zeros / 8, leading_zeros % 8
-            // altstack: expected length
-
-            // pull the prefix
-            OP_DEPTH OP_1SUB OP_ROLL
-
-            // check the prefix length
-            OP_SIZE OP_FROMALTSTACK OP_EQUALVERIFY
-
-            // stack: h, leading_zeros / 8, leading_zeros % 8, prefix
-            // check if msb is needed
-            OP_OVER OP_0NOTEQUAL
-            OP_IF
-                OP_DEPTH OP_1SUB OP_ROLL
-
-                // check its size to be 1 (it would not be 2 because its most significant bit must be zero)
-                // and it is not 0 because in that case, it would have more leading zeros
-                //
-                // note: it includes an assumption that the number of leading zeros provided needs be to somewhat exact,
-                // but this is not a strict requirement.
-                OP_SIZE 1 OP_EQUALVERIFY
-
-                OP_DUP
-                0 OP_EQUAL OP_IF
-                    OP_PUSHBYTES_1 OP_PUSHBYTES_0
-                OP_ELSE
-                    OP_DUP
-                OP_ENDIF
-                OP_TOALTSTACK
-
-                OP_ROT
-
-                // stack: h, leading_zeros / 8, prefix, msb, leading_zeros % 8
-                // altstack: msb (forcing 0 to be "0")
-
-                OP_DUP OP_TOALTSTACK
-
-                8 OP_SWAP OP_SUB
-
-                OP_DUP
-                4 OP_GREATERTHANOREQUAL OP_IF
-                    4 OP_SUB
-                    16
-                OP_ELSE
-                    1
-                OP_ENDIF
-
-                OP_SWAP
-
-                // stack: h, leading_zeros / 8, prefix, msb, (2^4 or 2^0), leading_zeros % 4
-                // altstack: msb, leading_zeros % 8
-
-                OP_DUP
-                2 OP_GREATERTHANOREQUAL OP_IF
-                    2 OP_SUB
-                    OP_SWAP OP_DUP OP_ADD OP_DUP OP_ADD OP_SWAP
-                OP_ENDIF
-
-                // stack: h, leading_zeros / 8, prefix, msb, (2^6, 2^4, 2^2 or 2^0), leading_zeros % 2
-                // altstack: msb, leading_zeros % 8
-
-                OP_IF
-                    OP_DUP OP_ADD
-                OP_ENDIF
-
-                // stack: h, leading_zeros / 8, prefix, msb, 1 << (8 - leading_zeros % 8)
-                // altstack: msb, leading_zeros % 8
-
-                OP_LESSTHAN OP_VERIFY
-
-                // stack: h, leading_zeros / 8, prefix
-                // altstack: msb, leading_zeros % 8
-            OP_ELSE
-                OP_SWAP OP_TOALTSTACK
-                OP_PUSHBYTES_0 OP_TOALTSTACK
-
-                // stack: h, leading_zeros / 8, prefix
-                // altstack: msb, leading_zeros % 8
-            OP_ENDIF
-
-            // generate the suffix
-            OP_OVER
-            // stack: h, leading_zeros / 8, prefix, leading_zeros / 8
-            // altstack: msb, leading_zeros % 8
-
-            OP_DUP
-            16 OP_GREATERTHANOREQUAL OP_IF
-                16 OP_SUB
-                OP_PUSHBYTES_4 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0
-                OP_DUP OP_CAT
-                OP_DUP OP_CAT
-            OP_ELSE
-                OP_PUSHBYTES_0
-            OP_ENDIF
-
-            OP_SWAP
-            // stack: h, leading_zeros / 8, prefix, suffix (pending), (leading_zeros / 8) % 16
-            // altstack: msb, leading_zeros % 8
-
-            OP_DUP
-            8 OP_GREATERTHANOREQUAL OP_IF
-                8 OP_SUB
-
-                OP_SWAP
-
-                OP_PUSHBYTES_4 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0
-                OP_DUP OP_CAT
-
-                OP_CAT OP_SWAP
-            OP_ENDIF
-            // stack: h, leading_zeros / 8, prefix, suffix (pending), (leading_zeros / 8) % 8
-            // altstack: msb, leading_zeros % 8
-
-            OP_DUP
-            4 OP_GREATERTHANOREQUAL OP_IF
-                4 OP_SUB
-
-                OP_SWAP
-
-                OP_PUSHBYTES_4 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0 OP_PUSHBYTES_0
-
-                OP_CAT OP_SWAP
-            OP_ENDIF
-            // stack: h, leading_zeros / 8, prefix, suffix (pending), (leading_zeros / 8) % 4
-            // altstack: msb, leading_zeros % 8
-
-            OP_DUP
-            2 OP_GREATERTHANOREQUAL OP_IF
-                2 OP_SUB
-
-                OP_SWAP
-
-                OP_PUSHBYTES_2 OP_PUSHBYTES_0 OP_PUSHBYTES_0
-
-                OP_CAT OP_SWAP
-            OP_ENDIF
-            // stack: h, leading_zeros / 8, prefix, suffix (pending), (leading_zeros / 8) % 2
-            // altstack: msb, leading_zeros % 8
-
-            OP_IF
-                OP_PUSHBYTES_1 OP_PUSHBYTES_0
-                OP_CAT
-            OP_ENDIF
-            // stack: h, leading_zeros / 8, prefix, suffix
-            // altstack: msb, leading_zeros % 8
-
-            OP_FROMALTSTACK OP_FROMALTSTACK
-            OP_ROT OP_CAT
-
-            // stack: h, leading_zeros / 8, prefix, leading_zeros % 8, msb+suffix
-
-            OP_ROT OP_SWAP OP_CAT
-
-            // stack: h, leading_zeros / 8, leading_zeros % 8, hash
+    pub fn get_bit_security(hint: &HintSource) -> Script {
+        HashPowGadget::get_leading_zeros(HashByteOrder::LittleEndian, hint)
+    }
 
-            OP_2SWAP OP_TOALTSTACK
-            OP_EQUALVERIFY OP_FROMALTSTACK
+    /// Push the hint for checking the exact bits of security.
+    pub fn push_exact_bit_security_hint(hash: &BlockHash) -> Script {
+        HashPowGadget::push_exact_leading_zeros_hint(
+            hash.as_byte_array(),
+            HashByteOrder::LittleEndian,
+        )
+    }
 
-            // stack: leading_zeros % 8, leading_zeros / 8
+    /// Get the exact bits of security for the hash, using a hint.
+    ///
+    /// hint:
+    ///   leading zeros // 8 (must be non-negative and smaller or equal to 31)
+    ///   leading zeros % 8 (must be non-negative and smaller or equal to 7)
+    ///   prefix
+    ///   msb (as a number)
+    ///
+    /// See [`HashPowGadget::get_exact_leading_zeros`], which this specializes to block hashes.
+    ///
+    pub fn get_exact_bit_security(hint: &HintSource) -> Script {
+        HashPowGadget::get_exact_leading_zeros(HashByteOrder::LittleEndian, hint)
+    }
 
-            OP_DUP OP_ADD OP_DUP OP_ADD OP_DUP OP_ADD
-            OP_ADD
+    /// Push the hint for checking that the hash has at least `min_bits` bits of security.
+    pub fn push_min_bits_hint(hash: &BlockHash, min_bits: usize) -> Script {
+        HashPowGadget::push_min_leading_zeros_hint(
+            hash.as_byte_array(),
+            min_bits,
+            HashByteOrder::LittleEndian,
+        )
+    }
 
-            // stack: leading zeros
-        }
+    /// Check that the hash has at least `min_bits` bits of security, using a hint.
+    ///
+    /// hint:
+    ///   remainder
+    ///   partial byte (only if min_bits % 8 is not zero, as a number)
+    ///
+    /// See [`HashPowGadget::check_min_leading_zeros`], which this specializes to block hashes.
+    ///
+    pub fn check_min_bits(min_bits: usize, hint: &HintSource) -> Script {
+        HashPowGadget::check_min_leading_zeros(min_bits, HashByteOrder::LittleEndian, hint)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::hint::HintSource;
     use crate::structures::hash::BlockHashGadget;
     use crate::treepp::*;
     use bitcoin::consensus::Decodable;
@@ -290,7 +127,7 @@ mod test {
             }
             for (block_hash, bit_security) in block_hashes.iter().zip(bits_security.iter()) {
                 { BlockHashGadget::from_constant(block_hash) }
-                { BlockHashGadget::get_bit_security() }
+                { BlockHashGadget::get_bit_security(&HintSource::default()) }
                 { *bit_security }
                 OP_EQUALVERIFY
             }
@@ -301,5 +138,21 @@ mod test {
         println!("{:8}", exec_result.final_stack);
         println!("{:?}", exec_result.error);
         assert!(exec_result.success);
+
+        let script = script! {
+            for block_hash in block_hashes.iter() {
+                { BlockHashGadget::push_exact_bit_security_hint(block_hash) }
+            }
+            for (block_hash, bit_security) in block_hashes.iter().zip(bits_security.iter()) {
+                { BlockHashGadget::from_constant(block_hash) }
+                { BlockHashGadget::get_exact_bit_security(&HintSource::default()) }
+                { *bit_security }
+                OP_EQUALVERIFY
+            }
+            OP_TRUE
+        };
+
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
     }
 }
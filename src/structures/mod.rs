@@ -11,3 +11,5 @@ pub mod time;
 pub mod compact_target;
 
 pub mod nonce;
+
+pub mod amount;
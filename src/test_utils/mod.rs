@@ -0,0 +1,78 @@
+//! Helpers shared by the tests.
+
+use crate::covenant::PowSpvCovenant;
+use crate::network::NetworkParams;
+use bitcoin::block::Header;
+use bitcoin::consensus::Decodable;
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, Network, ScriptBuf, Transaction, TxMerkleNode};
+
+/// Mine a header on top of `prev` with the given merkle root, `600 + time_offset` seconds after
+/// it and with the same bits, which only takes a few tries with the bits of regtest.
+///
+/// Different time offsets give different headers on top of the same block, for forks.
+pub(crate) fn mine(prev: &Header, time_offset: u32, merkle_root: TxMerkleNode) -> Header {
+    let mut header = *prev;
+    header.prev_blockhash = prev.block_hash();
+    header.merkle_root = merkle_root;
+    header.time += 600 + time_offset;
+    header.nonce = 0;
+    while header.validate_pow(header.target()).is_err() {
+        header.nonce += 1;
+    }
+    header
+}
+
+/// Mine `n` headers on top of `prev` with [`mine`], without transactions.
+pub(crate) fn mine_chain(prev: &Header, n: usize, time_offset: u32) -> Vec<Header> {
+    let mut headers = vec![];
+    for _ in 0..n {
+        let prev = headers.last().unwrap_or(prev);
+        headers.push(mine(prev, time_offset, TxMerkleNode::all_zeros()));
+    }
+    headers
+}
+
+/// Mine a block with the given transactions on top of `prev` with [`mine`].
+pub(crate) fn mine_block(prev: &Header, time_offset: u32, txdata: Vec<Transaction>) -> Block {
+    let mut block = Block {
+        header: *prev,
+        txdata,
+    };
+    let merkle_root = block
+        .compute_merkle_root()
+        .unwrap_or(TxMerkleNode::all_zeros());
+    block.header = mine(prev, time_offset, merkle_root);
+    block
+}
+
+/// Load the mainnet block 845797, which is stored in hex.
+pub(crate) fn block_845797() -> Block {
+    let bytes = std::fs::read("./src/spv/block_845797").unwrap();
+    let encoded_block = hex::decode(&bytes).unwrap();
+    Block::consensus_decode(&mut encoded_block.as_slice()).unwrap()
+}
+
+/// The transaction with one input and two outputs in [`block_845797`] that the tests spend.
+pub(crate) fn given_tx() -> Transaction {
+    let given_tx_bytes = hex::decode("0200000000010152c0ef39e255fbe3858282c59ed3a3747b71bc17632daf1029e5f86e19761f290000000000fdffffff02e803000000000000220020ba714b93459645d8c931819b567a75b304eb8a69a3f71432f6ad3be9780b639c0085070000000000160014ba3cde39438c04d6645b8c130d36bb0c7cbf2fbd0247304402203d99f19bb84c2c8b60f6495b0851ff68d42527600735a521efcffe9549bcaa4002203b6cf74de4a1d36a4eb3cf8dd3e61d359dedd708153862501f9b439ab1da49d9012102113f09ba5f346c77205630298995acbe1f95c77c882b2c0e1408277e5290db4f9de70c00").unwrap();
+    Transaction::consensus_decode(&mut given_tx_bytes.as_slice()).unwrap()
+}
+
+/// A covenant on mainnet headers for a transaction with one input and two outputs, such as
+/// [`given_tx`].
+pub(crate) fn mainnet_covenant(
+    script_pub_key: &ScriptBuf,
+    num_headers: usize,
+    min_bit_security: usize,
+) -> PowSpvCovenant {
+    PowSpvCovenant::new(
+        NetworkParams::new(Network::Bitcoin),
+        script_pub_key.clone(),
+        1,
+        2,
+        num_headers,
+        min_bit_security,
+    )
+    .unwrap()
+}
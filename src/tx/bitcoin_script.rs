@@ -0,0 +1,1035 @@
+use crate::consensus_encode;
+use crate::hint::HintSource;
+use crate::sha256::{sha256_pad, Sha256Gadget};
+use crate::treepp::*;
+use crate::tx::{LargeTxFields, TxFields, MAX_LARGE_TX_BASE_SIZE, MAX_LARGE_TX_SCRIPT_SIZE};
+use crate::utils::{byte_to_byte_string, varint_from_number};
+use bitcoin::consensus::Encodable;
+use bitcoin::{OutPoint, Transaction, VarInt};
+use covenants_gadgets::wizards::tx::step3_input::{Step1OutPointGadget, Step3SequenceGadget};
+use covenants_gadgets::wizards::tx::step5_output::{Step1AmountGadget, Step2ScriptPubKeyGadget};
+use covenants_gadgets::wizards::tx::Step6LockTimeGadget;
+
+/// Gadget for rebuilding a confirmed transaction from the hint and reading its fields, such as
+/// to show that an output of a transaction proven by [`crate::spv::TxInclusionProofGadget`]
+/// pays some amount to some script pubkey.
+pub struct TxFieldsGadget;
+
+impl TxFieldsGadget {
+    /// Push the transaction as the hint, in the order that [`Self::compute_txid`] pulls it.
+    pub fn push_tx_as_hint(tx: &Transaction) -> Script {
+        script! {
+            { consensus_encode!(tx.version) }
+            { tx.input.len() }
+            for input in tx.input.iter() {
+                { consensus_encode!(input.previous_output) }
+                { input.script_sig.as_bytes().to_vec() }
+                { consensus_encode!(input.sequence) }
+            }
+            { tx.output.len() }
+            for output in tx.output.iter() {
+                { consensus_encode!(output.value) }
+                { output.script_pubkey.as_bytes().to_vec() }
+            }
+            { consensus_encode!(tx.lock_time) }
+        }
+    }
+
+    /// Push the transaction as the hint, in the order that [`Self::compute_txid_spending`]
+    /// pulls it, for the input at `input_index`.
+    pub fn push_tx_spending_as_hint(tx: &Transaction, input_index: usize) -> Script {
+        let mut rest = vec![];
+        for input in tx.input.iter().skip(input_index + 1) {
+            rest.extend(consensus_encode!(input));
+        }
+        rest.extend(consensus_encode!(tx.output));
+        rest.extend(consensus_encode!(tx.lock_time));
+
+        script! {
+            { consensus_encode!(tx.version) }
+            { tx.input.len() }
+            { input_index }
+            for input in tx.input.iter().take(input_index) {
+                { consensus_encode!(input.previous_output) }
+                { input.script_sig.as_bytes().to_vec() }
+                { consensus_encode!(input.sequence) }
+            }
+            { tx.input[input_index].script_sig.as_bytes().to_vec() }
+            { consensus_encode!(tx.input[input_index].sequence) }
+            { rest }
+        }
+    }
+
+    /// Rebuild a transaction that spends the outpoint in one of its inputs and compute its txid.
+    ///
+    /// The transaction is parsed from the start up to the input, which must be one of the first
+    /// `max_input_index + 1` inputs, so that the outpoint is the `previous_output` of an input
+    /// rather than any 36 bytes of the transaction. The rest is not parsed.
+    ///
+    /// hint:
+    ///     version
+    ///     number of inputs
+    ///     index of the input (smaller than the number of inputs)
+    ///     [outpoint, scriptSig, and sequence of each input before it]
+    ///     scriptSig of the input
+    ///     sequence of the input
+    ///     the rest of the transaction (the other inputs, the outputs, and the lock time)
+    ///
+    /// input:
+    ///
+    /// output:
+    ///     txid
+    ///
+    /// See [`crate::tx::check_tx_size`] for the transactions that can be rebuilt.
+    pub fn compute_txid_spending(
+        outpoint: &OutPoint,
+        max_input_index: usize,
+        hint: &HintSource,
+    ) -> Script {
+        script! {
+            // pull the version
+            { hint.pull(0, 0) }
+            OP_SIZE 4 OP_EQUALVERIFY
+
+            // pull the number of inputs
+            { hint.pull(1, 0) }
+            OP_1ADD OP_1SUB
+            OP_DUP { varint_from_number() }
+            OP_ROT OP_SWAP OP_CAT
+
+            // pull the index of the input
+            { hint.pull(2, 0) }
+            OP_DUP 0 { max_input_index + 1 } OP_WITHIN OP_VERIFY
+            OP_DUP 3 OP_PICK OP_LESSTHAN OP_VERIFY
+            OP_ROT OP_DROP
+
+            // stack: tx so far, index
+            for i in 0..max_input_index {
+                OP_DUP { i } OP_GREATERTHAN OP_IF
+                    OP_SWAP
+
+                    { hint.pull(2, 0) }
+                    { Step1OutPointGadget::from_provided() }
+                    OP_CAT
+
+                    { Self::script_sig_from_provided(hint, 2, 0) }
+                    OP_CAT
+
+                    { hint.pull(2, 0) }
+                    { Step3SequenceGadget::from_provided() }
+                    OP_CAT
+
+                    OP_SWAP
+                OP_ENDIF
+            }
+            OP_DROP
+
+            { consensus_encode!(outpoint) }
+            OP_CAT
+
+            { Self::script_sig_from_provided(hint, 1, 0) }
+            OP_CAT
+
+            { hint.pull(1, 0) }
+            { Step3SequenceGadget::from_provided() }
+            OP_CAT
+
+            // the rest is not parsed, as the input is already located
+            { hint.pull(1, 0) }
+            OP_CAT
+
+            // a 64-byte transaction could be confused with an inner node of the merkle tree
+            OP_SIZE 64 OP_NUMNOTEQUAL OP_VERIFY
+
+            OP_SHA256 OP_SHA256
+        }
+    }
+
+    /// Pull a scriptSig and prepend its length.
+    fn script_sig_from_provided(hint: &HintSource, main_above: usize, alt_above: usize) -> Script {
+        script! {
+            { hint.pull(main_above, alt_above) }
+            OP_SIZE { varint_from_number() }
+            OP_SWAP OP_CAT
+        }
+    }
+
+    /// Check that the number of inputs or outputs on the stack is between `min` and `max`,
+    /// keep a copy of it on the altstack, and turn it into its varint.
+    fn counter_from_provided(min: usize, max: usize) -> Script {
+        script! {
+            // normalize the encoding of the number
+            OP_1ADD OP_1SUB
+            OP_DUP { min } { max + 1 } OP_WITHIN OP_VERIFY
+            OP_DUP OP_TOALTSTACK
+            { varint_from_number() }
+        }
+    }
+
+    /// Rebuild the transaction and compute its txid, keeping a copy of the exposed fields.
+    ///
+    /// The transaction can have up to `max_inputs` inputs and `max_outputs` outputs. The inputs
+    /// and outputs that are exposed, and those before them, are always rebuilt, and the others
+    /// only if the numbers of inputs and outputs in the hint include them.
+    ///
+    /// hint:
+    ///     version
+    ///     number of inputs
+    ///     [outpoint, scriptSig, and sequence of each input]
+    ///     number of outputs
+    ///     [amount and script pubkey of each output]
+    ///     lock time
+    ///
+    /// input:
+    ///
+    /// output:
+    ///     [outpoint of each exposed input]
+    ///     [amount and script pubkey of each exposed output]
+    ///     txid
+    ///
+    /// See [`TxFields::check`] for the transactions that can be rebuilt.
+    pub fn compute_txid(fields: &TxFields, hint: &HintSource) -> Script {
+        // the number of exposed fields, which sit below the transaction being rebuilt
+        let mut num_exposed = 0;
+
+        // the inputs and outputs up to the last exposed one must be present
+        let min_inputs = fields.inputs.last().map_or(1, |i| i + 1);
+        let min_outputs = fields.outputs.last().map_or(1, |i| i + 1);
+
+        let mut script = script! {
+            // pull the version
+            { hint.pull(0, 0) }
+            OP_SIZE 4 OP_EQUALVERIFY
+
+            // pull the number of inputs
+            { hint.pull(1, 0) }
+            { Self::counter_from_provided(min_inputs, fields.max_inputs) }
+            OP_CAT
+        };
+
+        for i in 0..fields.max_inputs {
+            let exposed = fields.inputs.contains(&i);
+            let input = script! {
+                { hint.pull(num_exposed + 1, 1) }
+                if exposed {
+                    OP_TUCK
+                }
+                { Step1OutPointGadget::from_provided() }
+                OP_CAT
+
+                { Self::script_sig_from_provided(hint, num_exposed + if exposed { 2 } else { 1 }, 1) }
+                OP_CAT
+
+                { hint.pull(num_exposed + if exposed { 2 } else { 1 }, 1) }
+                { Step3SequenceGadget::from_provided() }
+                OP_CAT
+            };
+
+            script = script! {
+                { script }
+                if i < min_inputs {
+                    { input }
+                } else {
+                    OP_FROMALTSTACK OP_DUP OP_TOALTSTACK
+                    { i } OP_GREATERTHAN OP_IF
+                        { input }
+                    OP_ENDIF
+                }
+            };
+            if exposed {
+                num_exposed += 1;
+            }
+        }
+
+        script = script! {
+            { script }
+            OP_FROMALTSTACK OP_DROP
+
+            // pull the number of outputs
+            { hint.pull(num_exposed + 1, 0) }
+            { Self::counter_from_provided(min_outputs, fields.max_outputs) }
+            OP_CAT
+        };
+
+        for i in 0..fields.max_outputs {
+            let exposed = fields.outputs.contains(&i);
+            let output = script! {
+                { hint.pull(num_exposed + 1, 1) }
+                if exposed {
+                    OP_TUCK
+                }
+                { Step1AmountGadget::from_provided() }
+                OP_CAT
+
+                { hint.pull(num_exposed + if exposed { 2 } else { 1 }, 1) }
+                if exposed {
+                    OP_TUCK
+                }
+                { Step2ScriptPubKeyGadget::from_provided() }
+                OP_CAT
+            };
+
+            script = script! {
+                { script }
+                if i < min_outputs {
+                    { output }
+                } else {
+                    OP_FROMALTSTACK OP_DUP OP_TOALTSTACK
+                    { i } OP_GREATERTHAN OP_IF
+                        { output }
+                    OP_ENDIF
+                }
+            };
+            if exposed {
+                num_exposed += 2;
+            }
+        }
+
+        script! {
+            { script }
+            OP_FROMALTSTACK OP_DROP
+
+            { hint.pull(num_exposed + 1, 0) }
+            { Step6LockTimeGadget::from_provided() }
+            OP_CAT
+
+            // a 64-byte transaction could be confused with an inner node of the merkle tree
+            OP_SIZE 64 OP_NUMNOTEQUAL OP_VERIFY
+
+            OP_SHA256 OP_SHA256
+        }
+    }
+
+    /// Push the transaction as the hint, in the order that
+    /// [`Self::compute_txid_with_last_output`] pulls it.
+    pub fn push_tx_with_last_output_as_hint(tx: &Transaction) -> Script {
+        let mut blocks = BlockSplitter::default();
+
+        blocks.piece(
+            script! { { consensus_encode!(tx.version) } },
+            consensus_encode!(tx.version),
+        );
+        blocks.piece(
+            script! { { tx.input.len() } },
+            consensus_encode!(VarInt(tx.input.len() as u64)),
+        );
+        for input in tx.input.iter() {
+            blocks.piece(
+                script! { { consensus_encode!(input.previous_output) } },
+                consensus_encode!(input.previous_output),
+            );
+            blocks.piece(
+                script! { { input.script_sig.as_bytes().to_vec() } },
+                consensus_encode!(input.script_sig),
+            );
+            blocks.piece(
+                script! { { consensus_encode!(input.sequence) } },
+                consensus_encode!(input.sequence),
+            );
+        }
+        blocks.piece(
+            script! { { tx.output.len() } },
+            consensus_encode!(VarInt(tx.output.len() as u64)),
+        );
+        for output in tx.output.iter() {
+            blocks.piece(
+                script! { { consensus_encode!(output.value) } },
+                consensus_encode!(output.value),
+            );
+            blocks.piece(
+                script! { { output.script_pubkey.as_bytes().to_vec() } },
+                consensus_encode!(output.script_pubkey),
+            );
+        }
+        blocks.piece(
+            script! { { consensus_encode!(tx.lock_time) } },
+            consensus_encode!(tx.lock_time),
+        );
+
+        // the bytes not yet in a block, with the padding, make one or two last blocks
+        let mut message = blocks.blocks.concat();
+        message.extend(&blocks.rest);
+        let padded = sha256_pad(&message);
+        let last = padded[blocks.blocks.len() * 64..].to_vec();
+        if last.len() == 128 {
+            blocks.split(&last);
+            let rest = blocks.rest.clone();
+            blocks.blocks.push(rest);
+        } else {
+            blocks.blocks.push(last);
+        }
+
+        script! {
+            for hint in blocks.hints.into_iter() {
+                { hint }
+            }
+            for block in blocks.blocks.iter() {
+                { Sha256Gadget::push_compress_hint(block) }
+            }
+        }
+    }
+
+    /// Rebuild a transaction that can be larger than a stack element and compute its txid,
+    /// keeping a copy of its last output.
+    ///
+    /// The transaction is rebuilt as in [`Self::compute_txid`], but each time it reaches 64
+    /// bytes, the first 64 bytes are split off into a block and moved to the altstack. The last
+    /// bytes are then padded, and the blocks go through [`Sha256Gadget::compress`] one after the
+    /// other, before `OP_SHA256` of the digest gives the txid. The script is about 311 KB for
+    /// each block of a transaction of `fields.max_size` bytes, which makes the spending
+    /// transaction non-standard, see [`MAX_LARGE_TX_BASE_SIZE`].
+    ///
+    /// The hint must come from the bottom of the stack, as the rebuilt transaction is spread over
+    /// a varying number of elements.
+    ///
+    /// hint:
+    ///     version
+    ///     number of inputs
+    ///     [outpoint, scriptSig, and sequence of each input]
+    ///     number of outputs
+    ///     [amount and script pubkey of each output]
+    ///     lock time
+    ///     interleaved with each block split off and the rest after it
+    ///     [the hint of `Sha256Gadget::compress` for each block]
+    ///
+    /// input:
+    ///
+    /// output:
+    ///     amount of the last output
+    ///     script pubkey of the last output
+    ///     txid
+    ///
+    /// See [`LargeTxFields::check`] for the transactions that can be rebuilt.
+    pub fn compute_txid_with_last_output(fields: &LargeTxFields, hint: &HintSource) -> Script {
+        assert!(
+            matches!(hint, HintSource::Bottom { .. }),
+            "The hint of a large transaction must come from the bottom of the stack."
+        );
+        assert!(fields.max_inputs > 0 && fields.max_outputs > 0);
+        assert!(fields.max_size <= MAX_LARGE_TX_BASE_SIZE);
+
+        // a scriptSig or script pubkey and its length can fill up to eight blocks
+        let max_script_blocks = (63 + 3 + MAX_LARGE_TX_SCRIPT_SIZE) / 64;
+        let compress = Sha256Gadget::compress(hint);
+
+        let mut script = script! {
+            // the number of blocks split off, which stays on top of them on the altstack
+            0 OP_TOALTSTACK
+
+            // pull the version
+            { hint.pull(0, 0) }
+            OP_SIZE 4 OP_EQUALVERIFY
+
+            // pull the number of inputs
+            { hint.pull(0, 0) }
+            { Self::large_counter_from_provided(fields.max_inputs) }
+            OP_ROT OP_SWAP OP_CAT
+            { Self::split_blocks(1, hint) }
+        };
+
+        // stack: number of inputs, tx not yet in a block
+        for i in 0..fields.max_inputs {
+            let input = script! {
+                { hint.pull(0, 0) }
+                { Step1OutPointGadget::from_provided() }
+                OP_CAT
+                { Self::split_blocks(1, hint) }
+
+                { Self::script_sig_from_provided(hint, 0, 0) }
+                OP_CAT
+                { Self::split_blocks(max_script_blocks, hint) }
+
+                { hint.pull(0, 0) }
+                { Step3SequenceGadget::from_provided() }
+                OP_CAT
+                { Self::split_blocks(1, hint) }
+            };
+
+            script = script! {
+                { script }
+                if i < 1 {
+                    { input }
+                } else {
+                    OP_OVER { i } OP_GREATERTHAN OP_IF
+                        { input }
+                    OP_ENDIF
+                }
+            };
+        }
+
+        script = script! {
+            { script }
+            OP_NIP
+
+            // pull the number of outputs
+            { hint.pull(0, 0) }
+            { Self::large_counter_from_provided(fields.max_outputs) }
+            OP_ROT OP_SWAP OP_CAT
+            { Self::split_blocks(1, hint) }
+        };
+
+        // stack: number of outputs, [amount and script pubkey of the last output so far],
+        // tx not yet in a block
+        for i in 0..fields.max_outputs {
+            let output = script! {
+                if i > 0 {
+                    OP_NIP OP_NIP
+                }
+
+                { hint.pull(0, 0) }
+                OP_TUCK
+                { Step1AmountGadget::from_provided() }
+                OP_CAT
+                { Self::split_blocks(1, hint) }
+
+                { hint.pull(0, 0) }
+                OP_TUCK
+                { Step2ScriptPubKeyGadget::from_provided() }
+                OP_CAT
+                { Self::split_blocks(max_script_blocks, hint) }
+            };
+
+            script = script! {
+                { script }
+                if i < 1 {
+                    { output }
+                } else {
+                    3 OP_PICK { i } OP_GREATERTHAN OP_IF
+                        { output }
+                    OP_ENDIF
+                }
+            };
+        }
+
+        script! {
+            { script }
+            3 OP_ROLL OP_DROP
+
+            { hint.pull(0, 0) }
+            { Step6LockTimeGadget::from_provided() }
+            OP_CAT
+            { Self::split_blocks(1, hint) }
+
+            // stack: amount, script pubkey, tx not yet in a block
+            // altstack: [blocks], number of blocks
+            OP_SIZE OP_DUP
+            OP_FROMALTSTACK OP_DUP OP_TOALTSTACK
+
+            // a 64-byte transaction could be confused with an inner node of the merkle tree
+            OP_2DUP 1 OP_NUMEQUAL OP_SWAP 0 OP_NUMEQUAL OP_BOOLAND OP_NOT OP_VERIFY
+
+            // the transaction is at most max_size bytes, which also bounds the number of blocks
+            for _ in 0..6 {
+                OP_DUP OP_ADD
+            }
+            OP_ADD { fields.max_size + 1 } OP_LESSTHAN OP_VERIFY
+
+            // the padding is 0x80, zeros, and the length in bits as eight big-endian bytes, of
+            // which only the last two can be nonzero, and for k bytes not yet in a block and n
+            // blocks, 8 * k is 256 * c + b and the other byte is 2 * n + c
+            for k in 0..64 {
+                OP_DUP { k } OP_EQUAL OP_IF
+                    { Self::padding_prefix(k) }
+                    { vec![((8 * k) & 0xff) as u8] }
+                    { (8 * k) >> 8 }
+                    3 OP_ROLL
+                OP_ENDIF
+            }
+            OP_DROP
+
+            // stack: amount, script pubkey, tx not yet in a block, padding prefix, b, c
+            OP_FROMALTSTACK OP_TUCK OP_DUP OP_ADD OP_ADD
+            { byte_to_byte_string() }
+            OP_ROT OP_CAT
+            OP_ROT OP_SWAP OP_CAT
+            OP_ROT OP_SWAP OP_CAT
+
+            // stack: amount, script pubkey, n, last one or two blocks
+            OP_SIZE 128 OP_EQUAL
+            OP_DUP OP_1ADD 3 OP_PICK OP_ADD
+            OP_ROT OP_ROT
+            OP_IF
+                { Self::split_block(hint) }
+                OP_SWAP OP_2SWAP
+            OP_ELSE
+                OP_ROT OP_ROT
+            OP_ENDIF
+
+            // stack: amount, script pubkey, [last blocks, the last one deepest], n, number of all blocks
+            for i in 0..fields.max_size / 64 {
+                OP_OVER { i } OP_GREATERTHAN OP_IF
+                    OP_FROMALTSTACK OP_ROT OP_ROT
+                OP_ENDIF
+            }
+            OP_NIP
+
+            // stack: amount, script pubkey, [blocks, the first on top], number of blocks
+            { Sha256Gadget::push_initial_state() }
+            for i in 0..fields.max_blocks() {
+                if i < 1 {
+                    17 OP_ROLL
+                    { compress.clone() }
+                } else {
+                    16 OP_PICK { i } OP_GREATERTHAN OP_IF
+                        17 OP_ROLL
+                        { compress.clone() }
+                    OP_ENDIF
+                }
+            }
+            { Sha256Gadget::state_to_digest() }
+            OP_NIP
+
+            OP_SHA256
+        }
+    }
+
+    /// Check that the number of inputs or outputs on the stack is between 1 and `max`, keep it,
+    /// and push its varint.
+    fn large_counter_from_provided(max: usize) -> Script {
+        script! {
+            // normalize the encoding of the number
+            OP_1ADD OP_1SUB
+            OP_DUP 1 { max + 1 } OP_WITHIN OP_VERIFY
+            OP_DUP { varint_from_number() }
+        }
+    }
+
+    /// Pull the first 64 bytes of the element on top and the rest, and check that they make it.
+    fn split_block(hint: &HintSource) -> Script {
+        script! {
+            { hint.pull(0, 0) }
+            OP_SIZE 64 OP_EQUALVERIFY
+            { hint.pull(0, 0) }
+            OP_2DUP OP_CAT 3 OP_ROLL OP_EQUALVERIFY
+        }
+    }
+
+    /// Move the first 64 bytes of the tx not yet in a block on top to the altstack, as long as
+    /// there are 64 bytes and at most `max_blocks` times, counting them in the number on top of
+    /// the altstack.
+    fn split_blocks(max_blocks: usize, hint: &HintSource) -> Script {
+        script! {
+            for _ in 0..max_blocks {
+                OP_SIZE 64 OP_GREATERTHANOREQUAL OP_IF
+                    { Self::split_block(hint) }
+                    OP_FROMALTSTACK OP_1ADD
+                    OP_ROT OP_TOALTSTACK OP_TOALTSTACK
+                OP_ENDIF
+            }
+        }
+    }
+
+    /// The padding of SHA-256 after `k` bytes not yet in a block, up to the last two bytes.
+    fn padding_prefix(k: usize) -> Vec<u8> {
+        let mut prefix = vec![0x80];
+        prefix.resize(1 + (119 - k) % 64 + 6, 0);
+        prefix
+    }
+}
+
+/// The hint of the blocks split off a transaction as it is rebuilt, as
+/// [`TxFieldsGadget::compute_txid_with_last_output`] does.
+#[derive(Default)]
+struct BlockSplitter {
+    hints: Vec<Script>,
+    rest: Vec<u8>,
+    blocks: Vec<Vec<u8>>,
+}
+
+impl BlockSplitter {
+    /// Add a piece of the transaction, with its hint, and split off the blocks that it fills.
+    fn piece(&mut self, hint: Script, bytes: Vec<u8>) {
+        self.hints.push(hint);
+        self.rest.extend(bytes);
+        while self.rest.len() >= 64 {
+            let bytes = self.rest.clone();
+            self.split(&bytes);
+        }
+    }
+
+    /// Split the first 64 bytes off into a block.
+    fn split(&mut self, bytes: &[u8]) {
+        self.hints.push(script! {
+            { bytes[..64].to_vec() }
+            { bytes[64..].to_vec() }
+        });
+        self.blocks.push(bytes[..64].to_vec());
+        self.rest = bytes[64..].to_vec();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::consensus_encode;
+    use crate::hint::HintSource;
+    use crate::spv::{TxInclusionProof, TxInclusionProofGadget};
+    use crate::structures::amount::AmountGadget;
+    use crate::test_utils::block_845797;
+    use crate::treepp::*;
+    use crate::tx::{
+        check_tx_size, LargeTxFields, TxFields, TxFieldsGadget, MAX_LARGE_TX_BASE_SIZE,
+    };
+    use bitcoin::absolute::LockTime;
+    use bitcoin::consensus::Encodable;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+
+    #[test]
+    fn test_tx_fields() {
+        let block = block_845797();
+
+        // a transaction with two inputs spending legacy outputs and three outputs
+        let tx = &block.txdata[541];
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+
+        let fields = TxFields {
+            max_inputs: 4,
+            max_outputs: 5,
+            inputs: vec![1],
+            outputs: vec![0, 2],
+        };
+        fields.check(tx).unwrap();
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            let script = script! {
+                { b"unrelated".to_vec() }
+                { hint.push(TxFieldsGadget::push_tx_as_hint(tx)) }
+
+                { TxFieldsGadget::compute_txid(&fields, &hint) }
+                { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+                for _ in 0..fields.num_exposed() {
+                    OP_DROP
+                }
+                { b"unrelated".to_vec() } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // output 2 of the confirmed transaction pays at least this amount to this script pubkey
+        let script = script! {
+            { TxFieldsGadget::push_tx_as_hint(tx) }
+            { TxInclusionProofGadget::push_tx_inclusion_proof_as_hint(&proof) }
+            { AmountGadget::push_at_least_hint(tx.output[2].value) }
+
+            { TxFieldsGadget::compute_txid(&fields, &HintSource::default()) }
+            { TxInclusionProofGadget::compute_merkle_root(&HintSource::default()) }
+            { block.header.merkle_root.as_byte_array().to_vec() }
+            OP_EQUALVERIFY
+
+            { tx.output[2].script_pubkey.as_bytes().to_vec() } OP_EQUALVERIFY
+            { AmountGadget::check_at_least(tx.output[2].value, &HintSource::default()) }
+
+            { tx.output[0].script_pubkey.as_bytes().to_vec() } OP_EQUALVERIFY
+            { consensus_encode!(tx.output[0].value) } OP_EQUALVERIFY
+
+            { consensus_encode!(tx.input[1].previous_output) } OP_EQUAL
+        };
+
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // a different amount in an exposed output leads to another txid
+        let mut other = tx.clone();
+        other.output[0].value += bitcoin::Amount::from_sat(1);
+        let script = script! {
+            { TxFieldsGadget::push_tx_as_hint(&other) }
+            { TxFieldsGadget::compute_txid(&fields, &HintSource::default()) }
+            { tx.compute_txid().as_byte_array().to_vec() }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_tx_fields_shapes() {
+        let block = block_845797();
+
+        let fields = TxFields {
+            max_inputs: 3,
+            max_outputs: 4,
+            inputs: vec![0],
+            outputs: vec![0],
+        };
+        let script = TxFieldsGadget::compute_txid(&fields, &HintSource::default());
+
+        // segwit and legacy transactions with one to three inputs and one to four outputs
+        let txs = block
+            .txdata
+            .iter()
+            .skip(1)
+            .filter(|tx| fields.check(tx).is_ok())
+            .take(30)
+            .collect::<Vec<_>>();
+        assert_eq!(txs.len(), 30);
+
+        for tx in txs {
+            let exec_script = script! {
+                { TxFieldsGadget::push_tx_as_hint(tx) }
+                { script.clone() }
+                { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+                { tx.output[0].script_pubkey.as_bytes().to_vec() } OP_EQUALVERIFY
+                { consensus_encode!(tx.output[0].value) } OP_EQUALVERIFY
+                { consensus_encode!(tx.input[0].previous_output) } OP_EQUAL
+            };
+
+            let exec_result = execute_script(exec_script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_tx_too_large() {
+        let block = block_845797();
+
+        // a transaction with seven inputs and two outputs, which is 522 bytes without the witness
+        let tx = &block.txdata[54];
+        assert_eq!(tx.base_size(), 522);
+
+        let fields = TxFields {
+            max_inputs: 7,
+            max_outputs: 2,
+            inputs: vec![0],
+            outputs: vec![0],
+        };
+        assert!(check_tx_size(tx).is_err());
+        assert!(fields.check(tx).is_err());
+
+        // the script cannot concatenate the transaction into a single stack element
+        let script = script! {
+            { TxFieldsGadget::push_tx_as_hint(tx) }
+            { TxFieldsGadget::compute_txid(&fields, &HintSource::default()) }
+            { tx.compute_txid().as_byte_array().to_vec() } OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+
+        let script = script! {
+            { TxFieldsGadget::push_tx_spending_as_hint(tx, 6) }
+            { TxFieldsGadget::compute_txid_spending(&tx.input[6].previous_output, 6, &HintSource::default()) }
+            { tx.compute_txid().as_byte_array().to_vec() } OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+
+        // every transaction of the block that fits is accepted
+        for tx in block.txdata.iter() {
+            assert_eq!(check_tx_size(tx).is_ok(), tx.base_size() <= 520);
+        }
+    }
+
+    #[test]
+    fn test_tx_with_last_output() {
+        let block = block_845797();
+
+        // a transaction with seven inputs and two outputs, which is 522 bytes without the witness
+        let tx = &block.txdata[54];
+        let proof = TxInclusionProof::construct_from_block(&block, &tx.compute_txid()).unwrap();
+
+        let fields = LargeTxFields {
+            max_inputs: 7,
+            max_outputs: 2,
+            max_size: 600,
+        };
+        fields.check(tx).unwrap();
+        assert_eq!(fields.max_blocks(), 10);
+
+        let compute_txid =
+            TxFieldsGadget::compute_txid_with_last_output(&fields, &HintSource::default());
+
+        let script = script! {
+            { TxFieldsGadget::push_tx_with_last_output_as_hint(tx) }
+            { TxInclusionProofGadget::push_tx_inclusion_proof_as_hint(&proof) }
+            { compute_txid.clone() }
+            { TxInclusionProofGadget::compute_merkle_root(&HintSource::default()) }
+            { block.header.merkle_root.as_byte_array().to_vec() } OP_EQUALVERIFY
+            { tx.output[1].script_pubkey.as_bytes().to_vec() } OP_EQUALVERIFY
+            { consensus_encode!(tx.output[1].value) } OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // another amount in the last output gives another txid
+        let mut other = tx.clone();
+        other.output[1].value += Amount::from_sat(1);
+        let script = script! {
+            { TxFieldsGadget::push_tx_with_last_output_as_hint(&other) }
+            { compute_txid.clone() }
+            { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+            OP_2DROP OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+
+        // the bounds are enforced
+        for fields in [
+            LargeTxFields {
+                max_inputs: 6,
+                ..fields.clone()
+            },
+            LargeTxFields {
+                max_outputs: 1,
+                ..fields.clone()
+            },
+            LargeTxFields {
+                max_size: 521,
+                ..fields.clone()
+            },
+            LargeTxFields {
+                max_size: MAX_LARGE_TX_BASE_SIZE + 1,
+                ..fields.clone()
+            },
+        ] {
+            assert!(fields.check(tx).is_err());
+        }
+
+        // the largest transaction keeps the script under the weight of a block
+        let largest = LargeTxFields {
+            max_size: MAX_LARGE_TX_BASE_SIZE,
+            ..fields.clone()
+        };
+        assert_eq!(largest.max_blocks(), 12);
+        assert!(
+            TxFieldsGadget::compute_txid_with_last_output(&largest, &HintSource::default()).len()
+                < 4_000_000
+        );
+        let short = LargeTxFields {
+            max_inputs: 7,
+            max_outputs: 2,
+            max_size: 521,
+        };
+        let script = script! {
+            { TxFieldsGadget::push_tx_with_last_output_as_hint(tx) }
+            { TxFieldsGadget::compute_txid_with_last_output(&short, &HintSource::default()) }
+            OP_DROP OP_2DROP OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+
+        // smaller transactions, whose padding takes one more block or not
+        let fields = LargeTxFields {
+            max_inputs: 3,
+            max_outputs: 3,
+            max_size: 400,
+        };
+        let compute_txid =
+            TxFieldsGadget::compute_txid_with_last_output(&fields, &HintSource::default());
+        for two_last_blocks in [false, true] {
+            let tx = block
+                .txdata
+                .iter()
+                .find(|tx| {
+                    fields.check(tx).is_ok() && (tx.base_size() % 64 >= 56) == two_last_blocks
+                })
+                .unwrap();
+            let last_output = tx.output.last().unwrap();
+
+            let script = script! {
+                { TxFieldsGadget::push_tx_with_last_output_as_hint(tx) }
+                { compute_txid.clone() }
+                { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+                { last_output.script_pubkey.as_bytes().to_vec() } OP_EQUALVERIFY
+                { consensus_encode!(last_output.value) } OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_tx_spending() {
+        let block = block_845797();
+
+        let tx = &block.txdata[541];
+
+        for hint in [
+            HintSource::Bottom { offset: 1 },
+            HintSource::AltStack,
+            HintSource::Inline,
+        ] {
+            for input_index in 0..2 {
+                let script = script! {
+                    { b"unrelated".to_vec() }
+                    { hint.push(TxFieldsGadget::push_tx_spending_as_hint(tx, input_index)) }
+
+                    { TxFieldsGadget::compute_txid_spending(&tx.input[input_index].previous_output, 2, &hint) }
+                    { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+                    { b"unrelated".to_vec() } OP_EQUAL
+                };
+
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+        }
+
+        // the input must be among the first max_input_index + 1 inputs
+        let script = script! {
+            { TxFieldsGadget::push_tx_spending_as_hint(tx, 1) }
+            { TxFieldsGadget::compute_txid_spending(&tx.input[1].previous_output, 0, &HintSource::default()) }
+            OP_DROP OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_tx_64_bytes() {
+        // a transaction of 64 bytes without the witness, which could be confused with an inner
+        // node of the merkle tree
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from_bytes(vec![0x01, 0x01]),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::from_bytes(vec![0x51, 0x51]),
+            }],
+        };
+        assert_eq!(tx.base_size(), 64);
+
+        let fields = TxFields {
+            max_inputs: 1,
+            max_outputs: 1,
+            inputs: vec![0],
+            outputs: vec![0],
+        };
+        assert!(fields.check(&tx).is_err());
+
+        let script = script! {
+            { TxFieldsGadget::push_tx_as_hint(&tx) }
+            { TxFieldsGadget::compute_txid(&fields, &HintSource::default()) }
+            { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+            OP_2DROP OP_DROP OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+
+        let fields = LargeTxFields {
+            max_inputs: 1,
+            max_outputs: 1,
+            max_size: 100,
+        };
+        assert!(fields.check(&tx).is_err());
+
+        let script = script! {
+            { TxFieldsGadget::push_tx_with_last_output_as_hint(&tx) }
+            { TxFieldsGadget::compute_txid_with_last_output(&fields, &HintSource::default()) }
+            { tx.compute_txid().as_byte_array().to_vec() } OP_EQUALVERIFY
+            OP_2DROP OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+}
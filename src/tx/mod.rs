@@ -0,0 +1,215 @@
+use anyhow::{Error, Result};
+use bitcoin::Transaction;
+
+mod bitcoin_script;
+pub use bitcoin_script::*;
+
+/// The largest transaction, without the witness, that can be rebuilt in script.
+pub const MAX_TX_BASE_SIZE: usize = 520;
+
+/// The largest transaction, without the witness, that
+/// [`TxFieldsGadget::compute_txid_with_last_output`] can rebuild.
+///
+/// Each 64-byte block of the padded transaction takes about 311 KB of script, so that the 12
+/// blocks of a 759-byte transaction keep the script under the 4,000,000 weight units of a block.
+/// Such a script is far over the 400,000 weight units of a standard transaction, so the
+/// spending transaction is never relayed and has to be sent to a miner directly.
+pub const MAX_LARGE_TX_BASE_SIZE: usize = 64 * 12 - 9;
+
+/// The largest scriptSig or script pubkey that
+/// [`TxFieldsGadget::compute_txid_with_last_output`] can rebuild, so that with its length and
+/// the at most 63 bytes not yet in a block it fits in a stack element.
+pub const MAX_LARGE_TX_SCRIPT_SIZE: usize = 454;
+
+/// Check that the transaction, without the witness, can be rebuilt in script to compute its txid.
+///
+/// [`TxFieldsGadget::compute_txid`] and [`TxFieldsGadget::compute_txid_spending`] hash the whole
+/// transaction without the witness with `OP_SHA256 OP_SHA256`, so it has to be a single stack
+/// element, which is at most 520 bytes. Larger transactions are rejected here rather than failing
+/// with an `OP_CAT` error during execution. They can still be rebuilt by
+/// [`TxFieldsGadget::compute_txid_with_last_output`], which runs the SHA-256 compression over
+/// 64-byte blocks in script. Hashing only a suffix in script from a midstate computed off-stack
+/// would not be sound, as the script could not check that the midstate comes from the prefix.
+///
+/// A 64-byte transaction is also rejected, as it could be confused with an inner node of the
+/// merkle tree.
+pub fn check_tx_size(tx: &Transaction) -> Result<()> {
+    let base_size = tx.base_size();
+    if base_size > MAX_TX_BASE_SIZE {
+        return Err(Error::msg(format!(
+            "The transaction without the witness is {} bytes, more than the {} bytes that can be hashed in script.",
+            base_size, MAX_TX_BASE_SIZE
+        )));
+    }
+    if base_size == 64 {
+        return Err(Error::msg(
+            "The transaction without the witness is 64 bytes, which could be confused with an inner node of the merkle tree.",
+        ));
+    }
+    Ok(())
+}
+
+/// The bounds on the numbers of inputs and outputs of a confirmed transaction and the fields of
+/// it that [`TxFieldsGadget`] exposes on the stack after rebuilding it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxFields {
+    pub max_inputs: usize,
+    pub max_outputs: usize,
+    /// The inputs whose outpoints are exposed, in increasing order.
+    pub inputs: Vec<usize>,
+    /// The outputs whose amounts and script pubkeys are exposed, in increasing order.
+    pub outputs: Vec<usize>,
+}
+
+impl TxFields {
+    /// Check that the transaction is within the bounds, has the exposed inputs and outputs, and
+    /// can be rebuilt in script.
+    ///
+    /// The size of the transaction is checked with [`check_tx_size`].
+    pub fn check(&self, tx: &Transaction) -> Result<()> {
+        if self.max_inputs > 0x7fff || self.max_outputs > 0x7fff {
+            return Err(Error::msg(
+                "The numbers of inputs and outputs must be at most 32767.",
+            ));
+        }
+        if self.inputs.windows(2).any(|w| w[0] >= w[1])
+            || self.inputs.iter().any(|i| *i >= self.max_inputs)
+            || self.outputs.windows(2).any(|w| w[0] >= w[1])
+            || self.outputs.iter().any(|i| *i >= self.max_outputs)
+        {
+            return Err(Error::msg(
+                "The exposed inputs and outputs must be distinct, increasing, and in range.",
+            ));
+        }
+        let min_inputs = self.inputs.last().map_or(1, |i| i + 1);
+        let min_outputs = self.outputs.last().map_or(1, |i| i + 1);
+        if !(min_inputs..=self.max_inputs).contains(&tx.input.len())
+            || !(min_outputs..=self.max_outputs).contains(&tx.output.len())
+        {
+            return Err(Error::msg(format!(
+                "The transaction has {} inputs and {} outputs, but {} to {} inputs and {} to {} outputs are expected.",
+                tx.input.len(),
+                tx.output.len(),
+                min_inputs,
+                self.max_inputs,
+                min_outputs,
+                self.max_outputs
+            )));
+        }
+        check_tx_size(tx)
+    }
+
+    /// The number of elements that the gadget exposes below the txid.
+    pub fn num_exposed(&self) -> usize {
+        self.inputs.len() + 2 * self.outputs.len()
+    }
+}
+
+/// The bounds on a confirmed transaction, possibly larger than a stack element, that
+/// [`TxFieldsGadget::compute_txid_with_last_output`] rebuilds in 64-byte blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LargeTxFields {
+    pub max_inputs: usize,
+    pub max_outputs: usize,
+    /// The largest size of the transaction without the witness.
+    pub max_size: usize,
+}
+
+impl LargeTxFields {
+    /// Check that the transaction is within the bounds and can be rebuilt in script.
+    pub fn check(&self, tx: &Transaction) -> Result<()> {
+        if !(1..=0x7fff).contains(&self.max_inputs) || !(1..=0x7fff).contains(&self.max_outputs) {
+            return Err(Error::msg(
+                "The numbers of inputs and outputs must be between 1 and 32767.",
+            ));
+        }
+        if self.max_size > MAX_LARGE_TX_BASE_SIZE {
+            return Err(Error::msg(format!(
+                "The transaction size must be at most {} bytes.",
+                MAX_LARGE_TX_BASE_SIZE
+            )));
+        }
+        if !(1..=self.max_inputs).contains(&tx.input.len())
+            || !(1..=self.max_outputs).contains(&tx.output.len())
+        {
+            return Err(Error::msg(format!(
+                "The transaction has {} inputs and {} outputs, but at most {} inputs and {} outputs are expected.",
+                tx.input.len(),
+                tx.output.len(),
+                self.max_inputs,
+                self.max_outputs
+            )));
+        }
+        let base_size = tx.base_size();
+        if base_size > self.max_size {
+            return Err(Error::msg(format!(
+                "The transaction without the witness is {} bytes, more than the {} bytes expected.",
+                base_size, self.max_size
+            )));
+        }
+        if base_size == 64 {
+            return Err(Error::msg(
+                "The transaction without the witness is 64 bytes, which could be confused with an inner node of the merkle tree.",
+            ));
+        }
+        if tx
+            .input
+            .iter()
+            .map(|input| input.script_sig.len())
+            .chain(tx.output.iter().map(|output| output.script_pubkey.len()))
+            .any(|len| len > MAX_LARGE_TX_SCRIPT_SIZE)
+        {
+            return Err(Error::msg(format!(
+                "The scriptSigs and script pubkeys must be at most {} bytes.",
+                MAX_LARGE_TX_SCRIPT_SIZE
+            )));
+        }
+        Ok(())
+    }
+
+    /// The number of 64-byte blocks of the largest transaction once padded for SHA-256.
+    pub fn max_blocks(&self) -> usize {
+        (self.max_size + 9).div_ceil(64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::given_tx;
+    use crate::tx::TxFields;
+
+    #[test]
+    fn test_tx_fields_check() {
+        let tx = given_tx();
+
+        let fields = TxFields {
+            max_inputs: 1,
+            max_outputs: 2,
+            inputs: vec![0],
+            outputs: vec![0, 1],
+        };
+        fields.check(&tx).unwrap();
+        assert_eq!(fields.num_exposed(), 5);
+
+        let mut more_outputs = fields.clone();
+        more_outputs.max_outputs = 3;
+        more_outputs.check(&tx).unwrap();
+
+        let mut fewer_outputs = fields.clone();
+        fewer_outputs.max_outputs = 1;
+        fewer_outputs.outputs = vec![0];
+        assert!(fewer_outputs.check(&tx).is_err());
+
+        let mut missing_output = more_outputs.clone();
+        missing_output.outputs = vec![2];
+        assert!(missing_output.check(&tx).is_err());
+
+        let mut out_of_range = fields.clone();
+        out_of_range.outputs = vec![2];
+        assert!(out_of_range.check(&tx).is_err());
+
+        let mut unordered = fields.clone();
+        unordered.outputs = vec![1, 0];
+        assert!(unordered.check(&tx).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::hint::HintSource;
 use crate::treepp::*;
 use bitcoin::opcodes::all::OP_PUSHBYTES_4;
 use std::cmp::min;
@@ -12,6 +13,19 @@ pub fn push_u32(v: u32) -> Script {
     ])
 }
 
+/// Pull a hint from the bottom of the stack, skipping the `offset` elements below it.
+pub fn pull_hint_from_bottom(offset: usize) -> Script {
+    script! {
+        OP_DEPTH
+        if offset == 0 {
+            OP_1SUB
+        } else {
+            { offset + 1 } OP_SUB
+        }
+        OP_ROLL
+    }
+}
+
 /// Convert a limb (u31 at most) to bits.
 /// Adapted from https://github.com/BitVM/BitVM/blob/main/src/bigint/bits.rs
 fn limb_to_be_bits_common(num_bits: u32) -> Script {
@@ -67,3 +81,205 @@ pub fn limb_to_be_bits_toaltstack(num_bits: u32) -> Script {
         }
     }
 }
+
+/// Convert a byte (as a number between 0 and 255) into its one-byte string.
+///
+/// Numbers 0 and 128 have no one-byte minimal encoding, and numbers above 128 are encoded with
+/// an extra byte, so they are handled separately. For the latter, we use the fact that the number
+/// -(v - 128) is encoded as the single byte v.
+pub fn byte_to_byte_string() -> Script {
+    script! {
+        OP_DUP 128 OP_GREATERTHANOREQUAL OP_IF
+            128 OP_SWAP OP_SUB
+            OP_DUP OP_0NOTEQUAL OP_NOTIF
+                // push the byte 0x80
+                OP_DROP OP_PUSHBYTES_1 OP_LEFT
+            OP_ENDIF
+        OP_ELSE
+            OP_DUP OP_0NOTEQUAL OP_NOTIF
+                OP_DROP OP_PUSHBYTES_1 OP_PUSHBYTES_0
+            OP_ENDIF
+        OP_ENDIF
+    }
+}
+
+/// Convert a number between 0 and 32767 into its varint, as in the counters and the lengths of
+/// a transaction.
+///
+/// Numbers from 253 on are encoded as 0xfd followed by two little-endian bytes, which are the
+/// bytes of the number itself on the stack, as a minimally encoded number below 2^15 has at most
+/// two bytes and a clear sign bit. It must therefore come from script arithmetic or `OP_SIZE`.
+pub fn varint_from_number() -> Script {
+    script! {
+        OP_DUP 0 32768 OP_WITHIN OP_VERIFY
+        OP_DUP 253 OP_LESSTHAN OP_IF
+            { byte_to_byte_string() }
+        OP_ELSE
+            { vec![0xfdu8] } OP_SWAP OP_CAT
+        OP_ENDIF
+    }
+}
+
+/// Push the hint for decomposing a little-endian field into u16 limbs, which is the bytes of the
+/// field, least significant first.
+pub fn push_le_bytes_to_u16_limbs_hint(bytes: &[u8]) -> Script {
+    assert_eq!(bytes.len() % 2, 0);
+    script! {
+        for byte in bytes.iter() {
+            { *byte }
+        }
+    }
+}
+
+/// Decompose a little-endian field of `2 * num_limbs` bytes into u16 limbs that can be used in
+/// script arithmetic.
+///
+/// hint:
+///   [each byte of the field, least significant first, as a number]
+///
+/// input:
+///   field
+///
+/// output:
+///   [each limb, least significant first]
+///
+pub fn le_bytes_to_u16_limbs(num_limbs: usize, hint: &HintSource) -> Script {
+    script! {
+        // the bytes recomposed so far
+        OP_PUSHBYTES_0
+
+        for i in 0..num_limbs {
+            // pull the lower byte and the higher byte of the limb
+            for j in 0..2 {
+                { hint.pull(i + j + 2, 0) }
+                OP_DUP 0 256 OP_WITHIN OP_VERIFY
+            }
+
+            // stack: field, limbs, recomposed, lower byte, higher byte
+            OP_2DUP
+            { byte_to_byte_string() }
+            OP_SWAP
+            { byte_to_byte_string() }
+            OP_SWAP OP_CAT
+
+            3 OP_ROLL OP_SWAP OP_CAT OP_TOALTSTACK
+
+            // stack: field, limbs, lower byte, higher byte
+            // altstack: recomposed
+            for _ in 0..8 {
+                OP_DUP OP_ADD
+            }
+            OP_ADD
+
+            OP_FROMALTSTACK
+        }
+
+        // stack: field, limbs, recomposed
+        { num_limbs + 1 } OP_ROLL OP_EQUALVERIFY
+    }
+}
+
+/// Push the hint for [`u32_to_u16_limbs`].
+pub fn push_u32_to_u16_limbs_hint(v: u32) -> Script {
+    push_le_bytes_to_u16_limbs_hint(&v.to_le_bytes())
+}
+
+/// Decompose a 4-byte little-endian field (such as time, version, or nonce) into two u16 limbs.
+///
+/// This avoids the sign-bit problem of treating the field as a script number, which would be
+/// negative (or, for 0x80000000, invalid) for values of 2^31 or above.
+///
+/// hint:
+///   [the four bytes of the field, least significant first]
+///
+/// input:
+///   field
+///
+/// output:
+///   lower limb
+///   higher limb
+///
+pub fn u32_to_u16_limbs(hint: &HintSource) -> Script {
+    le_bytes_to_u16_limbs(2, hint)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hint::HintSource;
+    use crate::treepp::*;
+    use crate::utils::{
+        byte_to_byte_string, push_u32, push_u32_to_u16_limbs_hint, u32_to_u16_limbs,
+        varint_from_number,
+    };
+    use bitcoin::consensus::Encodable;
+    use bitcoin::VarInt;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_byte_to_byte_string() {
+        for v in 0..=255u8 {
+            let script = script! {
+                { v }
+                { byte_to_byte_string() }
+                { vec![v] }
+                OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_varint_from_number() {
+        for v in [0usize, 1, 127, 128, 252, 253, 255, 256, 520, 0x7fff]
+            .into_iter()
+            .chain(200..300)
+        {
+            let mut expected = vec![];
+            VarInt(v as u64).consensus_encode(&mut expected).unwrap();
+
+            let script = script! {
+                { v }
+                { varint_from_number() }
+                { expected }
+                OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        let script = script! {
+            32768
+            { varint_from_number() }
+            OP_DROP OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_u32_to_u16_limbs() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let mut values = vec![0u32, 0x7fffffff, 0x80000000, 0xffffffff, 0x3d8c9b1b];
+        for _ in 0..20 {
+            values.push(prng.gen());
+        }
+
+        for v in values.iter() {
+            let script = script! {
+                { push_u32_to_u16_limbs_hint(*v) }
+                { push_u32(*v) }
+                { u32_to_u16_limbs(&HintSource::default()) }
+                { (*v >> 16) as usize } OP_EQUALVERIFY
+                { (*v & 0xffff) as usize } OP_EQUAL
+            };
+
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+}